@@ -0,0 +1,27 @@
+// Copyright 2018-2024 the Deno authors. All rights reserved. MIT license.
+
+#![no_main]
+
+use std::path::PathBuf;
+
+use deno_lockfile::Lockfile;
+use libfuzzer_sys::fuzz_target;
+
+// Run with `cargo +nightly fuzz run with_lockfile_content`, using the
+// lockfiles in `tests/specs` as the initial corpus.
+fuzz_target!(|text: &str| {
+  let Ok(mut lockfile) =
+    Lockfile::with_lockfile_content(PathBuf::from("deno.lock"), text, false)
+  else {
+    return;
+  };
+  lockfile.as_json_string();
+  lockfile.graph_json();
+  lockfile.to_dot(Default::default());
+  lockfile.to_mermaid(Default::default());
+  let _ = lockfile.topological_order();
+  lockfile.content.unreachable_packages();
+  lockfile.canonicalize();
+  lockfile.prune(Default::default());
+  let _ = lockfile.normalize_specifiers();
+});
@@ -0,0 +1,174 @@
+// Copyright 2018-2024 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use serde::de::DeserializeSeed;
+use serde::de::MapAccess;
+use serde::de::SeqAccess;
+use serde::de::Visitor;
+use serde::Deserializer;
+
+/// A key that appears more than once in the same object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateKey {
+  /// Keys and array indexes leading to the object with the duplicate
+  /// key, which is empty for the top level object.
+  pub path: Vec<String>,
+  pub key: String,
+}
+
+/// Finds the first object key that appears more than once in the same
+/// object of the provided JSON text.
+///
+/// `serde_json` keeps the last value for duplicate keys, which hides
+/// problems like a bad merge of the lockfile.
+pub fn find_duplicate_key(
+  text: &str,
+) -> Result<Option<DuplicateKey>, serde_json::Error> {
+  let mut deserializer = serde_json::Deserializer::from_str(text);
+  let duplicate = DuplicateKeySeed {
+    path: &mut Vec::new(),
+  }
+  .deserialize(&mut deserializer)?;
+  deserializer.end()?;
+  Ok(duplicate)
+}
+
+struct DuplicateKeySeed<'a> {
+  path: &'a mut Vec<String>,
+}
+
+impl<'de> DeserializeSeed<'de> for DuplicateKeySeed<'_> {
+  type Value = Option<DuplicateKey>;
+
+  fn deserialize<D: Deserializer<'de>>(
+    self,
+    deserializer: D,
+  ) -> Result<Self::Value, D::Error> {
+    deserializer.deserialize_any(self)
+  }
+}
+
+impl<'de> Visitor<'de> for DuplicateKeySeed<'_> {
+  type Value = Option<DuplicateKey>;
+
+  fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    formatter.write_str("any JSON value")
+  }
+
+  fn visit_bool<E>(self, _: bool) -> Result<Self::Value, E> {
+    Ok(None)
+  }
+
+  fn visit_i64<E>(self, _: i64) -> Result<Self::Value, E> {
+    Ok(None)
+  }
+
+  fn visit_u64<E>(self, _: u64) -> Result<Self::Value, E> {
+    Ok(None)
+  }
+
+  fn visit_f64<E>(self, _: f64) -> Result<Self::Value, E> {
+    Ok(None)
+  }
+
+  fn visit_str<E>(self, _: &str) -> Result<Self::Value, E> {
+    Ok(None)
+  }
+
+  fn visit_unit<E>(self) -> Result<Self::Value, E> {
+    Ok(None)
+  }
+
+  fn visit_seq<A: SeqAccess<'de>>(
+    self,
+    mut seq: A,
+  ) -> Result<Self::Value, A::Error> {
+    let mut found = None;
+    let mut index = 0;
+    loop {
+      self.path.push(index.to_string());
+      let element = seq.next_element_seed(DuplicateKeySeed {
+        path: &mut *self.path,
+      });
+      self.path.pop();
+      let Some(duplicate) = element? else {
+        break;
+      };
+      found = found.or(duplicate);
+      index += 1;
+    }
+    Ok(found)
+  }
+
+  fn visit_map<A: MapAccess<'de>>(
+    self,
+    mut map: A,
+  ) -> Result<Self::Value, A::Error> {
+    let mut keys = HashSet::new();
+    let mut found = None;
+    while let Some(key) = map.next_key::<String>()? {
+      self.path.push(key.clone());
+      let value = map.next_value_seed(DuplicateKeySeed {
+        path: &mut *self.path,
+      });
+      self.path.pop();
+      let duplicate = value?;
+      if found.is_none() {
+        if keys.contains(&key) {
+          found = Some(DuplicateKey {
+            path: self.path.clone(),
+            key,
+          });
+        } else {
+          found = duplicate;
+          keys.insert(key);
+        }
+      }
+    }
+    Ok(found)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn duplicate(path: &[&str], key: &str) -> Option<DuplicateKey> {
+    Some(DuplicateKey {
+      path: path.iter().map(|p| p.to_string()).collect(),
+      key: key.to_string(),
+    })
+  }
+
+  #[test]
+  fn finds_duplicate_keys() {
+    assert_eq!(
+      find_duplicate_key(r#"{ "version": "3", "remote": {} }"#).unwrap(),
+      None
+    );
+    assert_eq!(
+      find_duplicate_key(r#"{ "version": "3", "version": "2" }"#).unwrap(),
+      duplicate(&[], "version")
+    );
+    assert_eq!(
+      find_duplicate_key(
+        r#"{ "remote": { "https://a/mod.ts": "1", "https://a/mod.ts": "2" } }"#
+      )
+      .unwrap(),
+      duplicate(&["remote"], "https://a/mod.ts")
+    );
+    // same key in different objects
+    assert_eq!(
+      find_duplicate_key(r#"[{ "a": 1 }, { "a": [null, true, 1.5] }]"#)
+        .unwrap(),
+      None
+    );
+    assert_eq!(
+      find_duplicate_key(r#"[{ "a": 1 }, { "b": [{ "c": 1, "c": 2 }] }]"#)
+        .unwrap(),
+      duplicate(&["1", "b", "0"], "c")
+    );
+  }
+}
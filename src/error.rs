@@ -2,6 +2,8 @@
 
 use thiserror::Error;
 
+use crate::LockfileProblem;
+
 #[derive(Debug, Error)]
 pub enum LockfileError {
   #[error("Unable to read lockfile. {0}")]
@@ -10,6 +12,151 @@ pub enum LockfileError {
   #[error("Unable to parse contents of lockfile. {0}: {1:#}")]
   ParseError(String, serde_json::Error),
 
+  #[error("Unable to read lockfile {0}. {1}")]
+  Io(String, std::io::Error),
+
+  #[error("Duplicate key '{key}'{} in lockfile {filename}. The lockfile may have been incorrectly merged.", display_key_path(.path))]
+  DuplicateKey {
+    filename: String,
+    /// Keys and array indexes leading to the object with the duplicate
+    /// key (ex. `["packages", "npm"]`), which is empty for the top level.
+    path: Vec<String>,
+    key: String,
+  },
+
+  #[error("Invalid package specifier '{0}'. {1}")]
+  InvalidPackageSpecifier(String, String),
+
+  #[error("Unable to convert npm package-lock.json. {0}")]
+  InvalidNpmPackageLock(String),
+
+  #[error(transparent)]
+  Transform(#[from] TransformError),
+
   #[error("Unsupported lockfile version '{0}'. Try upgrading Deno or recreating the lockfile.")]
   UnsupportedVersion(String),
+
+  #[error("Unable to write a version {0} lockfile. {1}")]
+  UnsupportedWriteVersion(String, String),
+
+  #[error(transparent)]
+  Frozen(#[from] FrozenError),
+
+  #[error(transparent)]
+  MissingPackage(#[from] MissingPackageError),
+}
+
+fn display_key_path(path: &[String]) -> String {
+  if path.is_empty() {
+    String::new()
+  } else {
+    format!(" at '{}'", path.join(" > "))
+  }
+}
+
+/// Error for when packages unexpectedly depend on each other.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Dependency cycle found between packages: {}", .component.join(", "))]
+pub struct CycleError {
+  /// Sorted ids of the packages in the strongly connected component.
+  pub component: Vec<String>,
+}
+
+/// Error for when a resolution graph references packages it doesn't have.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Unable to create the lockfile from the resolution. {}", display_problems(.problems))]
+pub struct ResolutionError {
+  /// Every dangling reference found in the resolution graph.
+  pub problems: Vec<LockfileProblem>,
+}
+
+fn display_problems(problems: &[LockfileProblem]) -> String {
+  problems
+    .iter()
+    .map(|problem| problem.to_string())
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// Error for when a package isn't in the lockfile.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Package '{name}' was not found in the lockfile.")]
+pub struct MissingPackageError {
+  /// Name and version (ex. `@std/path@0.75.0`) or
+  /// id (ex. `npm:chalk@5.0.0`) of the package.
+  pub name: String,
+}
+
+/// Error for when a package specifier can't be renamed.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum RenameError {
+  #[error("Package specifier '{0}' was not found in the lockfile.")]
+  NotFound(String),
+
+  #[error(
+    "Package specifier '{0}' already resolves to '{1}' instead of '{2}'."
+  )]
+  Conflict(String, String, String),
+}
+
+/// Error for when a lockfile can't be transformed between versions.
+/// See [`crate::upgrade_to_latest`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Unable to transform lockfile from version {from_version} to {to_version}. {message}")]
+pub struct TransformError {
+  /// Version of the lockfile (ex. `4`).
+  pub from_version: String,
+  /// Version the lockfile was transformed to (ex. `3`).
+  pub to_version: String,
+  pub message: String,
+}
+
+/// Error for when a package specifier can't be pinned or unpinned.
+/// See [`crate::Lockfile::pin_specifier`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PinError {
+  #[error("Package specifier '{0}' was not found in the lockfile.")]
+  NotFound(String),
+
+  #[error("Package specifier '{0}' resolves to invalid package id '{1}'.")]
+  InvalidId(String, String),
+
+  #[error("Package specifier '{0}' isn't pinned to '{1}'.")]
+  NotPinned(String, String),
+
+  #[error(
+    "Package specifier '{0}' already resolves to '{1}' instead of '{2}'."
+  )]
+  Conflict(String, String, String),
+}
+
+impl From<RenameError> for PinError {
+  fn from(err: RenameError) -> Self {
+    match err {
+      RenameError::NotFound(req) => PinError::NotFound(req),
+      RenameError::Conflict(req, existing, id) => {
+        PinError::Conflict(req, existing, id)
+      }
+    }
+  }
+}
+
+/// Error for when a workspace dependency can't be moved.
+/// See [`crate::Lockfile::move_workspace_dependency`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Dependency '{dep}' was not found in {member}.")]
+pub struct MoveError {
+  /// The dependency that was moved (ex. `jsr:@std/path@^0.75`).
+  pub dep: String,
+  /// Description of the source (ex. `workspace member 'a'`).
+  pub member: String,
+}
+
+/// Error for when a frozen lockfile would be changed.
+/// See [`crate::Lockfile::freeze`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("The lockfile is frozen, but '{entry}' would change it. The lockfile is out of date.")]
+pub struct FrozenError {
+  /// Key of the entry that would change (ex. `npm:chalk@5`).
+  pub entry: String,
 }
@@ -6,6 +6,8 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::VecDeque;
 
+use crate::CycleError;
+use crate::LockfileContent;
 use crate::NpmPackageInfo;
 use crate::PackagesContent;
 
@@ -15,6 +17,27 @@ enum LockfilePkgId {
   Jsr(LockfileJsrPkgNv),
 }
 
+impl LockfilePkgId {
+  /// Gets the id as found in the values of the specifiers
+  /// (ex. `npm:chalk@5.0.0`).
+  fn serialized(&self) -> String {
+    match self {
+      LockfilePkgId::Npm(id) => format!("npm:{}", id.0),
+      LockfilePkgId::Jsr(nv) => format!("jsr:{}", nv.0),
+    }
+  }
+
+  /// Parses an id as found in the values of the specifiers.
+  fn from_serialized(id: &str) -> Option<Self> {
+    if let Some(id) = id.strip_prefix("npm:") {
+      Some(LockfilePkgId::Npm(LockfileNpmPackageId(id.to_string())))
+    } else {
+      id.strip_prefix("jsr:")
+        .map(|nv| LockfilePkgId::Jsr(LockfileJsrPkgNv(nv.to_string())))
+    }
+  }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 struct LockfileJsrPkgNv(String);
 
@@ -44,6 +67,8 @@ struct LockfileNpmGraphPackage {
   root_ids: HashSet<LockfilePkgId>,
   integrity: String,
   dependencies: BTreeMap<String, LockfileNpmPackageId>,
+  optional_dependencies: BTreeMap<String, String>,
+  peer_dependencies: BTreeMap<String, String>,
 }
 
 #[derive(Debug)]
@@ -56,6 +81,11 @@ struct LockfileJsrGraphPackage {
 
 /// Graph used to analyze a lockfile to determine which packages
 /// and remotes can be removed based on config file changes.
+///
+/// Create it with [`LockfilePackageGraph::from_lockfile`] or
+/// [`LockfilePackageGraph::from_content`], remove the root package
+/// requirements that are no longer used, then write the result back
+/// with [`LockfilePackageGraph::populate_packages`].
 pub struct LockfilePackageGraph {
   root_packages: HashMap<LockfilePkgReq, LockfilePkgId>,
   packages: HashMap<LockfilePkgId, LockfileGraphPackage>,
@@ -63,6 +93,11 @@ pub struct LockfilePackageGraph {
 }
 
 impl LockfilePackageGraph {
+  /// Creates the graph from the packages and remotes of a lockfile.
+  ///
+  /// The `old_config_file_packages` are the package requirements the
+  /// workspace depended on when the lockfile was created. Package
+  /// specifiers not transitively referenced by these are kept as roots.
   pub fn from_lockfile<'a>(
     content: PackagesContent,
     remotes: BTreeMap<String, String>,
@@ -116,6 +151,8 @@ impl LockfilePackageGraph {
               (key.clone(), LockfileNpmPackageId(dep_id.clone()))
             })
             .collect(),
+          optional_dependencies: package.optional_dependencies.clone(),
+          peer_dependencies: package.peer_dependencies.clone(),
         }),
       );
     }
@@ -172,10 +209,280 @@ impl LockfilePackageGraph {
     }
   }
 
+  /// Creates the graph without consuming the lockfile content, using
+  /// the workspace dependencies of the content as the roots.
+  pub fn from_content(content: &LockfileContent) -> Self {
+    Self::from_lockfile(
+      content.packages.clone(),
+      content.remote.clone(),
+      content.workspace.get_all_dep_reqs().map(|req| req.as_str()),
+    )
+  }
+
+  /// Gets the ids (ex. `npm:chalk@5.0.0`) of the packages transitively
+  /// referenced by the provided root package requirements, which is the
+  /// transitive closure of the roots including any dependency cycles.
+  pub fn reachable_from<'a>(
+    &self,
+    package_reqs: impl Iterator<Item = &'a str>,
+  ) -> BTreeSet<String> {
+    self
+      .reachable_ids(package_reqs.filter_map(|req| {
+        self
+          .root_packages
+          .get(&LockfilePkgReq(req.to_string()))
+          .cloned()
+      }))
+      .iter()
+      .map(|id| id.serialized())
+      .collect()
+  }
+
+  /// Gets every package id (ex. `npm:chalk@5.0.0`) reachable from the
+  /// provided root package requirements without changing the graph.
+  ///
+  /// Same as [`LockfilePackageGraph::reachable_from`].
+  pub fn transitive_closure<'a>(
+    &self,
+    roots: impl Iterator<Item = &'a str>,
+  ) -> BTreeSet<String> {
+    self.reachable_from(roots)
+  }
+
+  /// Gets the packages in the order they should be installed, which is
+  /// dependencies first.
+  ///
+  /// Only packages reachable from the package specifiers are included and
+  /// the order is deterministic. Errors with the first strongly connected
+  /// component found when the packages have a dependency cycle.
+  pub fn topological_order(&self) -> Result<Vec<String>, CycleError> {
+    let components = self.reachable_strongly_connected_components();
+    let mut order = Vec::with_capacity(components.len());
+    for component in components {
+      if component.len() > 1 || self.depends_on_itself(&component[0]) {
+        return Err(CycleError {
+          component: component.iter().map(|id| id.serialized()).collect(),
+        });
+      }
+      order.push(component[0].serialized());
+    }
+    Ok(order)
+  }
+
+  /// Groups the packages into levels that can be installed in order,
+  /// where each level only depends on packages in previous levels.
+  ///
+  /// Packages in a dependency cycle are always placed in the same level.
+  pub fn topological_levels_allowing_cycles(&self) -> Vec<Vec<String>> {
+    let components = self.reachable_strongly_connected_components();
+    let mut component_indexes = HashMap::with_capacity(components.len());
+    for (index, component) in components.iter().enumerate() {
+      for id in component {
+        component_indexes.insert(id, index);
+      }
+    }
+
+    // components are in dependency first order so the levels of
+    // the dependencies are always known before the dependents
+    let mut levels: Vec<Vec<String>> = Vec::new();
+    let mut level_of_component = vec![0; components.len()];
+    for (index, component) in components.iter().enumerate() {
+      let level = component
+        .iter()
+        .flat_map(|id| self.dependency_ids(id))
+        .filter_map(|dep_id| component_indexes.get(&dep_id).copied())
+        .filter(|dep_index| *dep_index != index)
+        .map(|dep_index| level_of_component[dep_index] + 1)
+        .max()
+        .unwrap_or(0);
+      level_of_component[index] = level;
+      if levels.len() <= level {
+        levels.resize_with(level + 1, Vec::new);
+      }
+      levels[level].extend(component.iter().map(|id| id.serialized()));
+    }
+    for level in &mut levels {
+      level.sort();
+    }
+    levels
+  }
+
+  /// Gets the dependency cycles found in the graph, which are the packages
+  /// that are strongly connected or depend on themselves.
+  ///
+  /// Each cycle is sorted and the cycles are sorted by their first package.
+  pub fn find_cycles(&self) -> Vec<Vec<String>> {
+    let mut cycles = self
+      .strongly_connected_components(self.packages.keys().cloned().collect())
+      .into_iter()
+      .filter(|component| {
+        component.len() > 1 || self.depends_on_itself(&component[0])
+      })
+      .map(|component| {
+        component
+          .iter()
+          .map(|id| id.serialized())
+          .collect::<Vec<_>>()
+      })
+      .collect::<Vec<_>>();
+    cycles.sort();
+    cycles
+  }
+
+  /// Gets the strongly connected components of the packages reachable from
+  /// the package specifiers.
+  fn reachable_strongly_connected_components(&self) -> Vec<Vec<LockfilePkgId>> {
+    self.strongly_connected_components(
+      self.reachable_ids(self.root_packages.values().cloned()),
+    )
+  }
+
+  /// Gets the strongly connected components of the provided packages
+  /// using Tarjan's algorithm.
+  ///
+  /// The components are in dependency first order and their members are sorted.
+  fn strongly_connected_components(
+    &self,
+    ids: BTreeSet<LockfilePkgId>,
+  ) -> Vec<Vec<LockfilePkgId>> {
+    const UNVISITED: usize = usize::MAX;
+
+    let nodes = ids.into_iter().collect::<Vec<_>>();
+    let node_indexes = nodes
+      .iter()
+      .enumerate()
+      .map(|(index, id)| (id, index))
+      .collect::<HashMap<_, _>>();
+    let edges = nodes
+      .iter()
+      .map(|id| {
+        self
+          .dependency_ids(id)
+          .into_iter()
+          .filter_map(|dep_id| node_indexes.get(&dep_id).copied())
+          .collect::<Vec<_>>()
+      })
+      .collect::<Vec<_>>();
+
+    let mut indexes = vec![UNVISITED; nodes.len()];
+    let mut low_links = vec![0; nodes.len()];
+    let mut on_stack = vec![false; nodes.len()];
+    let mut stack = Vec::new();
+    let mut next_index = 0;
+    let mut components = Vec::new();
+    for start in 0..nodes.len() {
+      if indexes[start] != UNVISITED {
+        continue;
+      }
+      // iterative to not overflow the call stack on deep graphs
+      let mut call_stack = vec![(start, 0)];
+      indexes[start] = next_index;
+      low_links[start] = next_index;
+      next_index += 1;
+      stack.push(start);
+      on_stack[start] = true;
+      while let Some((node, edge_index)) = call_stack.last_mut() {
+        let node = *node;
+        if let Some(&next) = edges[node].get(*edge_index) {
+          *edge_index += 1;
+          if indexes[next] == UNVISITED {
+            indexes[next] = next_index;
+            low_links[next] = next_index;
+            next_index += 1;
+            stack.push(next);
+            on_stack[next] = true;
+            call_stack.push((next, 0));
+          } else if on_stack[next] {
+            low_links[node] = low_links[node].min(indexes[next]);
+          }
+          continue;
+        }
+
+        call_stack.pop();
+        if let Some(&(parent, _)) = call_stack.last() {
+          low_links[parent] = low_links[parent].min(low_links[node]);
+        }
+        if low_links[node] == indexes[node] {
+          let mut component = Vec::new();
+          while let Some(member) = stack.pop() {
+            on_stack[member] = false;
+            component.push(nodes[member].clone());
+            if member == node {
+              break;
+            }
+          }
+          component.sort();
+          components.push(component);
+        }
+      }
+    }
+    components
+  }
+
+  fn depends_on_itself(&self, id: &LockfilePkgId) -> bool {
+    self.dependency_ids(id).contains(id)
+  }
+
+  /// Gets the sorted ids of the packages in the graph the provided
+  /// package directly depends on.
+  fn dependency_ids(&self, id: &LockfilePkgId) -> Vec<LockfilePkgId> {
+    let mut ids = match self.packages.get(id) {
+      Some(LockfileGraphPackage::Jsr(package)) => package
+        .dependencies
+        .iter()
+        .filter_map(|req| self.root_packages.get(req))
+        .cloned()
+        .collect::<Vec<_>>(),
+      Some(LockfileGraphPackage::Npm(package)) => package
+        .dependencies
+        .values()
+        .map(|dep_id| LockfilePkgId::Npm(dep_id.clone()))
+        .collect(),
+      None => Vec::new(),
+    };
+    ids.retain(|id| self.packages.contains_key(id));
+    ids.sort();
+    ids.dedup();
+    ids
+  }
+
+  /// Gets the ids of the packages in the graph transitively
+  /// referenced by the provided ids.
+  fn reachable_ids(
+    &self,
+    ids: impl Iterator<Item = LockfilePkgId>,
+  ) -> BTreeSet<LockfilePkgId> {
+    let mut seen = BTreeSet::new();
+    let mut pending = ids.collect::<Vec<_>>();
+    while let Some(id) = pending.pop() {
+      if !self.packages.contains_key(&id) || seen.contains(&id) {
+        continue;
+      }
+      pending.extend(self.dependency_ids(&id));
+      seen.insert(id);
+    }
+    seen
+  }
+
+  /// Removes the provided root package requirements from the graph along
+  /// with any packages that are no longer referenced because of it.
   pub fn remove_root_packages(
     &mut self,
     package_reqs: impl Iterator<Item = String>,
   ) {
+    self.remove_root_packages_keeping(package_reqs, std::iter::empty());
+  }
+
+  /// Same as [`LockfilePackageGraph::remove_root_packages`], but never
+  /// removes the packages with the provided ids (ex. `npm:chalk@5.0.0`)
+  /// or anything they transitively depend on.
+  pub(crate) fn remove_root_packages_keeping<'a>(
+    &mut self,
+    package_reqs: impl Iterator<Item = String>,
+    kept_ids: impl Iterator<Item = &'a str>,
+  ) {
+    let kept_ids =
+      self.reachable_ids(kept_ids.filter_map(LockfilePkgId::from_serialized));
     let mut root_ids = Vec::new();
 
     // collect the root ids being removed
@@ -223,7 +530,7 @@ impl LockfilePackageGraph {
                     pending.push_back(id.clone());
                   }
                 }
-                if package.root_ids.is_empty() {
+                if package.root_ids.is_empty() && !kept_ids.contains(&id) {
                   self.remove_package(id);
                 }
               }
@@ -233,7 +540,7 @@ impl LockfilePackageGraph {
                 for dep_id in package.dependencies.values() {
                   pending.push_back(LockfilePkgId::Npm(dep_id.clone()));
                 }
-                if package.root_ids.is_empty() {
+                if package.root_ids.is_empty() && !kept_ids.contains(&id) {
                   self.remove_package(id);
                 }
               }
@@ -249,6 +556,7 @@ impl LockfilePackageGraph {
     self.root_packages.retain(|_, pkg_id| *pkg_id != id);
   }
 
+  /// Writes the packages and remotes of the graph back into the lockfile.
   pub fn populate_packages(
     self,
     packages: &mut PackagesContent,
@@ -256,13 +564,7 @@ impl LockfilePackageGraph {
   ) {
     *remotes = self.remotes;
     for (req, id) in self.root_packages {
-      packages.specifiers.insert(
-        req.0,
-        match id {
-          LockfilePkgId::Npm(id) => format!("npm:{}", id.0),
-          LockfilePkgId::Jsr(nv) => format!("jsr:{}", nv.0),
-        },
-      );
+      packages.specifiers.insert(req.0, id.serialized());
     }
 
     for (id, package) in self.packages {
@@ -296,6 +598,8 @@ impl LockfilePackageGraph {
                 .into_iter()
                 .map(|(name, id)| (name, id.0))
                 .collect(),
+              optional_dependencies: package.optional_dependencies,
+              peer_dependencies: package.peer_dependencies,
             },
           );
         }
@@ -303,3 +607,797 @@ impl LockfilePackageGraph {
     }
   }
 }
+
+/// How npm and jsr packages are visually distinguished in DOT output.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DotKindStyle {
+  /// npm packages are drawn as boxes and jsr packages as ellipses.
+  #[default]
+  Shape,
+  /// npm packages are drawn in red and jsr packages in yellow.
+  Color,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct DotOptions {
+  pub kind_style: DotKindStyle,
+  /// Only output the subtree of this package requirement
+  /// (ex. `jsr:@std/path@^0.75`).
+  pub root: Option<String>,
+  /// Maximum number of edges to follow from the starting nodes.
+  ///
+  /// Packages not reachable from a workspace root are only
+  /// included when this is `None` and no `root` is provided.
+  pub max_depth: Option<usize>,
+}
+
+/// Gets the ids of the packages each package directly depends on with
+/// the specifier indirection of jsr packages resolved.
+///
+/// Ids are prefixed with their kind (ex. `npm:chalk@5.0.0`).
+fn resolved_package_deps(
+  packages: &PackagesContent,
+) -> BTreeMap<String, BTreeSet<String>> {
+  let mut result = BTreeMap::new();
+  for (nv, package) in &packages.jsr {
+    result.insert(
+      format!("jsr:{}", nv),
+      package
+        .dependencies
+        .iter()
+        .filter_map(|req| packages.specifiers.get(req).cloned())
+        .collect(),
+    );
+  }
+  for (id, package) in &packages.npm {
+    result.insert(
+      format!("npm:{}", id),
+      package
+        .dependencies
+        .values()
+        .map(|dep_id| format!("npm:{}", dep_id))
+        .collect(),
+    );
+  }
+  result
+}
+
+/// Graph of the packages and workspace members used for
+/// outputting diagrams.
+struct DiagramGraph {
+  /// Dependencies of each node, where workspace members are named
+  /// `workspace` for the root and `workspace:<member>` for members.
+  edges: BTreeMap<String, BTreeSet<String>>,
+  workspace_nodes: BTreeSet<String>,
+}
+
+impl DiagramGraph {
+  fn new(content: &LockfileContent) -> Self {
+    let packages = &content.packages;
+    let mut edges = resolved_package_deps(packages);
+    let mut workspace_nodes = BTreeSet::new();
+    let workspace_members =
+      std::iter::once((workspace_member_node(None), &content.workspace.root))
+        .chain(
+          content
+            .workspace
+            .members
+            .iter()
+            .map(|(name, member)| (workspace_member_node(Some(name)), member)),
+        );
+    for (node, member) in workspace_members {
+      if member.is_empty() {
+        continue;
+      }
+      edges.insert(
+        node.clone(),
+        member
+          .dep_reqs()
+          .filter_map(|req| packages.specifiers.get(req).cloned())
+          .collect(),
+      );
+      workspace_nodes.insert(node);
+    }
+    Self {
+      edges,
+      workspace_nodes,
+    }
+  }
+
+  /// Gets the nodes reachable from the start nodes within the max depth.
+  fn reachable_nodes(
+    &self,
+    start_nodes: BTreeSet<String>,
+    max_depth: Option<usize>,
+  ) -> BTreeSet<String> {
+    let mut included = BTreeSet::new();
+    let mut pending = start_nodes
+      .into_iter()
+      .map(|id| (id, 0))
+      .collect::<VecDeque<_>>();
+    while let Some((id, depth)) = pending.pop_front() {
+      if !included.insert(id.clone()) {
+        continue;
+      }
+      if max_depth.map(|max| depth >= max).unwrap_or(false) {
+        continue;
+      }
+      if let Some(deps) = self.edges.get(&id) {
+        for dep in deps {
+          pending.push_back((dep.clone(), depth + 1));
+        }
+      }
+    }
+    included
+  }
+
+  /// Gets the edges between the provided nodes.
+  fn edges_between<'a>(
+    &'a self,
+    nodes: &'a BTreeSet<String>,
+  ) -> impl Iterator<Item = (&'a String, &'a String)> {
+    nodes.iter().flat_map(move |id| {
+      self
+        .edges
+        .get(id)
+        .into_iter()
+        .flatten()
+        .filter(|dep| nodes.contains(*dep))
+        .map(move |dep| (id, dep))
+    })
+  }
+}
+
+fn workspace_member_node(member: Option<&str>) -> String {
+  match member {
+    Some(name) => format!("workspace:{}", name),
+    None => "workspace".to_string(),
+  }
+}
+
+/// Quotes a node name as a DOT id, escaping only the quotes and
+/// backslashes so other characters are kept as is.
+fn dot_node_id(name: &str) -> String {
+  let mut id = String::with_capacity(name.len() + 2);
+  id.push('"');
+  for c in name.chars() {
+    if c == '"' || c == '\\' {
+      id.push('\\');
+    }
+    id.push(c);
+  }
+  id.push('"');
+  id
+}
+
+pub fn to_dot(content: &LockfileContent, options: &DotOptions) -> String {
+  fn node_attrs(id: &str, kind_style: DotKindStyle) -> &'static str {
+    let is_npm = id.starts_with("npm:");
+    match (kind_style, is_npm) {
+      (DotKindStyle::Shape, true) => "shape=box",
+      (DotKindStyle::Shape, false) => "shape=ellipse",
+      (DotKindStyle::Color, true) => "color=\"#cb3837\"",
+      (DotKindStyle::Color, false) => "color=\"#f7df1e\"",
+    }
+  }
+
+  let graph = DiagramGraph::new(content);
+  let included = match &options.root {
+    Some(req) => graph.reachable_nodes(
+      content
+        .packages
+        .specifiers
+        .get(req)
+        .cloned()
+        .into_iter()
+        .collect(),
+      options.max_depth,
+    ),
+    None if options.max_depth.is_some() => {
+      graph.reachable_nodes(graph.workspace_nodes.clone(), options.max_depth)
+    }
+    None => graph.edges.keys().cloned().collect(),
+  };
+
+  let mut text = String::from("digraph lockfile {\n");
+  for id in &included {
+    let attrs = if graph.workspace_nodes.contains(id) {
+      "shape=doubleoctagon"
+    } else {
+      node_attrs(id, options.kind_style)
+    };
+    text.push_str(&format!("  {} [{}];\n", dot_node_id(id), attrs));
+  }
+  for (id, dep) in graph.edges_between(&included) {
+    text.push_str(&format!("  {} -> {};\n", dot_node_id(id), dot_node_id(dep)));
+  }
+  text.push_str("}\n");
+  text
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct MermaidOptions {
+  /// Only output the subtree of this workspace member, where
+  /// an empty string is the root of the workspace.
+  pub workspace_member: Option<String>,
+}
+
+/// Converts a node name to a valid Mermaid node id by escaping every
+/// character other than ASCII letters and digits with its code point
+/// (ex. `npm:a@1` becomes `npm_3a_a_40_1`).
+///
+/// Underscores are escaped as well so two distinct names can never
+/// have the same id.
+fn mermaid_node_id(name: &str) -> String {
+  let mut id = String::with_capacity(name.len());
+  for c in name.chars() {
+    if c.is_ascii_alphanumeric() {
+      id.push(c);
+    } else {
+      id.push_str(&format!("_{:x}_", c as u32));
+    }
+  }
+  id
+}
+
+pub fn to_mermaid(
+  content: &LockfileContent,
+  options: &MermaidOptions,
+) -> String {
+  let graph = DiagramGraph::new(content);
+  let included = match &options.workspace_member {
+    Some(member) => {
+      let node =
+        workspace_member_node((!member.is_empty()).then_some(member.as_str()));
+      let mut start_nodes = BTreeSet::new();
+      if graph.workspace_nodes.contains(&node) {
+        start_nodes.insert(node);
+      }
+      graph.reachable_nodes(start_nodes, None)
+    }
+    None => graph.edges.keys().cloned().collect(),
+  };
+
+  let mut text = String::from("graph TD\n");
+  for name in &included {
+    let id = mermaid_node_id(name);
+    let label = name.replace('"', "#quot;");
+    if graph.workspace_nodes.contains(name) {
+      text.push_str(&format!("  {}{{{{\"{}\"}}}}\n", id, label));
+    } else if name.starts_with("npm:") {
+      text.push_str(&format!("  {}[\"{}\"]\n", id, label));
+    } else {
+      text.push_str(&format!("  {}(\"{}\")\n", id, label));
+    }
+  }
+  for (name, dep) in graph.edges_between(&included) {
+    text.push_str(&format!(
+      "  {} --> {}\n",
+      mermaid_node_id(name),
+      mermaid_node_id(dep)
+    ));
+  }
+  text
+}
+
+/// Gets the ids of the packages the workspace depends on or, when the
+/// lockfile has no workspace config, the ids of every package specifier.
+///
+/// Patched packages are always roots.
+fn root_package_ids(content: &LockfileContent) -> BTreeSet<String> {
+  let specifiers = &content.packages.specifiers;
+  let mut ids: BTreeSet<String> = if content.workspace.is_empty() {
+    specifiers.values().cloned().collect()
+  } else {
+    content
+      .workspace
+      .get_all_dep_reqs()
+      .filter_map(|req| specifiers.get(req).cloned())
+      .collect()
+  };
+  ids.extend(content.patches.keys().cloned());
+  ids
+}
+
+/// Gets the ids of the packages that can't be reached from the roots
+/// of the lockfile.
+pub fn unreachable_package_ids(content: &LockfileContent) -> BTreeSet<String> {
+  let edges = resolved_package_deps(&content.packages);
+  let mut seen = HashSet::with_capacity(edges.len());
+  let mut pending = root_package_ids(content).into_iter().collect::<Vec<_>>();
+  while let Some(id) = pending.pop() {
+    if seen.insert(id.clone()) {
+      if let Some(deps) = edges.get(&id) {
+        pending.extend(deps.iter().cloned());
+      }
+    }
+  }
+  edges.into_keys().filter(|id| !seen.contains(id)).collect()
+}
+
+pub fn to_graph_json(content: &LockfileContent) -> serde_json::Value {
+  let packages = &content.packages;
+  let mut missing = BTreeSet::new();
+  let mut nodes = serde_json::Map::new();
+
+  for (nv, package) in &packages.jsr {
+    let mut deps = Vec::with_capacity(package.dependencies.len());
+    for req in &package.dependencies {
+      match packages.specifiers.get(req) {
+        Some(id) if package_exists(content, id) => deps.push(id.clone()),
+        Some(id) => {
+          missing.insert(id.clone());
+        }
+        None => {
+          missing.insert(req.clone());
+        }
+      }
+    }
+    nodes.insert(
+      format!("jsr:{}", nv),
+      serde_json::json!({
+        "kind": "jsr",
+        "integrity": package.integrity,
+        "deps": deps,
+      }),
+    );
+  }
+  for (id, package) in &packages.npm {
+    let mut deps = Vec::with_capacity(package.dependencies.len());
+    for dep_id in package.dependencies.values() {
+      let dep_id = format!("npm:{}", dep_id);
+      if package_exists(content, &dep_id) {
+        deps.push(dep_id);
+      } else {
+        missing.insert(dep_id);
+      }
+    }
+    nodes.insert(
+      format!("npm:{}", id),
+      serde_json::json!({
+        "kind": "npm",
+        "integrity": package.integrity,
+        "deps": deps,
+      }),
+    );
+  }
+
+  let mut roots = Vec::new();
+  for id in root_package_ids(content) {
+    if package_exists(content, &id) {
+      roots.push(id);
+    } else {
+      missing.insert(id);
+    }
+  }
+
+  serde_json::json!({
+    "roots": roots,
+    "nodes": nodes,
+    "missing": missing,
+  })
+}
+
+/// Gets if the package has an entry in the lockfile.
+pub(crate) fn package_exists(content: &LockfileContent, id: &str) -> bool {
+  let packages = &content.packages;
+  if let Some(id) = id.strip_prefix("npm:") {
+    packages.npm.contains_key(id)
+  } else if let Some(nv) = id.strip_prefix("jsr:") {
+    packages.jsr.contains_key(nv)
+  } else {
+    false
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use pretty_assertions::assert_eq;
+  use serde_json::json;
+
+  use super::*;
+
+  fn graph_test_content() -> LockfileContent {
+    serde_json::from_value(json!({
+      "version": "3",
+      "packages": {
+        "specifiers": {
+          "jsr:@scope/a@1": "jsr:@scope/a@1.0.0",
+          "jsr:@scope/b@1": "jsr:@scope/b@1.0.0",
+          "npm:chalk@5": "npm:chalk@5.0.0",
+          "npm:nanoid@3": "npm:nanoid@3.3.4"
+        },
+        "jsr": {
+          "@scope/a@1.0.0": {
+            "integrity": "a",
+            "dependencies": ["jsr:@scope/b@1", "npm:chalk@5"]
+          },
+          "@scope/b@1.0.0": {
+            "integrity": "b"
+          }
+        },
+        "npm": {
+          "ansi-styles@4.1.0": {
+            "integrity": "sha512-ansi",
+            "dependencies": {}
+          },
+          "chalk@5.0.0": {
+            "integrity": "sha512-chalk",
+            "dependencies": {
+              "ansi-styles": "ansi-styles@4.1.0"
+            }
+          },
+          "nanoid@3.3.4": {
+            "integrity": "sha512-nanoid",
+            "dependencies": {}
+          }
+        }
+      },
+      "remote": {
+        "https://deno.land/x/mod.ts": "checksum"
+      },
+      "workspace": {
+        "dependencies": ["jsr:@scope/a@1", "npm:nanoid@3"]
+      }
+    }))
+    .unwrap()
+  }
+
+  fn graph_from_npm_packages(
+    specifiers: &[&str],
+    packages: &[(&str, &[&str])],
+  ) -> LockfilePackageGraph {
+    let content: LockfileContent = serde_json::from_value(json!({
+      "version": "3",
+      "packages": {
+        "specifiers": specifiers
+          .iter()
+          .map(|id| (format!("npm:{}", id), json!(format!("npm:{}", id))))
+          .collect::<serde_json::Map<_, _>>(),
+        "npm": packages
+          .iter()
+          .map(|(id, deps)| {
+            let deps = deps
+              .iter()
+              .map(|dep| (npm_dep_name(dep).to_string(), json!(dep)))
+              .collect::<serde_json::Map<_, _>>();
+            (id.to_string(), json!({ "integrity": "", "dependencies": deps }))
+          })
+          .collect::<serde_json::Map<_, _>>(),
+      },
+      "remote": {},
+    }))
+    .unwrap();
+    LockfilePackageGraph::from_content(&content)
+  }
+
+  fn npm_dep_name(id: &str) -> &str {
+    &id[..id.find('@').unwrap()]
+  }
+
+  #[test]
+  fn graph_topological_order() {
+    let graph = graph_from_npm_packages(
+      &["a@1.0.0", "e@1.0.0"],
+      &[
+        ("a@1.0.0", &["b@1.0.0", "c@1.0.0"]),
+        ("b@1.0.0", &["d@1.0.0"]),
+        ("c@1.0.0", &["d@1.0.0"]),
+        ("d@1.0.0", &[]),
+        ("e@1.0.0", &["c@1.0.0"]),
+        // not reachable
+        ("f@1.0.0", &["a@1.0.0"]),
+      ],
+    );
+    let order = graph.topological_order().unwrap();
+    assert_eq!(
+      order,
+      vec![
+        "npm:d@1.0.0".to_string(),
+        "npm:b@1.0.0".to_string(),
+        "npm:c@1.0.0".to_string(),
+        "npm:a@1.0.0".to_string(),
+        "npm:e@1.0.0".to_string(),
+      ]
+    );
+    assert_eq!(
+      graph.topological_levels_allowing_cycles(),
+      vec![
+        vec!["npm:d@1.0.0".to_string()],
+        vec!["npm:b@1.0.0".to_string(), "npm:c@1.0.0".to_string()],
+        vec!["npm:a@1.0.0".to_string(), "npm:e@1.0.0".to_string()],
+      ]
+    );
+
+    // deterministic regardless of the hash map ordering
+    for _ in 0..10 {
+      let graph = graph_from_npm_packages(
+        &["e@1.0.0", "a@1.0.0"],
+        &[
+          ("e@1.0.0", &["c@1.0.0"]),
+          ("d@1.0.0", &[]),
+          ("c@1.0.0", &["d@1.0.0"]),
+          ("b@1.0.0", &["d@1.0.0"]),
+          ("a@1.0.0", &["c@1.0.0", "b@1.0.0"]),
+        ],
+      );
+      assert_eq!(graph.topological_order().unwrap(), order);
+    }
+  }
+
+  #[test]
+  fn graph_topological_order_cycle() {
+    let graph = graph_from_npm_packages(
+      &["a@1.0.0"],
+      &[
+        ("a@1.0.0", &["b@1.0.0"]),
+        ("b@1.0.0", &["c@1.0.0"]),
+        ("c@1.0.0", &["b@1.0.0", "d@1.0.0"]),
+        ("d@1.0.0", &[]),
+      ],
+    );
+    let err = graph.topological_order().unwrap_err();
+    assert_eq!(
+      err.component,
+      vec!["npm:b@1.0.0".to_string(), "npm:c@1.0.0".to_string()]
+    );
+    assert_eq!(
+      err.to_string(),
+      "Dependency cycle found between packages: npm:b@1.0.0, npm:c@1.0.0"
+    );
+    assert_eq!(
+      graph.topological_levels_allowing_cycles(),
+      vec![
+        vec!["npm:d@1.0.0".to_string()],
+        vec!["npm:b@1.0.0".to_string(), "npm:c@1.0.0".to_string()],
+        vec!["npm:a@1.0.0".to_string()],
+      ]
+    );
+
+    // depending on itself
+    let graph =
+      graph_from_npm_packages(&["a@1.0.0"], &[("a@1.0.0", &["a@1.0.0"])]);
+    assert_eq!(
+      graph.topological_order().unwrap_err().component,
+      vec!["npm:a@1.0.0".to_string()]
+    );
+  }
+
+  #[test]
+  fn graph_find_cycles() {
+    let graph = graph_from_npm_packages(
+      &["a@1.0.0"],
+      &[
+        ("a@1.0.0", &["b@1.0.0", "e@1.0.0"]),
+        ("b@1.0.0", &["c@1.0.0"]),
+        ("c@1.0.0", &["d@1.0.0"]),
+        ("d@1.0.0", &["b@1.0.0", "e@1.0.0"]),
+        ("e@1.0.0", &["f@1.0.0"]),
+        ("f@1.0.0", &[]),
+        // not reachable, but still reported
+        ("g@1.0.0", &["g@1.0.0"]),
+      ],
+    );
+    let expected = vec![
+      vec![
+        "npm:b@1.0.0".to_string(),
+        "npm:c@1.0.0".to_string(),
+        "npm:d@1.0.0".to_string(),
+      ],
+      vec!["npm:g@1.0.0".to_string()],
+    ];
+    assert_eq!(graph.find_cycles(), expected);
+
+    let graph = graph_from_npm_packages(
+      &["a@1.0.0"],
+      &[("a@1.0.0", &["b@1.0.0"]), ("b@1.0.0", &[])],
+    );
+    assert!(graph.find_cycles().is_empty());
+  }
+
+  #[test]
+  fn mermaid_node_ids_are_distinct() {
+    let names = [
+      "npm:a@1.0.0",
+      "npm:a_1.0.0",
+      "npm:a-1.0.0",
+      "npm:a@1_0_0",
+      "npm:a@1.0.0_b@1.0.0",
+      "npm:a@1.0.0_b_40_1.0.0",
+      "jsr:@scope/a@1.0.0",
+      "jsr:@scope_a@1.0.0",
+      "workspace",
+      "workspace:a",
+      "workspace:a/b",
+      "workspace:a_b",
+    ];
+    let ids = names
+      .iter()
+      .map(|name| mermaid_node_id(name))
+      .collect::<HashSet<_>>();
+    assert_eq!(ids.len(), names.len());
+    for id in ids {
+      assert!(id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+    }
+    assert_eq!(mermaid_node_id("npm:a@1.0.0"), "npm_3a_a_40_1_2e_0_2e_0");
+  }
+
+  #[test]
+  fn dot_node_ids_escape_quotes_and_backslashes() {
+    assert_eq!(dot_node_id("npm:a@1.0.0"), r#""npm:a@1.0.0""#);
+    assert_eq!(dot_node_id(r#"workspace:a"b\c"#), r#""workspace:a\"b\\c""#);
+    assert_eq!(dot_node_id("workspace:café"), r#""workspace:café""#);
+  }
+
+  #[test]
+  fn graph_reachable_from() {
+    let content = graph_test_content();
+    let graph = LockfilePackageGraph::from_content(&content);
+    assert_eq!(
+      graph.reachable_from(["jsr:@scope/a@1"].into_iter()),
+      BTreeSet::from([
+        "jsr:@scope/a@1.0.0".to_string(),
+        "jsr:@scope/b@1.0.0".to_string(),
+        "npm:ansi-styles@4.1.0".to_string(),
+        "npm:chalk@5.0.0".to_string(),
+      ])
+    );
+    assert_eq!(
+      graph.reachable_from(["npm:chalk@5", "npm:unknown@1"].into_iter()),
+      BTreeSet::from([
+        "npm:ansi-styles@4.1.0".to_string(),
+        "npm:chalk@5.0.0".to_string(),
+      ])
+    );
+    // the content was not consumed
+    assert_eq!(content.packages.npm.len(), 3);
+  }
+
+  #[test]
+  fn graph_reachable_from_cycle() {
+    let graph = graph_from_npm_packages(
+      &["a@1.0.0"],
+      &[
+        ("a@1.0.0", &["b@1.0.0"]),
+        ("b@1.0.0", &["c@1.0.0"]),
+        ("c@1.0.0", &["a@1.0.0", "d@1.0.0"]),
+        ("d@1.0.0", &[]),
+        ("e@1.0.0", &[]),
+      ],
+    );
+    let expected = BTreeSet::from([
+      "npm:a@1.0.0".to_string(),
+      "npm:b@1.0.0".to_string(),
+      "npm:c@1.0.0".to_string(),
+      "npm:d@1.0.0".to_string(),
+    ]);
+    assert_eq!(graph.reachable_from(["npm:a@1.0.0"].into_iter()), expected);
+    assert_eq!(
+      graph.transitive_closure(["npm:a@1.0.0"].into_iter()),
+      expected
+    );
+  }
+
+  #[test]
+  fn graph_remove_root_packages() {
+    let content = graph_test_content();
+    let mut graph = LockfilePackageGraph::from_content(&content);
+    graph.remove_root_packages(["jsr:@scope/a@1".to_string()].into_iter());
+    assert_eq!(
+      graph.reachable_from(["jsr:@scope/a@1", "npm:chalk@5"].into_iter()),
+      BTreeSet::new(),
+    );
+
+    let mut packages = PackagesContent::default();
+    let mut remotes = BTreeMap::new();
+    graph.populate_packages(&mut packages, &mut remotes);
+    assert_eq!(
+      packages.specifiers,
+      BTreeMap::from([(
+        "npm:nanoid@3".to_string(),
+        "npm:nanoid@3.3.4".to_string()
+      )])
+    );
+    assert!(packages.jsr.is_empty());
+    assert_eq!(
+      packages.npm.keys().collect::<Vec<_>>(),
+      vec!["nanoid@3.3.4"]
+    );
+    assert_eq!(remotes, content.remote);
+  }
+
+  #[test]
+  fn graph_json_reachability_round_trip() {
+    let content: LockfileContent = serde_json::from_value(json!({
+      "version": "3",
+      "packages": {
+        "specifiers": {
+          "jsr:@scope/a@1": "jsr:@scope/a@1.0.0",
+          "jsr:@scope/b@1": "jsr:@scope/b@1.0.0",
+          "npm:chalk@5": "npm:chalk@5.0.0",
+          "npm:gone@1": "npm:gone@1.0.0"
+        },
+        "jsr": {
+          "@scope/a@1.0.0": {
+            "integrity": "a",
+            "dependencies": ["npm:chalk@5", "jsr:@scope/unknown@1"]
+          },
+          "@scope/b@1.0.0": {
+            "integrity": "b"
+          }
+        },
+        "npm": {
+          "ansi-styles@4.1.0": {
+            "integrity": "sha512-ansi",
+            "dependencies": {}
+          },
+          "chalk@5.0.0": {
+            "integrity": "sha512-chalk",
+            "dependencies": {
+              "ansi-styles": "ansi-styles@4.1.0",
+              "missing": "missing@1.0.0"
+            }
+          },
+          "nanoid@3.3.4": {
+            "integrity": "sha512-nanoid",
+            "dependencies": {}
+          }
+        }
+      },
+      "remote": {},
+      "workspace": {
+        "dependencies": ["jsr:@scope/a@1", "npm:gone@1"]
+      }
+    }))
+    .unwrap();
+
+    let json = to_graph_json(&content);
+    assert_eq!(
+      json["missing"],
+      json!([
+        "jsr:@scope/unknown@1",
+        "npm:gone@1.0.0",
+        "npm:missing@1.0.0"
+      ])
+    );
+    assert_eq!(json["roots"], json!(["jsr:@scope/a@1.0.0"]));
+    assert_eq!(
+      json["nodes"]["jsr:@scope/a@1.0.0"],
+      json!({
+        "kind": "jsr",
+        "integrity": "a",
+        "deps": ["npm:chalk@5.0.0"],
+      })
+    );
+
+    // reconstruct the reachability from the exported json
+    let nodes = json["nodes"].as_object().unwrap();
+    let mut reached = BTreeSet::new();
+    let mut pending = json["roots"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|id| id.as_str().unwrap())
+      .collect::<Vec<_>>();
+    while let Some(id) = pending.pop() {
+      if reached.insert(id.to_string()) {
+        for dep in nodes[id]["deps"].as_array().unwrap() {
+          pending.push(dep.as_str().unwrap());
+        }
+      }
+    }
+    let unreachable = nodes
+      .keys()
+      .filter(|id| !reached.contains(*id))
+      .cloned()
+      .collect::<BTreeSet<_>>();
+    assert_eq!(
+      unreachable,
+      BTreeSet::from([
+        "jsr:@scope/b@1.0.0".to_string(),
+        "npm:nanoid@3.3.4".to_string(),
+      ])
+    );
+    assert_eq!(unreachable, content.unreachable_packages());
+  }
+}
@@ -1,22 +1,102 @@
 // Copyright 2018-2024 the Deno authors. All rights reserved. MIT license.
 
+mod duplicate_keys;
 mod error;
 mod graphs;
+mod npm_package_lock;
+mod package_ids;
 
 use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 use std::collections::HashSet;
+use std::ops::Bound;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 
 use serde::Deserialize;
 use serde::Serialize;
 
 mod transforms;
+mod version_req;
 
+pub use error::CycleError;
+pub use error::FrozenError;
 pub use error::LockfileError as Error;
+pub use error::MissingPackageError;
+pub use error::MoveError;
+pub use error::PinError;
+pub use error::RenameError;
+pub use error::ResolutionError;
+pub use error::TransformError;
+pub use graphs::DotKindStyle;
+pub use graphs::DotOptions;
+pub use graphs::LockfilePackageGraph;
+pub use graphs::MermaidOptions;
+pub use package_ids::PackageId;
+pub use package_ids::PackageReq;
 
-use crate::graphs::LockfilePackageGraph;
+/// Transforms the JSON of a lockfile in any supported version (ex. the
+/// parsed text of a `deno.lock`) to version 3, which is the version
+/// lockfiles are written in, without creating a [`Lockfile`].
+///
+/// The input is a version 1 lockfile (an object of remote URLs and
+/// checksums without a `version`), a version 2 lockfile (`remote` and an
+/// `npm` object of `specifiers` and `packages`), or a version 3 lockfile.
+/// The output has the version 3 shape, where npm and jsr packages are
+/// stored under `packages` and remote checksums under `remote`.
+///
+/// Version 4 lockfiles are rejected since that would downgrade them.
+/// Use [`transform4_to_3`] to do that deliberately.
+pub fn upgrade_to_latest(
+  value: serde_json::Value,
+) -> Result<serde_json::Value, TransformError> {
+  let serde_json::Value::Object(json) = value else {
+    return Err(TransformError {
+      from_version: "unknown".to_string(),
+      to_version: "3".to_string(),
+      message: "Expected the lockfile to be an object.".to_string(),
+    });
+  };
+  let (json, _) = transforms::transform_to_current(json)?;
+  Ok(json.into())
+}
+
+/// Computes the checksum of a remote module's bytes in the format stored
+/// in `remote`, which is the lowercase hex sha256 digest without a
+/// prefix (ex. `e3b0c442...`).
+pub fn compute_remote_checksum(bytes: &[u8]) -> String {
+  use sha2::Digest;
+  format!("{:x}", sha2::Sha256::digest(bytes))
+}
+
+/// Downgrades the JSON of a version 4 lockfile, which is written by newer
+/// versions of Deno, to version 3 so it can be loaded by
+/// [`Lockfile::with_lockfile_content`] and written in version 3.
+///
+/// Moves the `specifiers`, `jsr`, and `npm` sections back under `packages`
+/// and reconstructs the version 3 specifier and npm dependency formats.
+/// Errors with the offending entry when there's data that can't be
+/// represented in version 3.
+pub fn transform4_to_3(
+  value: serde_json::Value,
+) -> Result<serde_json::Value, TransformError> {
+  let error = |message: String| TransformError {
+    from_version: "4".to_string(),
+    to_version: "3".to_string(),
+    message,
+  };
+  let serde_json::Value::Object(json) = value else {
+    return Err(error("Expected the lockfile to be an object.".to_string()));
+  };
+  if json.get("version").and_then(|v| v.as_str()) != Some("4") {
+    return Err(error("Expected a version 4 lockfile.".to_string()));
+  }
+  transforms::transform4_to_3(json)
+    .map(|json| json.into())
+    .map_err(error)
+}
 
 pub struct SetWorkspaceConfigOptions {
   pub config: WorkspaceConfig,
@@ -50,27 +130,92 @@ pub struct WorkspaceMemberConfig {
   pub package_json_deps: BTreeSet<String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Refers to the root of the workspace or one of its members.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkspaceMemberRef<'a> {
+  Root,
+  Member(&'a str),
+}
+
+impl<'a> std::fmt::Display for WorkspaceMemberRef<'a> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      WorkspaceMemberRef::Root => write!(f, "the workspace root"),
+      WorkspaceMemberRef::Member(name) => {
+        write!(f, "workspace member '{}'", name)
+      }
+    }
+  }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct NpmPackageLockfileInfo {
+  /// Id without the `npm:` scheme (ex. `chalk@5.0.0`), which is the
+  /// [`PackageId::package_key`] of a parsed id.
   pub serialized_id: String,
   pub integrity: String,
   pub dependencies: Vec<NpmPackageDependencyLockfileInfo>,
+  /// Dependencies that are optional. These should also be in
+  /// `dependencies` when they're resolved.
+  pub optional_dependencies: Vec<NpmPackageDependencyLockfileInfo>,
+  /// Peer dependencies. These should also be in `dependencies`
+  /// when they're resolved.
+  pub peer_dependencies: Vec<NpmPackageDependencyLockfileInfo>,
 }
 
+/// A dependency of an npm package.
+///
+/// Aliased dependencies (ex. `"chalk-v4": "npm:chalk@^4"` in a
+/// package.json) use the alias as the name and the id of the real
+/// package, which is stored as `"chalk-v4": "chalk@4.1.2"`.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct NpmPackageDependencyLockfileInfo {
+  /// Name or alias the package depends on the dependency with.
   pub name: String,
+  /// Id of the dependency (ex. `chalk@4.1.2`).
   pub id: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsrPackageLockfileInfo {
+  /// Name and version of the package (ex. `@std/path@0.75.0`).
+  pub nv: String,
+  pub integrity: String,
+  /// Package requirements of the package's dependencies
+  /// (ex. `jsr:@std/assert@^0.75`).
+  pub dependencies: Vec<String>,
+}
+
+/// Resolved packages, specifiers, and remotes used to create the content
+/// of a lockfile in one step.
+#[derive(Debug, Default, Clone)]
+pub struct ResolutionGraph {
+  /// Mapping between package requirements and the package ids they
+  /// resolve to (ex. `jsr:@std/path@^0.75` to `jsr:@std/path@0.75.0`).
+  pub specifiers: BTreeMap<String, String>,
+  pub jsr_packages: Vec<JsrPackageLockfileInfo>,
+  pub npm_packages: Vec<NpmPackageLockfileInfo>,
+  pub redirects: BTreeMap<String, String>,
+  /// Mapping between URLs and their checksums.
+  pub remotes: BTreeMap<String, String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, Eq)]
 pub struct NpmPackageInfo {
   pub integrity: String,
   // todo(dsherret): we should skip serializing this in a future lockfile version
   pub dependencies: BTreeMap<String, String>,
+  /// Subset of the dependencies that are optional.
+  #[serde(rename = "optionalDependencies")]
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub optional_dependencies: BTreeMap<String, String>,
+  /// Subset of the dependencies that are peer dependencies.
+  #[serde(rename = "peerDependencies")]
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub peer_dependencies: BTreeMap<String, String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, Eq)]
 pub struct JsrPackageInfo {
   pub integrity: String,
   /// List of package requirements found in the dependency.
@@ -81,6 +226,49 @@ pub struct JsrPackageInfo {
   pub dependencies: BTreeSet<String>,
 }
 
+/// Information about a jsr or npm package in the lockfile.
+#[derive(Debug, Clone, Copy)]
+pub enum PackageInfoRef<'a> {
+  Jsr(&'a JsrPackageInfo),
+  Npm(&'a NpmPackageInfo),
+}
+
+impl<'a> PackageInfoRef<'a> {
+  pub fn integrity(&self) -> &'a str {
+    match self {
+      PackageInfoRef::Jsr(info) => &info.integrity,
+      PackageInfoRef::Npm(info) => &info.integrity,
+    }
+  }
+}
+
+/// Registry a package is from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegistryKind {
+  Npm,
+  Jsr,
+}
+
+impl RegistryKind {
+  /// Scheme that the package requirements and ids of the registry
+  /// start with (ex. `npm:`).
+  pub fn scheme(&self) -> &'static str {
+    match self {
+      RegistryKind::Npm => "npm:",
+      RegistryKind::Jsr => "jsr:",
+    }
+  }
+}
+
+/// Overrides the contents of a package, such as with a local copy
+/// that has fixes applied.
+#[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, Eq)]
+pub struct PatchInfo {
+  pub integrity: String,
+  /// Where the patched package is located (ex. `./patches/chalk`).
+  pub location: String,
+}
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize, Hash)]
 pub struct PackagesContent {
   /// Mapping between requests for deno specifiers and resolved packages, eg.
@@ -140,9 +328,10 @@ impl LockfilePackageJsonContent {
   }
 }
 
+/// Package requirements of the workspace root or of a workspace member.
 #[derive(Debug, Default, Clone, Serialize, Deserialize, Hash)]
 #[serde(rename_all = "camelCase")]
-struct WorkspaceMemberConfigContent {
+pub struct WorkspaceMemberConfigContent {
   #[serde(skip_serializing_if = "BTreeSet::is_empty")]
   #[serde(default)]
   dependencies: BTreeSet<String>,
@@ -156,6 +345,18 @@ impl WorkspaceMemberConfigContent {
     self.dependencies.is_empty() && self.package_json.is_empty()
   }
 
+  /// Package requirements of the member's config file
+  /// (ex. `jsr:@std/path@^0.75`).
+  pub fn dependencies(&self) -> &BTreeSet<String> {
+    &self.dependencies
+  }
+
+  /// Package requirements of the member's package.json
+  /// (ex. `npm:chalk@^5`).
+  pub fn package_json_deps(&self) -> &BTreeSet<String> {
+    &self.package_json.dependencies
+  }
+
   pub fn dep_reqs(&self) -> impl Iterator<Item = &String> {
     self
       .package_json
@@ -165,9 +366,10 @@ impl WorkspaceMemberConfigContent {
   }
 }
 
+/// Package requirements of the workspace, as in the `workspace` section.
 #[derive(Debug, Default, Clone, Serialize, Deserialize, Hash)]
 #[serde(rename_all = "camelCase")]
-struct WorkspaceConfigContent {
+pub struct WorkspaceConfigContent {
   #[serde(default, flatten)]
   root: WorkspaceMemberConfigContent,
   #[serde(skip_serializing_if = "BTreeMap::is_empty")]
@@ -180,12 +382,88 @@ impl WorkspaceConfigContent {
     self.root.is_empty() && self.members.is_empty()
   }
 
+  /// Package requirements of the workspace root.
+  pub fn root(&self) -> &WorkspaceMemberConfigContent {
+    &self.root
+  }
+
+  /// Package requirements of the workspace members keyed by their path.
+  pub fn members(&self) -> &BTreeMap<String, WorkspaceMemberConfigContent> {
+    &self.members
+  }
+
   fn get_all_dep_reqs(&self) -> impl Iterator<Item = &String> {
     self
       .root
       .dep_reqs()
       .chain(self.members.values().flat_map(|m| m.dep_reqs()))
   }
+
+  /// Renames a dependency of the root and the members, in both the
+  /// deno.json and package.json dependencies.
+  fn rename_dep_req(&mut self, old_req: &str, new_req: &str) {
+    let members =
+      std::iter::once(&mut self.root).chain(self.members.values_mut());
+    for member in members {
+      for deps in [
+        &mut member.dependencies,
+        &mut member.package_json.dependencies,
+      ] {
+        if deps.remove(old_req) {
+          deps.insert(new_req.to_string());
+        }
+      }
+    }
+  }
+}
+
+impl From<WorkspaceMemberConfigContent> for WorkspaceMemberConfig {
+  fn from(content: WorkspaceMemberConfigContent) -> Self {
+    WorkspaceMemberConfig {
+      dependencies: content.dependencies,
+      package_json_deps: content.package_json.dependencies,
+    }
+  }
+}
+
+impl From<WorkspaceMemberConfig> for WorkspaceMemberConfigContent {
+  fn from(config: WorkspaceMemberConfig) -> Self {
+    WorkspaceMemberConfigContent {
+      dependencies: config.dependencies,
+      package_json: LockfilePackageJsonContent {
+        dependencies: config.package_json_deps,
+      },
+    }
+  }
+}
+
+/// Converts the `workspace` section of a lockfile, where the package.json
+/// dependencies are stored in `packageJson.dependencies`, to the config
+/// that can be provided to [`Lockfile::set_workspace_config`].
+impl From<WorkspaceConfigContent> for WorkspaceConfig {
+  fn from(content: WorkspaceConfigContent) -> Self {
+    WorkspaceConfig {
+      root: content.root.into(),
+      members: content
+        .members
+        .into_iter()
+        .map(|(name, member)| (name, member.into()))
+        .collect(),
+    }
+  }
+}
+
+impl From<WorkspaceConfig> for WorkspaceConfigContent {
+  fn from(config: WorkspaceConfig) -> Self {
+    WorkspaceConfigContent {
+      root: config.root.into(),
+      members: config
+        .members
+        .into_iter()
+        .map(|(name, member)| (name, member.into()))
+        .collect(),
+    }
+  }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Hash)]
@@ -200,13 +478,81 @@ pub struct LockfileContent {
   #[serde(default)]
   pub redirects: BTreeMap<String, String>,
   // todo(dsherret): in the next lockfile version we should skip
-  // serializing this when it's empty
+  // serializing this when it's empty. Until then it's only skipped when
+  // opting in with `LockfileOutputOptions::omit_empty_remote`.
   /// Mapping between URLs and their checksums for "http:" and "https:" deps
   #[serde(default)]
   remote: BTreeMap<String, String>,
   #[serde(skip_serializing_if = "WorkspaceConfigContent::is_empty")]
   #[serde(default)]
   workspace: WorkspaceConfigContent,
+  /// Patches of packages keyed by their id (ex. `npm:chalk@5.0.0`).
+  ///
+  /// Patched packages are always kept in the lockfile.
+  #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+  #[serde(default)]
+  pub patches: BTreeMap<String, PatchInfo>,
+  /// Notes about packages keyed by their id (ex. `npm:chalk@5.0.0`),
+  /// such as why a package is pinned. These aren't used when resolving.
+  #[serde(rename = "$comments")]
+  #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+  #[serde(default)]
+  pub comments: BTreeMap<String, String>,
+  /// Top level keys not known to this crate, which are maintained so
+  /// sections written by newer versions aren't lost.
+  #[serde(flatten)]
+  extra: ExtraKeys,
+}
+
+/// Writes the JSON that's written to the lockfile file, without the
+/// trailing newline.
+impl std::fmt::Display for LockfileContent {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    // serializes directly into the formatter instead of a string
+    struct FormatterWriter<'a, 'b>(&'a mut std::fmt::Formatter<'b>);
+
+    impl std::io::Write for FormatterWriter<'_, '_> {
+      fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let text = std::str::from_utf8(buf).map_err(|err| {
+          std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+        })?;
+        self
+          .0
+          .write_str(text)
+          .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+        Ok(buf.len())
+      }
+
+      fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+      }
+    }
+
+    serde_json::to_writer_pretty(FormatterWriter(f), self)
+      .map_err(|_| std::fmt::Error)
+  }
+}
+
+/// Keys of the sections under `packages`.
+const PACKAGE_SECTIONS: [&str; 3] = ["specifiers", "jsr", "npm"];
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+struct ExtraKeys(BTreeMap<String, serde_json::Value>);
+
+impl std::hash::Hash for ExtraKeys {
+  fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+    for (key, value) in &self.0 {
+      key.hash(state);
+      value.to_string().hash(state);
+    }
+  }
+}
+
+impl Default for LockfileContent {
+  fn default() -> Self {
+    Self::empty()
+  }
 }
 
 impl LockfileContent {
@@ -217,844 +563,8627 @@ impl LockfileContent {
       redirects: Default::default(),
       remote: BTreeMap::new(),
       workspace: Default::default(),
+      patches: Default::default(),
+      comments: Default::default(),
+      extra: Default::default(),
     }
   }
 
-  pub fn is_empty(&self) -> bool {
-    self.packages.is_empty()
-      && self.redirects.is_empty()
-      && self.remote.is_empty()
-      && self.workspace.is_empty()
-  }
-}
-
-#[derive(Debug, Clone, Hash)]
-pub struct Lockfile {
-  pub overwrite: bool,
-  pub has_content_changed: bool,
-  pub content: LockfileContent,
-  pub filename: PathBuf,
-}
-
-impl Lockfile {
-  pub fn new_empty(filename: PathBuf, overwrite: bool) -> Lockfile {
-    Lockfile {
-      overwrite,
-      has_content_changed: false,
-      content: LockfileContent::empty(),
-      filename,
+  /// Creates content that only has the packages and specifiers reachable
+  /// from the provided package requirements, which become the workspace
+  /// dependencies of the content.
+  ///
+  /// The remotes and redirects are limited to the modules of the
+  /// reachable jsr packages.
+  fn subtree_for_roots<'a>(
+    &self,
+    reqs: impl Iterator<Item = &'a str>,
+  ) -> LockfileContent {
+    let mut content = LockfileContent::empty();
+    let mut pending_reqs = Vec::new();
+    for req in reqs {
+      content.workspace.root.dependencies.insert(req.to_string());
+      pending_reqs.push(req.to_string());
     }
-  }
 
-  /// Create a new [`Lockfile`] instance from given filename and its content.
-  pub fn with_lockfile_content(
-    filename: PathBuf,
-    content: &str,
-    overwrite: bool,
-  ) -> Result<Lockfile, Error> {
-    // Writing a lock file always uses the new format.
-    if overwrite {
-      return Ok(Lockfile::new_empty(filename, overwrite));
+    let mut pending_npm_ids = Vec::new();
+    while let Some(req) = pending_reqs.pop() {
+      let Some(id) = self.packages.specifiers.get(&req) else {
+        continue;
+      };
+      if content
+        .packages
+        .specifiers
+        .insert(req, id.clone())
+        .is_some()
+      {
+        continue;
+      }
+      if let Some(id) = id.strip_prefix("npm:") {
+        pending_npm_ids.push(id.to_string());
+      } else if let Some(nv) = id.strip_prefix("jsr:") {
+        if let Some(package) = self.packages.jsr.get(nv) {
+          pending_reqs.extend(package.dependencies.iter().cloned());
+          content.packages.jsr.insert(nv.to_string(), package.clone());
+        }
+      }
     }
-
-    if content.trim().is_empty() {
-      return Err(Error::ReadError("Lockfile was empty.".to_string()));
+    while let Some(id) = pending_npm_ids.pop() {
+      if content.packages.npm.contains_key(&id) {
+        continue;
+      }
+      if let Some(package) = self.packages.npm.get(&id) {
+        pending_npm_ids.extend(package.dependencies.values().cloned());
+        content.packages.npm.insert(id, package.clone());
+      }
     }
 
-    let value: serde_json::Map<String, serde_json::Value> =
-      serde_json::from_str(content).map_err(|err| {
-        Error::ParseError(filename.display().to_string(), err)
-      })?;
-    let version = value.get("version").and_then(|v| v.as_str());
-    let value = match version {
-      Some("3") => value,
-      Some("2") => transforms::transform2_to_3(value),
-      None => transforms::transform2_to_3(transforms::transform1_to_2(value)),
-      Some(version) => {
-        return Err(Error::UnsupportedVersion(version.to_string()));
-      }
+    // jsr modules are in the remotes (ex. `https://jsr.io/@std/path/0.75.0/mod.ts`)
+    let prefixes = content
+      .packages
+      .jsr
+      .keys()
+      .filter_map(|nv| {
+        let name = npm_id_name(nv);
+        let version = nv.get(name.len() + 1..)?;
+        Some([
+          format!("https://jsr.io/{}/{}/", name, version),
+          format!("jsr:{}/", nv),
+        ])
+      })
+      .flatten()
+      .collect::<Vec<_>>();
+    let is_reachable = |url: &str| {
+      let url = format!("{}/", url);
+      prefixes.iter().any(|prefix| url.starts_with(prefix))
     };
-    let content = serde_json::from_value::<LockfileContent>(value.into())
-      .map_err(|err| Error::ParseError(filename.display().to_string(), err))?;
+    content.remote = self
+      .remote
+      .iter()
+      .filter(|(url, _)| is_reachable(url))
+      .map(|(url, checksum)| (url.clone(), checksum.clone()))
+      .collect();
+    content.redirects = self
+      .redirects
+      .iter()
+      .filter(|(_, to)| is_reachable(to))
+      .map(|(from, to)| (from.clone(), to.clone()))
+      .collect();
+    content
+  }
 
-    Ok(Lockfile {
-      overwrite,
-      has_content_changed: false,
-      content,
-      filename,
-    })
+  /// Converts the packages of an npm `package-lock.json` with a
+  /// `lockfileVersion` of 2 or 3 into the npm section of the content.
+  ///
+  /// The dependencies of the root package become the specifiers. Packages
+  /// without a version or integrity, such as linked workspace packages
+  /// and git dependencies, are dropped along with the dependencies on them.
+  /// The other fields of the packages (ex. `resolved`, `dev`, `bin`,
+  /// `engines`, and `license`) are dropped since they have no equivalent.
+  /// When a package is installed at multiple paths, the dependencies of
+  /// the first path are used.
+  pub fn from_npm_package_lock(
+    json: &serde_json::Value,
+  ) -> Result<LockfileContent, Error> {
+    npm_package_lock::from_npm_package_lock(json)
   }
 
-  pub fn as_json_string(&self) -> String {
-    let mut json_string = serde_json::to_string_pretty(&self.content).unwrap();
-    json_string.push('\n'); // trailing newline in file
-    json_string
+  /// Version of the lockfile format (ex. `3`).
+  pub fn version(&self) -> &str {
+    &self.version
   }
 
-  pub fn set_workspace_config(
-    &mut self,
-    mut options: SetWorkspaceConfigOptions,
-  ) {
-    fn update_workspace_member(
-      has_content_changed: &mut bool,
-      removed_deps: &mut HashSet<String>,
-      current: &mut WorkspaceMemberConfigContent,
-      new: WorkspaceMemberConfig,
-    ) {
-      if new.dependencies != current.dependencies {
-        let old_deps =
-          std::mem::replace(&mut current.dependencies, new.dependencies);
+  /// Checksums of the remote modules keyed by their specifier.
+  pub fn remote(&self) -> &BTreeMap<String, String> {
+    &self.remote
+  }
 
-        removed_deps.extend(old_deps);
+  /// Package requirements of the workspace.
+  pub fn workspace(&self) -> &WorkspaceConfigContent {
+    &self.workspace
+  }
 
-        *has_content_changed = true;
-      }
+  /// Gets the number of entries in each section.
+  pub fn package_count(&self) -> LockfileStats {
+    LockfileStats {
+      remote: self.remote.len(),
+      npm_packages: self.packages.npm.len(),
+      jsr_packages: self.packages.jsr.len(),
+      specifiers: self.packages.specifiers.len(),
+      redirects: self.redirects.len(),
+    }
+  }
 
-      if new.package_json_deps != current.package_json.dependencies {
-        // update self.content.package_json
-        let old_package_json_deps = std::mem::replace(
-          &mut current.package_json.dependencies,
-          new.package_json_deps,
-        );
+  /// Gets the entries that aren't in the base content or have a
+  /// different value there, such as the packages an application adds on
+  /// top of a shared lockfile. See [`Lockfile::remove_fragment`].
+  ///
+  /// The workspace config isn't included since it's not locked data.
+  pub fn subtract(&self, base: &LockfileContent) -> LockfileContent {
+    let mut content = LockfileContent::empty();
+    content.version = self.version.clone();
+    content.packages.specifiers =
+      subtract_map(&self.packages.specifiers, &base.packages.specifiers);
+    content.packages.jsr = subtract_map(&self.packages.jsr, &base.packages.jsr);
+    content.packages.npm = subtract_map(&self.packages.npm, &base.packages.npm);
+    content.redirects = subtract_map(&self.redirects, &base.redirects);
+    content.remote = subtract_map(&self.remote, &base.remote);
+    content.patches = subtract_map(&self.patches, &base.patches);
+    content.comments = subtract_map(&self.comments, &base.comments);
+    content
+  }
 
-        removed_deps.extend(old_package_json_deps);
+  /// Top level keys of the lockfile that aren't known to this crate.
+  pub fn extra(&self) -> &BTreeMap<String, serde_json::Value> {
+    &self.extra.0
+  }
 
-        *has_content_changed = true;
-      }
-    }
+  /// Names of the sections that belong under `packages`, but are at the
+  /// top level of the lockfile (ex. `npm`), such as after an incorrect
+  /// manual edit. See [`Lockfile::repair_misplaced_packages`].
+  pub fn misplaced_package_sections(&self) -> Vec<String> {
+    PACKAGE_SECTIONS
+      .iter()
+      .filter(|section| self.extra.0.contains_key(**section))
+      .map(|section| section.to_string())
+      .collect()
+  }
 
-    // if specified, don't modify the package.json dependencies
-    if options.no_npm || options.no_config {
-      if options.config.root.package_json_deps.is_empty() {
-        options.config.root.package_json_deps = self
-          .content
-          .workspace
-          .root
-          .package_json
-          .dependencies
-          .clone();
-      }
-      for (key, value) in options.config.members.iter_mut() {
-        if value.package_json_deps.is_empty() {
-          value.package_json_deps = self
-            .content
-            .workspace
-            .members
-            .get(key)
-            .map(|m| m.package_json.dependencies.clone())
-            .unwrap_or_default();
-        }
+  /// Converts the content to the JSON value that is written to the
+  /// lockfile file.
+  pub fn to_value(&self) -> serde_json::Value {
+    // can't fail since the content only consists of maps with string keys
+    serde_json::to_value(self).unwrap()
+  }
+
+  /// Creates the content from a resolution graph.
+  ///
+  /// Errors with every reference that can't be resolved within the graph:
+  /// specifiers resolving to packages not in the graph, jsr dependencies
+  /// without a specifier, and npm dependencies on packages not in the graph.
+  pub fn from_resolution(
+    graph: &ResolutionGraph,
+  ) -> Result<LockfileContent, ResolutionError> {
+    let jsr_nvs = graph
+      .jsr_packages
+      .iter()
+      .map(|package| package.nv.as_str())
+      .collect::<HashSet<_>>();
+    let npm_ids = graph
+      .npm_packages
+      .iter()
+      .map(|package| package.serialized_id.as_str())
+      .collect::<HashSet<_>>();
+
+    let mut problems = Vec::new();
+    for (req, id) in &graph.specifiers {
+      let exists = if let Some(id) = id.strip_prefix("npm:") {
+        npm_ids.contains(id)
+      } else if let Some(nv) = id.strip_prefix("jsr:") {
+        jsr_nvs.contains(nv)
+      } else {
+        false
+      };
+      if !exists {
+        problems.push(LockfileProblem::MissingSpecifierTarget {
+          req: req.clone(),
+          id: id.clone(),
+        });
       }
     }
-    if options.no_config {
-      if options.config.root.dependencies.is_empty() {
-        options.config.root.dependencies =
-          self.content.workspace.root.dependencies.clone();
-      }
-      for (key, value) in options.config.members.iter_mut() {
-        if value.dependencies.is_empty() {
-          value.dependencies = self
-            .content
-            .workspace
-            .members
-            .get(key)
-            .map(|m| m.dependencies.clone())
-            .unwrap_or_default();
+    for package in &graph.jsr_packages {
+      for req in &package.dependencies {
+        if !graph.specifiers.contains_key(req) {
+          problems.push(LockfileProblem::MissingJsrDependency {
+            id: format!("jsr:{}", package.nv),
+            req: req.clone(),
+          });
         }
       }
-      for (key, value) in self.content.workspace.members.iter() {
-        if options.config.members.get(key).is_none() {
-          options.config.members.insert(
-            key.clone(),
-            WorkspaceMemberConfig {
-              dependencies: value.dependencies.clone(),
-              package_json_deps: value.package_json.dependencies.clone(),
-            },
-          );
+    }
+    for package in &graph.npm_packages {
+      let deps = package
+        .dependencies
+        .iter()
+        .chain(&package.optional_dependencies)
+        .chain(&package.peer_dependencies);
+      for dep in deps {
+        if !npm_ids.contains(dep.id.as_str()) {
+          problems.push(LockfileProblem::MissingNpmDependency {
+            id: format!("npm:{}", package.serialized_id),
+            dep_id: dep.id.clone(),
+          });
         }
       }
     }
+    if !problems.is_empty() {
+      return Err(ResolutionError { problems });
+    }
 
-    // If the lockfile is empty, it's most likely not created yet and so
-    // we don't want this information being added to the lockfile to cause
-    // a lockfile to be created. If this is the case, revert the lockfile back
-    // to !self.has_content_changed after populating it with this information
-    let allow_content_changed =
-      self.has_content_changed || !self.content.is_empty();
-    let old_deps = self
-      .content
-      .workspace
-      .get_all_dep_reqs()
-      .map(|s| s.to_string())
-      .collect::<HashSet<_>>();
-    let mut removed_deps = HashSet::new();
+    let mut content = LockfileContent::empty();
+    let packages = &mut content.packages;
+    packages.specifiers = graph.specifiers.clone();
+    for package in &graph.jsr_packages {
+      packages.jsr.insert(
+        package.nv.clone(),
+        JsrPackageInfo {
+          integrity: package.integrity.clone(),
+          dependencies: package.dependencies.iter().cloned().collect(),
+        },
+      );
+    }
+    for package in graph.npm_packages.iter().cloned() {
+      let (id, info) = npm_package_info(package);
+      packages.npm.insert(id, info);
+    }
+    content.redirects = graph.redirects.clone();
+    content.remote = graph.remotes.clone();
+    Ok(content)
+  }
 
-    // set the root
-    update_workspace_member(
-      &mut self.has_content_changed,
-      &mut removed_deps,
-      &mut self.content.workspace.root,
-      options.config.root,
-    );
+  pub fn is_empty(&self) -> bool {
+    self.packages.is_empty()
+      && self.redirects.is_empty()
+      && self.remote.is_empty()
+      && self.workspace.is_empty()
+      && self.patches.is_empty()
+      && self.comments.is_empty()
+      && self.extra.0.is_empty()
+  }
 
-    // now go through the workspaces
-    let mut unhandled_members = self
-      .content
-      .workspace
-      .members
-      .keys()
-      .cloned()
-      .collect::<HashSet<_>>();
-    for (member_name, new_member) in options.config.members {
-      unhandled_members.remove(&member_name);
-      let current_member = self
-        .content
-        .workspace
-        .members
-        .entry(member_name)
-        .or_default();
-      update_workspace_member(
-        &mut self.has_content_changed,
-        &mut removed_deps,
-        current_member,
-        new_member,
-      );
-    }
+  /// Gets the ids of the packages (ex. `npm:chalk@5.0.0`) that can't be
+  /// reached from the workspace dependencies or, when there's no workspace
+  /// config, from the package specifiers.
+  pub fn unreachable_packages(&self) -> BTreeSet<String> {
+    graphs::unreachable_package_ids(self)
+  }
 
-    for member in unhandled_members {
-      if let Some(member) = self.content.workspace.members.remove(&member) {
-        removed_deps.extend(member.dep_reqs().cloned());
-        self.has_content_changed = true;
-      }
+  /// Counts the packages by the algorithm of their integrity
+  /// (ex. `sha512`). Integrities that are only a hex digest are
+  /// counted as `hex`, while anything else is `unknown`.
+  pub fn integrity_format_summary(&self) -> BTreeMap<String, usize> {
+    let mut summary = BTreeMap::new();
+    let integrities = self
+      .packages
+      .jsr
+      .values()
+      .map(|package| &package.integrity)
+      .chain(self.packages.npm.values().map(|package| &package.integrity));
+    for integrity in integrities {
+      let format = integrity_format(integrity).to_string();
+      *summary.entry(format).or_default() += 1;
     }
+    summary
+  }
 
-    // update the removed deps to keep what's still found in the workspace
-    for dep in self.content.workspace.get_all_dep_reqs() {
-      removed_deps.remove(dep);
-    }
+  /// Gets the remote URLs that are also in the lockfile with a trailing
+  /// slash as pairs of the URL without and with the trailing slash.
+  pub fn remote_trailing_slash_dupes(&self) -> Vec<(String, String)> {
+    self
+      .remote
+      .keys()
+      .filter_map(|url| {
+        let without_slash = url.strip_suffix('/')?;
+        self
+          .remote
+          .contains_key(without_slash)
+          .then(|| (without_slash.to_string(), url.clone()))
+      })
+      .collect()
+  }
 
-    if !removed_deps.is_empty() {
-      let packages = std::mem::take(&mut self.content.packages);
-      let remotes = std::mem::take(&mut self.content.remote);
+  /// Gets the redirects whose target URL has the provided host
+  /// (ex. `deno.land`). Targets that aren't URLs are skipped.
+  pub fn redirects_to_host(&self, host: &str) -> Vec<(&String, &String)> {
+    self
+      .redirects
+      .iter()
+      .filter(|(_, to)| {
+        url_host(to)
+          .map(|to_host| to_host.eq_ignore_ascii_case(host))
+          .unwrap_or(false)
+      })
+      .collect()
+  }
 
-      // create the graph
-      let mut graph = LockfilePackageGraph::from_lockfile(
-        packages,
-        remotes,
-        old_deps.iter().map(|dep| dep.as_str()),
-      );
+  /// Gets the ids of npm packages that have a dependency on an existing
+  /// package, but under a key that isn't a valid package name and so
+  /// can't be the name or an alias of the dependency.
+  pub fn inconsistent_dependency_declarations(&self) -> Vec<String> {
+    self
+      .packages
+      .npm
+      .iter()
+      .filter(|(_, package)| {
+        package.dependencies.iter().any(|(key, dep_id)| {
+          self.packages.npm.contains_key(dep_id)
+            && key != npm_id_name(dep_id)
+            && !is_valid_npm_name(key)
+        })
+      })
+      .map(|(id, _)| id.clone())
+      .collect()
+  }
+}
 
-      // remove the packages
-      graph.remove_root_packages(removed_deps.into_iter());
+fn npm_package_info(info: NpmPackageLockfileInfo) -> (String, NpmPackageInfo) {
+  fn to_map(
+    deps: Vec<NpmPackageDependencyLockfileInfo>,
+  ) -> BTreeMap<String, String> {
+    deps.into_iter().map(|dep| (dep.name, dep.id)).collect()
+  }
 
-      // now populate the graph back into the packages
-      graph.populate_packages(
-        &mut self.content.packages,
-        &mut self.content.remote,
-      );
+  let package_info = NpmPackageInfo {
+    integrity: info.integrity,
+    dependencies: to_map(info.dependencies),
+    optional_dependencies: to_map(info.optional_dependencies),
+    peer_dependencies: to_map(info.peer_dependencies),
+  };
+  (info.serialized_id, package_info)
+}
+
+/// Gets the host of a URL without the user info or port
+/// (ex. `deno.land` from `https://deno.land:443/x/mod.ts`).
+fn url_host(url: &str) -> Option<&str> {
+  let (_, rest) = url.split_once("://")?;
+  let authority = rest.split(['/', '?', '#']).next()?;
+  let authority = match authority.rsplit_once('@') {
+    Some((_, authority)) => authority,
+    None => authority,
+  };
+  let host = match authority.rsplit_once(':') {
+    Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+    _ => authority,
+  };
+  if host.is_empty() {
+    None
+  } else {
+    Some(host)
+  }
+}
+
+/// Gets the entries of the map that aren't in the base map
+/// with the same value.
+fn subtract_map<V: Clone + PartialEq>(
+  map: &BTreeMap<String, V>,
+  base: &BTreeMap<String, V>,
+) -> BTreeMap<String, V> {
+  map
+    .iter()
+    .filter(|(key, value)| base.get(*key) != Some(*value))
+    .map(|(key, value)| (key.clone(), value.clone()))
+    .collect()
+}
+
+/// Removes the entries of the fragment that have the same value in the
+/// map, collecting the keys of the entries with a different value.
+fn remove_matching<V: PartialEq>(
+  map: &mut BTreeMap<String, V>,
+  fragment: &BTreeMap<String, V>,
+  mismatched: &mut BTreeSet<String>,
+) -> bool {
+  let mut removed = false;
+  for (key, value) in fragment {
+    match map.get(key) {
+      Some(current) if current == value => {
+        map.remove(key);
+        removed = true;
+      }
+      Some(_) => {
+        mismatched.insert(key.clone());
+      }
+      None => {}
     }
+  }
+  removed
+}
+
+/// Splits a package requirement into its `npm:` or `jsr:` scheme
+/// and the rest (ex. `("jsr:", "@std/path@^0.75")`).
+fn split_pkg_req(req: &str) -> Option<(&str, &str)> {
+  let scheme = req.get(..4)?;
+  let rest = &req[4..];
+  if !matches!(scheme, "npm:" | "jsr:")
+    || rest.is_empty()
+    || rest.chars().any(|c| c.is_whitespace())
+  {
+    return None;
+  }
+  Some((scheme, rest))
+}
+
+/// Extracts the scheme, name, and version from a package id
+/// (ex. `("npm:", "@scope/name", "1.0.0_peer@1.0.0")`).
+fn extract_nv_from_id(id: &str) -> Option<(&str, &str, &str)> {
+  let (scheme, rest) = split_pkg_req(id)?;
+  let name = npm_id_name(rest);
+  let version = rest.get(name.len() + 1..)?;
+  if name.is_empty() || name == "@" || version.is_empty() {
+    return None;
+  }
+  Some((scheme, name, version))
+}
+
+/// Writes and syncs the bytes to a file, using the permissions
+/// of the file at `permissions_path` when it exists.
+fn write_synced(
+  path: &std::path::Path,
+  bytes: &[u8],
+  permissions_path: &std::path::Path,
+) -> std::io::Result<()> {
+  let mut file = std::fs::File::create(path)?;
+  std::io::Write::write_all(&mut file, bytes)?;
+  if let Ok(metadata) = std::fs::metadata(permissions_path) {
+    file.set_permissions(metadata.permissions())?;
+  }
+  file.sync_all()
+}
 
-    if !allow_content_changed {
-      // revert it back so this change doesn't by itself cause
-      // a lockfile to be created.
-      self.has_content_changed = false;
+/// Gets the algorithm of an integrity (ex. `sha512`), `hex` when it's
+/// only a hex digest, or `unknown` otherwise.
+fn integrity_format(integrity: &str) -> &str {
+  match integrity.split_once('-') {
+    Some((algorithm, _))
+      if !algorithm.is_empty()
+        && algorithm.chars().all(|c| c.is_ascii_alphanumeric()) =>
+    {
+      algorithm
     }
+    _ if !integrity.is_empty()
+      && integrity.chars().all(|c| c.is_ascii_hexdigit()) =>
+    {
+      "hex"
+    }
+    _ => "unknown",
   }
+}
 
-  /// Gets the bytes that should be written to the disk.
-  ///
-  /// Ideally when the caller should use an "atomic write"
-  /// when writing this—write to a temporary file beside the
-  /// lockfile, then rename to overwrite. This will make the
-  /// lockfile more resilient when multiple processes are
-  /// writing to it.
-  pub fn resolve_write_bytes(&self) -> Option<Vec<u8>> {
-    if !self.has_content_changed && !self.overwrite {
-      return None;
+/// Lowercases hex digests (ex. `ABC123` to `abc123`) and the algorithm
+/// of subresource integrities (ex. `SHA512-Ab==` to `sha512-Ab==`) since
+/// the base64 part of those is case sensitive.
+fn canonical_integrity(integrity: &str) -> String {
+  let integrity = integrity.trim();
+  if integrity.chars().all(|c| c.is_ascii_hexdigit()) {
+    return integrity.to_ascii_lowercase();
+  }
+  match integrity.split_once('-') {
+    Some((algorithm, hash))
+      if algorithm.chars().all(|c| c.is_ascii_alphanumeric()) =>
+    {
+      format!("{}-{}", algorithm.to_ascii_lowercase(), hash)
     }
+    _ => integrity.to_string(),
+  }
+}
 
-    Some(self.as_json_string().into_bytes())
+/// Gets the package name from a serialized npm package id
+/// (ex. `@scope/name` from `@scope/name@1.0.0_peer@1.0.0`).
+fn npm_id_name(id: &str) -> &str {
+  match id.get(1..).and_then(|rest| rest.find('@')) {
+    Some(index) => &id[..index + 1],
+    None => id,
   }
+}
 
-  pub fn remote(&self) -> &BTreeMap<String, String> {
-    &self.content.remote
+fn is_valid_npm_name(name: &str) -> bool {
+  let is_valid_part = |part: &str| {
+    !part.is_empty()
+      && !part.starts_with('.')
+      && part.chars().all(|c| {
+        c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '~')
+      })
+  };
+  match name.strip_prefix('@') {
+    Some(scoped) => match scoped.split_once('/') {
+      Some((scope, name)) => is_valid_part(scope) && is_valid_part(name),
+      None => false,
+    },
+    None => is_valid_part(name),
   }
+}
 
-  /// Inserts a remote specifier into the lockfile replacing the existing package if it exists.
-  ///
-  /// WARNING: It is up to the caller to ensure checksums of remote modules are
-  /// valid before it is inserted here.
-  pub fn insert_remote(&mut self, specifier: String, hash: String) {
-    let entry = self.content.remote.entry(specifier);
-    match entry {
-      Entry::Vacant(entry) => {
-        entry.insert(hash);
-        self.has_content_changed = true;
+/// What would be removed from a lockfile when removing root package
+/// requirements. See [`Lockfile::simulate_remove_roots`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RemovalPreview {
+  /// Ids of the npm packages (ex. `chalk@5.0.0`).
+  pub npm_packages: BTreeSet<String>,
+  /// Names and versions of the jsr packages (ex. `@std/path@0.75.0`).
+  pub jsr_packages: BTreeSet<String>,
+  /// Package requirements of the specifiers (ex. `npm:chalk@5`).
+  pub specifiers: BTreeSet<String>,
+}
+
+/// What was removed from a lockfile by
+/// [`Lockfile::remove_package_and_orphans`].
+pub type RemovedPackages = RemovalPreview;
+
+/// What was removed from a lockfile by
+/// [`Lockfile::set_workspace_config`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WorkspaceConfigChange {
+  /// Package requirements no longer in the workspace (ex. `npm:chalk@5`).
+  pub removed_reqs: BTreeSet<String>,
+  /// Ids of the npm packages (ex. `chalk@5.0.0`).
+  pub npm_packages: BTreeSet<String>,
+  /// Names and versions of the jsr packages (ex. `@std/path@0.75.0`).
+  pub jsr_packages: BTreeSet<String>,
+  /// Package requirements of the specifiers (ex. `npm:chalk@5`).
+  pub specifiers: BTreeSet<String>,
+}
+
+/// Options for [`Lockfile::prune`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PruneOptions {
+  /// Remote URLs to keep. Every other remote entry is removed. The
+  /// remote entries aren't pruned when `None` because their
+  /// reachability isn't known.
+  pub remote_allowlist: Option<BTreeSet<String>>,
+  /// Redirect sources to keep. Every other redirect is removed. The
+  /// redirects aren't pruned when `None` because their reachability
+  /// isn't known.
+  pub redirects_allowlist: Option<BTreeSet<String>>,
+}
+
+/// What was removed from a lockfile by [`Lockfile::prune`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PruneReport {
+  /// Ids of the npm packages (ex. `chalk@5.0.0`).
+  pub npm_packages: BTreeSet<String>,
+  /// Names and versions of the jsr packages (ex. `@std/path@0.75.0`).
+  pub jsr_packages: BTreeSet<String>,
+  /// Package requirements of the specifiers (ex. `npm:chalk@5`).
+  pub specifiers: BTreeSet<String>,
+  /// Remote URLs.
+  pub remote: BTreeSet<String>,
+  /// Sources of the redirects.
+  pub redirects: BTreeSet<String>,
+}
+
+/// Entries of a fragment that weren't removed by
+/// [`Lockfile::remove_fragment`] because they have a different
+/// value in the lockfile.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FragmentRemovalReport {
+  /// Ids of the npm packages (ex. `chalk@5.0.0`).
+  pub npm_packages: BTreeSet<String>,
+  /// Names and versions of the jsr packages (ex. `@std/path@0.75.0`).
+  pub jsr_packages: BTreeSet<String>,
+  /// Package requirements of the specifiers (ex. `npm:chalk@5`).
+  pub specifiers: BTreeSet<String>,
+  /// Remote URLs.
+  pub remote: BTreeSet<String>,
+  /// Sources of the redirects.
+  pub redirects: BTreeSet<String>,
+  /// Ids of the patched packages (ex. `npm:chalk@5.0.0`).
+  pub patches: BTreeSet<String>,
+  /// Ids of the commented packages (ex. `npm:chalk@5.0.0`).
+  pub comments: BTreeSet<String>,
+}
+
+/// What was changed when updating an npm package.
+/// See [`Lockfile::update_npm_package`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct UpdateReport {
+  /// Ids of the npm packages that now depend on the new version
+  /// (ex. `chalk@5.0.0`).
+  pub dependents: BTreeSet<String>,
+  /// Package requirements of the specifiers that now resolve to the
+  /// new version (ex. `npm:chalk@5`).
+  pub specifiers: BTreeSet<String>,
+  /// If the old version was in the lockfile and was removed.
+  pub removed_old: bool,
+}
+
+/// Problem found in a lockfile by [`Lockfile::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockfileProblem {
+  /// A specifier resolves to something that isn't a package id
+  /// (ex. `chalk@5.0.0` instead of `npm:chalk@5.0.0`).
+  InvalidSpecifierId { req: String, id: String },
+  /// A specifier resolves to a package that isn't in the lockfile.
+  MissingSpecifierTarget { req: String, id: String },
+  /// A package id can't be split into a name and version
+  /// (ex. `npm:chalk`).
+  MalformedPackageId { id: String },
+  /// A jsr package depends on a requirement without a specifier.
+  MissingJsrDependency { id: String, req: String },
+  /// An npm package depends on a package that isn't in the lockfile.
+  MissingNpmDependency { id: String, dep_id: String },
+  /// The integrity of a package isn't a hex digest or in the
+  /// `<algorithm>-<hash>` format.
+  UnknownIntegrityFormat { id: String, integrity: String },
+  /// A section that belongs under `packages` is at the top level of the
+  /// lockfile (ex. `npm`).
+  MisplacedPackageSection { section: String },
+  /// A patch is for a package that isn't in the lockfile.
+  MissingPatchedPackage { id: String },
+}
+
+impl std::fmt::Display for LockfileProblem {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      LockfileProblem::InvalidSpecifierId { req, id } => {
+        write!(
+          f,
+          "Specifier '{}' resolves to invalid package id '{}'.",
+          req, id
+        )
       }
-      Entry::Occupied(mut entry) => {
-        if entry.get() != &hash {
-          entry.insert(hash);
-          self.has_content_changed = true;
-        }
+      LockfileProblem::MissingSpecifierTarget { req, id } => {
+        write!(
+          f,
+          "Specifier '{}' resolves to missing package '{}'.",
+          req, id
+        )
+      }
+      LockfileProblem::MalformedPackageId { id } => {
+        write!(f, "Package id '{}' is malformed.", id)
+      }
+      LockfileProblem::MissingJsrDependency { id, req } => {
+        write!(
+          f,
+          "Package '{}' depends on '{}', which has no specifier.",
+          id, req
+        )
+      }
+      LockfileProblem::MissingNpmDependency { id, dep_id } => {
+        write!(
+          f,
+          "Package '{}' depends on missing package '{}'.",
+          id, dep_id
+        )
+      }
+      LockfileProblem::UnknownIntegrityFormat { id, integrity } => {
+        write!(
+          f,
+          "Package '{}' has integrity '{}' in an unknown format.",
+          id, integrity
+        )
+      }
+      LockfileProblem::MisplacedPackageSection { section } => {
+        write!(
+          f,
+          "Section '{}' is at the top level instead of under 'packages'.",
+          section
+        )
+      }
+      LockfileProblem::MissingPatchedPackage { id } => {
+        write!(f, "Patched package '{}' is missing.", id)
       }
     }
   }
+}
 
-  /// Inserts an npm package into the lockfile replacing the existing package if it exists.
-  ///
-  /// WARNING: It is up to the caller to ensure checksums of packages are
-  /// valid before it is inserted here.
-  pub fn insert_npm_package(&mut self, package_info: NpmPackageLockfileInfo) {
-    let dependencies = package_info
-      .dependencies
-      .into_iter()
-      .map(|dep| (dep.name, dep.id))
-      .collect::<BTreeMap<String, String>>();
+/// Number of entries in each section of a lockfile.
+/// See [`LockfileContent::package_count`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LockfileStats {
+  pub remote: usize,
+  pub npm_packages: usize,
+  pub jsr_packages: usize,
+  pub specifiers: usize,
+  pub redirects: usize,
+}
 
-    let entry = self.content.packages.npm.entry(package_info.serialized_id);
-    let package_info = NpmPackageInfo {
-      integrity: package_info.integrity,
-      dependencies,
-    };
-    match entry {
-      Entry::Vacant(entry) => {
-        entry.insert(package_info);
-        self.has_content_changed = true;
-      }
-      Entry::Occupied(mut entry) => {
-        if *entry.get() != package_info {
-          entry.insert(package_info);
-          self.has_content_changed = true;
-        }
+impl std::fmt::Display for LockfileStats {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn plural(count: usize, word: &str) -> String {
+      if count == 1 {
+        format!("{} {}", count, word)
+      } else {
+        format!("{} {}s", count, word)
       }
     }
+
+    write!(
+      f,
+      "{}, {}, {}, {}, {}",
+      plural(self.remote, "remote module"),
+      plural(self.npm_packages, "npm package"),
+      plural(self.jsr_packages, "jsr package"),
+      plural(self.specifiers, "specifier"),
+      plural(self.redirects, "redirect"),
+    )
   }
+}
 
-  /// Inserts a package specifier into the lockfile.
-  pub fn insert_package_specifier(
-    &mut self,
-    serialized_package_req: String,
-    serialized_package_id: String,
-  ) {
-    let entry = self
-      .content
-      .packages
-      .specifiers
-      .entry(serialized_package_req);
-    match entry {
-      Entry::Vacant(entry) => {
-        entry.insert(serialized_package_id);
-        self.has_content_changed = true;
-      }
-      Entry::Occupied(mut entry) => {
-        if *entry.get() != serialized_package_id {
-          entry.insert(serialized_package_id);
-          self.has_content_changed = true;
-        }
-      }
+/// Normalization applied to package requirements when inserting
+/// package specifiers.
+///
+/// Everything is off by default so requirements are stored verbatim.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpecifierNormalization {
+  /// Lowercases the package name (ex. `npm:React@18` becomes `npm:react@18`).
+  pub lowercase_names: bool,
+  /// Trims the whitespace surrounding the requirement.
+  pub trim_whitespace: bool,
+}
+
+impl SpecifierNormalization {
+  fn normalize(&self, req: String) -> String {
+    let req = if self.trim_whitespace && req.trim() != req {
+      req.trim().to_string()
+    } else {
+      req
+    };
+    if !self.lowercase_names {
+      return req;
+    }
+    let Some((scheme, rest)) = req.split_once(':') else {
+      return req;
+    };
+    let name = npm_id_name(rest);
+    if name.chars().any(|c| c.is_uppercase()) {
+      format!("{}:{}{}", scheme, name.to_lowercase(), &rest[name.len()..])
+    } else {
+      req
     }
   }
+}
 
-  /// Inserts a JSR package into the lockfile replacing the existing package's integrity
-  /// if they differ.
+/// Options for the redirects recorded in the lockfile.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LockfileRedirectsOptions {
+  /// Records redirects from `jsr:` specifiers, which are otherwise
+  /// rejected because Deno tracks jsr versions in the package specifiers.
+  pub allow_jsr: bool,
+}
+
+/// Options for how the lockfile is written to the disk.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LockfileOutputOptions {
+  /// Writes the lockfile in this version instead of the current one, as
+  /// long as the content can be represented in it. This takes precedence
+  /// over `keep_original_version`.
+  pub version: Option<LockfileVersion>,
+  /// Writes the lockfile in the version it was loaded as
+  /// (see [`Lockfile::original_version`]) instead of upgrading it, as
+  /// long as the content can be represented in that version.
+  pub keep_original_version: bool,
+  /// Leaves out the `remote` section when it's empty, such as for
+  /// projects that only use jsr and npm packages. Lockfiles without it
+  /// have always been accepted when loading, so this is opt-in until
+  /// older readers that expect the section are no longer a concern.
   ///
-  /// WARNING: It is up to the caller to ensure checksums of packages are
-  /// valid before it is inserted here.
-  pub fn insert_package(&mut self, name: String, integrity: String) {
-    let entry = self.content.packages.jsr.entry(name);
-    match entry {
-      Entry::Vacant(entry) => {
-        entry.insert(JsrPackageInfo {
-          integrity,
-          dependencies: Default::default(),
-        });
-        self.has_content_changed = true;
-      }
-      Entry::Occupied(mut entry) => {
-        if *entry.get().integrity != integrity {
-          entry.get_mut().integrity = integrity;
-          self.has_content_changed = true;
-        }
-      }
+  /// This only applies when writing in the current version.
+  pub omit_empty_remote: bool,
+}
+
+/// The outcome of [`Lockfile::insert_redirect`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertRedirectResult {
+  Inserted,
+  Updated,
+  Unchanged,
+  /// The redirect was from a `jsr:` specifier and
+  /// [`LockfileRedirectsOptions::allow_jsr`] was not set.
+  RejectedJsrSpecifier,
+}
+
+/// Version of the lockfile format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LockfileVersion {
+  /// The original format without a `version` field.
+  V1,
+  V2,
+  /// The version lockfiles are written in.
+  V3,
+  /// The version written by newer versions of Deno, which isn't loaded
+  /// unless downgraded with [`transform4_to_3`].
+  V4,
+}
+
+impl std::fmt::Display for LockfileVersion {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      LockfileVersion::V1 => write!(f, "1"),
+      LockfileVersion::V2 => write!(f, "2"),
+      LockfileVersion::V3 => write!(f, "3"),
+      LockfileVersion::V4 => write!(f, "4"),
     }
   }
+}
 
-  /// Adds package dependencies of a JSR package. This is only used to track
-  /// when packages can be removed from the lockfile.
-  pub fn add_package_deps(
-    &mut self,
-    name: &str,
-    deps: impl Iterator<Item = String>,
-  ) {
-    if let Some(pkg) = self.content.packages.jsr.get_mut(name) {
-      let start_count = pkg.dependencies.len();
-      pkg.dependencies.extend(deps);
-      let end_count = pkg.dependencies.len();
-      if start_count != end_count {
-        self.has_content_changed = true;
-      }
-    }
+#[derive(Debug, Clone, Hash)]
+pub struct Lockfile {
+  pub overwrite: bool,
+  pub has_content_changed: bool,
+  pub content: LockfileContent,
+  pub filename: PathBuf,
+  pub specifier_normalization: SpecifierNormalization,
+  pub redirects_options: LockfileRedirectsOptions,
+  pub output_options: LockfileOutputOptions,
+  /// Makes [`Lockfile::try_insert_package_specifier`] error when the
+  /// package a specifier resolves to isn't in the lockfile.
+  ///
+  /// [`Lockfile::insert_package_specifier`] still inserts the specifier
+  /// since packages are often inserted after the specifiers, which
+  /// [`Lockfile::validate`] reports when they never are.
+  pub strict_specifiers: bool,
+  /// Content the lockfile was frozen with, which any change is undone to.
+  frozen: Option<Box<LockfileContent>>,
+  /// Whether a change was undone because the lockfile is frozen.
+  has_frozen_violation: bool,
+  /// Incremented each time the content changes.
+  generation: u64,
+  /// The text the lockfile was loaded from.
+  original_content: Option<String>,
+  /// Version the lockfile was in before being transformed on load.
+  original_version: LockfileVersion,
+}
+
+/// Creates an empty in-memory lockfile, which has an empty filename
+/// so [`Lockfile::set_filename`] needs to be called before writing it.
+impl Default for Lockfile {
+  fn default() -> Self {
+    Lockfile::new_empty(PathBuf::new(), false)
   }
+}
 
-  pub fn insert_redirect(&mut self, from: String, to: String) {
-    if from.starts_with("jsr:") {
-      return;
+impl Lockfile {
+  pub fn new_empty(filename: PathBuf, overwrite: bool) -> Lockfile {
+    Lockfile {
+      overwrite,
+      has_content_changed: false,
+      content: LockfileContent::empty(),
+      filename,
+      specifier_normalization: Default::default(),
+      redirects_options: Default::default(),
+      output_options: Default::default(),
+      strict_specifiers: false,
+      frozen: None,
+      has_frozen_violation: false,
+      generation: 0,
+      original_content: None,
+      original_version: LockfileVersion::V3,
     }
+  }
 
-    let entry = self.content.redirects.entry(from);
-    match entry {
-      Entry::Vacant(entry) => {
-        entry.insert(to);
-        self.has_content_changed = true;
-      }
-      Entry::Occupied(mut entry) => {
-        if *entry.get() != to {
-          entry.insert(to);
-          self.has_content_changed = true;
-        }
-      }
+  /// Same as [`Lockfile::with_lockfile_content`], but reads the content
+  /// from a reader, such as one that decompresses the lockfile.
+  ///
+  /// The reader is read to the end before parsing. It isn't read at all
+  /// when `overwrite` is `true`.
+  pub fn from_reader(
+    filename: PathBuf,
+    mut reader: impl std::io::Read,
+    overwrite: bool,
+  ) -> Result<Lockfile, Error> {
+    if overwrite {
+      return Ok(Lockfile::new_empty(filename, overwrite));
     }
+    let mut content = String::new();
+    if let Err(err) = reader.read_to_string(&mut content) {
+      return Err(Error::Io(filename.display().to_string(), err));
+    }
+    Lockfile::with_lockfile_content(filename, &content, overwrite)
   }
-}
 
-#[cfg(test)]
-mod tests {
-  use super::*;
-  use pretty_assertions::assert_eq;
+  /// Create a new [`Lockfile`] instance from given filename and its content.
+  pub fn with_lockfile_content(
+    filename: PathBuf,
+    content: &str,
+    overwrite: bool,
+  ) -> Result<Lockfile, Error> {
+    // Writing a lock file always uses the new format.
+    if overwrite {
+      return Ok(Lockfile::new_empty(filename, overwrite));
+    }
 
-  const LOCKFILE_JSON: &str = r#"
-{
+    let original_content = Some(content.to_string());
+    // editors on Windows may save the file with a byte order mark
+    let content = content.strip_prefix('\u{FEFF}').unwrap_or(content);
+    if content.trim().is_empty() {
+      return Err(Error::ReadError("Lockfile was empty.".to_string()));
+    }
+
+    let parse_error =
+      |err| Error::ParseError(filename.display().to_string(), err);
+    let duplicate_key =
+      duplicate_keys::find_duplicate_key(content).map_err(parse_error)?;
+    if let Some(duplicate) = duplicate_key {
+      return Err(Error::DuplicateKey {
+        filename: filename.display().to_string(),
+        path: duplicate.path,
+        key: duplicate.key,
+      });
+    }
+
+    let value: serde_json::Map<String, serde_json::Value> =
+      serde_json::from_str(content).map_err(parse_error)?;
+    let version = value.get("version").and_then(|v| v.as_str());
+    if let Some(version) = version {
+      if !matches!(version, "2" | "3") {
+        return Err(Error::UnsupportedVersion(version.to_string()));
+      }
+    }
+    let (value, original_version) = transforms::transform_to_current(value)?;
+    let content = serde_json::from_value::<LockfileContent>(value.into())
+      .map_err(parse_error)?;
+
+    Ok(Lockfile {
+      overwrite,
+      has_content_changed: false,
+      content,
+      filename,
+      specifier_normalization: Default::default(),
+      redirects_options: Default::default(),
+      output_options: Default::default(),
+      strict_specifiers: false,
+      frozen: None,
+      has_frozen_violation: false,
+      generation: 0,
+      original_content,
+      original_version,
+    })
+  }
+
+  /// Changes the path the lockfile is written to.
+  ///
+  /// This doesn't mark the content as changed, so set
+  /// `has_content_changed` when the content should be written to the
+  /// new path regardless.
+  pub fn set_filename(&mut self, filename: PathBuf) {
+    self.filename = filename;
+  }
+
+  /// Same as [`Lockfile::set_filename`], but consumes and returns the
+  /// lockfile.
+  pub fn with_filename(mut self, filename: PathBuf) -> Lockfile {
+    self.set_filename(filename);
+    self
+  }
+
+  /// Gets the text the lockfile was loaded from, which is `None` when
+  /// the lockfile was created empty.
+  pub fn original_text(&self) -> Option<&str> {
+    self.original_content.as_deref()
+  }
+
+  /// Gets the version the lockfile was loaded as before it was transformed
+  /// to the version it's written in. This is the written version when the
+  /// lockfile was created empty.
+  pub fn original_version(&self) -> LockfileVersion {
+    self.original_version
+  }
+
+  /// Serializes the content in the current version.
+  ///
+  /// This ignores `output_options`, so it differs from what's written to
+  /// the disk when they're set (see [`Lockfile::resolve_write_bytes`]).
+  ///
+  /// The keys are sorted and the formatting is canonical regardless of
+  /// how the loaded file was formatted, so this can be used to show how
+  /// a hand-edited lockfile would look. It doesn't depend on or change
+  /// `has_content_changed`.
+  ///
+  /// This prints the content as is regardless of its version and doesn't
+  /// panic, since the content only consists of maps with string keys.
+  pub fn as_json_string(&self) -> String {
+    let mut json_string = self.content.to_string();
+    json_string.push('\n'); // trailing newline in file
+    json_string
+  }
+
+  /// Same as [`Lockfile::as_json_string`], but in the provided version.
+  ///
+  /// Errors when the content has data that can't be represented in the
+  /// version, such as jsr packages in version 2. Version 4 can't be
+  /// written since lockfiles in it are transformed to version 3 on load.
+  pub fn to_json_with_version(
+    &self,
+    version: LockfileVersion,
+  ) -> Result<String, Error> {
+    #[derive(Serialize)]
+    struct Version2Npm<'a> {
+      specifiers: serde_json::Value,
+      packages: &'a BTreeMap<String, NpmPackageInfo>,
+    }
+
+    #[derive(Serialize)]
+    struct Version2Content<'a> {
+      version: serde_json::Value,
+      remote: serde_json::Value,
+      #[serde(skip_serializing_if = "Option::is_none")]
+      npm: Option<Version2Npm<'a>>,
+    }
+
+    let unsupported = |reason: String| {
+      Error::UnsupportedWriteVersion(version.to_string(), reason)
+    };
+    match version {
+      LockfileVersion::V3 => return Ok(self.as_json_string()),
+      LockfileVersion::V4 => {
+        return Err(unsupported(
+          "Only versions 1 to 3 can be written.".to_string(),
+        ))
+      }
+      LockfileVersion::V1 | LockfileVersion::V2 => {}
+    }
+    // the content is a struct, so it always serializes to an object
+    let serde_json::Value::Object(json) = self.content.to_value() else {
+      unreachable!();
+    };
+    let mut json = transforms::transform3_to_2(json).map_err(unsupported)?;
+    // the unwraps below are fine because the values only have string keys
+    let mut json_string = if version == LockfileVersion::V1 {
+      let json = transforms::transform2_to_1(json).map_err(unsupported)?;
+      serde_json::to_string_pretty(&json).unwrap()
+    } else {
+      // use the npm packages of the content to keep their field order
+      let npm = json.remove("npm").map(|mut npm| Version2Npm {
+        specifiers: npm["specifiers"].take(),
+        packages: &self.content.packages.npm,
+      });
+      serde_json::to_string_pretty(&Version2Content {
+        version: json.remove("version").unwrap_or_default(),
+        remote: json.remove("remote").unwrap_or_default(),
+        npm,
+      })
+      .unwrap()
+    };
+    json_string.push('\n'); // trailing newline in file
+    Ok(json_string)
+  }
+
+  /// Serializes the content as it should be written to the disk based
+  /// on `output_options`.
+  fn output_json_string(&self) -> String {
+    let options = &self.output_options;
+    let version = options.version.or(
+      options
+        .keep_original_version
+        .then_some(self.original_version),
+    );
+    if let Some(version) = version.filter(|v| *v != LockfileVersion::V3) {
+      if let Ok(text) = self.to_json_with_version(version) {
+        return text;
+      }
+    }
+    let json_string = self.as_json_string();
+    if options.omit_empty_remote && self.content.remote.is_empty() {
+      // the version always comes first and nested keys are indented
+      // further, so this only matches the top level section
+      return json_string.replacen(",\n  \"remote\": {}", "", 1);
+    }
+    json_string
+  }
+
+  /// Gets the package requirements the workspace would depend on after
+  /// calling [`Lockfile::set_workspace_config`] with the provided options,
+  /// without changing the lockfile.
+  ///
+  /// This includes the dependencies maintained from the lockfile because
+  /// of `no_config` and `no_npm`.
+  pub fn effective_workspace_deps(
+    &self,
+    options: &SetWorkspaceConfigOptions,
+  ) -> BTreeSet<String> {
+    let config = self.config_with_preserved_deps(
+      options.config.clone(),
+      options.no_npm,
+      options.no_config,
+    );
+    std::iter::once(&config.root)
+      .chain(config.members.values())
+      .flat_map(|member| {
+        member
+          .package_json_deps
+          .iter()
+          .chain(member.dependencies.iter())
+          .cloned()
+      })
+      .collect()
+  }
+
+  /// Fills in the dependencies that `no_npm` and `no_config` say to
+  /// maintain from the current workspace config.
+  fn config_with_preserved_deps(
+    &self,
+    mut config: WorkspaceConfig,
+    no_npm: bool,
+    no_config: bool,
+  ) -> WorkspaceConfig {
+    // if specified, don't modify the package.json dependencies
+    if no_npm || no_config {
+      if config.root.package_json_deps.is_empty() {
+        config.root.package_json_deps = self
+          .content
+          .workspace
+          .root
+          .package_json
+          .dependencies
+          .clone();
+      }
+      for (key, value) in config.members.iter_mut() {
+        if value.package_json_deps.is_empty() {
+          value.package_json_deps = self
+            .content
+            .workspace
+            .members
+            .get(key)
+            .map(|m| m.package_json.dependencies.clone())
+            .unwrap_or_default();
+        }
+      }
+    }
+    if no_config {
+      if config.root.dependencies.is_empty() {
+        config.root.dependencies =
+          self.content.workspace.root.dependencies.clone();
+      }
+      for (key, value) in config.members.iter_mut() {
+        if value.dependencies.is_empty() {
+          value.dependencies = self
+            .content
+            .workspace
+            .members
+            .get(key)
+            .map(|m| m.dependencies.clone())
+            .unwrap_or_default();
+        }
+      }
+      for (key, value) in self.content.workspace.members.iter() {
+        if config.members.get(key).is_none() {
+          config.members.insert(key.clone(), value.clone().into());
+        }
+      }
+    }
+    config
+  }
+
+  /// Sets the workspace config, removing the packages that are no longer
+  /// depended on.
+  pub fn set_workspace_config(
+    &mut self,
+    options: SetWorkspaceConfigOptions,
+  ) -> WorkspaceConfigChange {
+    fn update_workspace_member(
+      has_content_changed: &mut bool,
+      removed_deps: &mut HashSet<String>,
+      current: &mut WorkspaceMemberConfigContent,
+      new: WorkspaceMemberConfig,
+    ) {
+      if new.dependencies != current.dependencies {
+        let old_deps =
+          std::mem::replace(&mut current.dependencies, new.dependencies);
+
+        removed_deps.extend(old_deps);
+
+        *has_content_changed = true;
+      }
+
+      if new.package_json_deps != current.package_json.dependencies {
+        // update self.content.package_json
+        let old_package_json_deps = std::mem::replace(
+          &mut current.package_json.dependencies,
+          new.package_json_deps,
+        );
+
+        removed_deps.extend(old_package_json_deps);
+
+        *has_content_changed = true;
+      }
+    }
+
+    let config = self.config_with_preserved_deps(
+      options.config,
+      options.no_npm,
+      options.no_config,
+    );
+
+    // If the lockfile is empty, it's most likely not created yet and so
+    // we don't want this information being added to the lockfile to cause
+    // a lockfile to be created. If this is the case, revert the lockfile back
+    // to !self.has_content_changed after populating it with this information
+    let allow_content_changed =
+      self.has_content_changed || !self.content.is_empty();
+    let mut has_changed = false;
+    let old_deps = self
+      .content
+      .workspace
+      .get_all_dep_reqs()
+      .map(|s| s.to_string())
+      .collect::<HashSet<_>>();
+    let mut removed_deps = HashSet::new();
+
+    // set the root
+    update_workspace_member(
+      &mut has_changed,
+      &mut removed_deps,
+      &mut self.content.workspace.root,
+      config.root,
+    );
+
+    // now go through the workspaces
+    let mut unhandled_members = self
+      .content
+      .workspace
+      .members
+      .keys()
+      .cloned()
+      .collect::<HashSet<_>>();
+    for (member_name, new_member) in config.members {
+      unhandled_members.remove(&member_name);
+      let current_member = self
+        .content
+        .workspace
+        .members
+        .entry(member_name)
+        .or_default();
+      update_workspace_member(
+        &mut has_changed,
+        &mut removed_deps,
+        current_member,
+        new_member,
+      );
+    }
+
+    for member in unhandled_members {
+      if let Some(member) = self.content.workspace.members.remove(&member) {
+        removed_deps.extend(member.dep_reqs().cloned());
+        has_changed = true;
+      }
+    }
+
+    let change = self.prune_removed_deps(&old_deps, removed_deps);
+
+    // when not allowed, don't mark it as changed so this change
+    // doesn't by itself cause a lockfile to be created.
+    if has_changed && allow_content_changed {
+      self.mark_content_changed();
+    }
+    change
+  }
+
+  /// Removes the workspace config along with the packages that are no
+  /// longer depended on.
+  pub fn clear_workspace(&mut self) {
+    self.set_workspace_config(SetWorkspaceConfigOptions {
+      config: Default::default(),
+      no_config: false,
+      no_npm: false,
+    });
+  }
+
+  /// Adds a dependency to a member of the workspace or to the root when
+  /// `member` is `None`, creating the member when it doesn't exist.
+  ///
+  /// Returns if the dependency was added.
+  pub fn add_workspace_dependency(
+    &mut self,
+    member: Option<&str>,
+    dep_req: String,
+    from_package_json: bool,
+  ) -> bool {
+    // same as set_workspace_config, don't cause an empty lockfile to
+    // be created only because of the workspace information
+    let allow_content_changed =
+      self.has_content_changed || !self.content.is_empty();
+    let workspace = &mut self.content.workspace;
+    let current = match member {
+      Some(name) => workspace.members.entry(name.to_string()).or_default(),
+      None => &mut workspace.root,
+    };
+    let deps = if from_package_json {
+      &mut current.package_json.dependencies
+    } else {
+      &mut current.dependencies
+    };
+    if !deps.insert(dep_req) {
+      return false;
+    }
+    if allow_content_changed {
+      self.mark_content_changed();
+    }
+    true
+  }
+
+  /// Moves a dependency from one part of the workspace to another, keeping
+  /// whether it came from a package.json. The destination member is created
+  /// when it doesn't exist.
+  ///
+  /// Packages aren't pruned because the dependency is still in the
+  /// workspace.
+  pub fn move_workspace_dependency(
+    &mut self,
+    dep: &str,
+    from: WorkspaceMemberRef,
+    to: WorkspaceMemberRef,
+  ) -> Result<(), MoveError> {
+    let workspace = &mut self.content.workspace;
+    let source = match from {
+      WorkspaceMemberRef::Root => Some(&mut workspace.root),
+      WorkspaceMemberRef::Member(name) => workspace.members.get_mut(name),
+    };
+    let (source_deps, from_package_json) = match source {
+      Some(source) if source.package_json.dependencies.contains(dep) => {
+        (&mut source.package_json.dependencies, true)
+      }
+      Some(source) if source.dependencies.contains(dep) => {
+        (&mut source.dependencies, false)
+      }
+      _ => {
+        return Err(MoveError {
+          dep: dep.to_string(),
+          member: from.to_string(),
+        })
+      }
+    };
+    if from == to {
+      return Ok(());
+    }
+    source_deps.remove(dep);
+    let target = match to {
+      WorkspaceMemberRef::Root => &mut workspace.root,
+      WorkspaceMemberRef::Member(name) => {
+        workspace.members.entry(name.to_string()).or_default()
+      }
+    };
+    if from_package_json {
+      target.package_json.dependencies.insert(dep.to_string());
+    } else {
+      target.dependencies.insert(dep.to_string());
+    }
+    self.mark_content_changed();
+    Ok(())
+  }
+
+  /// Removes a single member from the workspace along with the packages
+  /// that only it depended on.
+  ///
+  /// Returns if the member existed.
+  pub fn remove_workspace_member(&mut self, name: &str) -> bool {
+    // same as set_workspace_config, don't cause an empty lockfile to
+    // be created only because of the workspace information
+    let allow_content_changed =
+      self.has_content_changed || !self.content.is_empty();
+    let old_deps = self
+      .content
+      .workspace
+      .get_all_dep_reqs()
+      .map(|s| s.to_string())
+      .collect::<HashSet<_>>();
+    let Some(member) = self.content.workspace.members.remove(name) else {
+      return false;
+    };
+    let removed_deps = member.dep_reqs().cloned().collect::<HashSet<_>>();
+    self.prune_removed_deps(&old_deps, removed_deps);
+    if allow_content_changed {
+      self.mark_content_changed();
+    }
+    true
+  }
+
+  /// Removes the packages only reachable from the removed workspace
+  /// dependencies, keeping those still found in the workspace.
+  fn prune_removed_deps(
+    &mut self,
+    old_deps: &HashSet<String>,
+    mut removed_deps: HashSet<String>,
+  ) -> WorkspaceConfigChange {
+    for dep in self.content.workspace.get_all_dep_reqs() {
+      removed_deps.remove(dep);
+    }
+
+    if removed_deps.is_empty() {
+      return WorkspaceConfigChange::default();
+    }
+
+    let mut change = WorkspaceConfigChange {
+      removed_reqs: removed_deps.iter().cloned().collect(),
+      ..Default::default()
+    };
+
+    let packages = std::mem::take(&mut self.content.packages);
+    let old_packages = packages.clone();
+    let remotes = std::mem::take(&mut self.content.remote);
+
+    // create the graph
+    let mut graph = LockfilePackageGraph::from_lockfile(
+      packages,
+      remotes,
+      old_deps.iter().map(|dep| dep.as_str()),
+    );
+
+    // remove the packages, but always keep the patched ones
+    graph.remove_root_packages_keeping(
+      removed_deps.into_iter(),
+      self.content.patches.keys().map(|id| id.as_str()),
+    );
+
+    // now populate the graph back into the packages
+    graph
+      .populate_packages(&mut self.content.packages, &mut self.content.remote);
+
+    let remaining = &self.content.packages;
+    change.npm_packages = old_packages
+      .npm
+      .into_keys()
+      .filter(|id| !remaining.npm.contains_key(id))
+      .collect();
+    change.jsr_packages = old_packages
+      .jsr
+      .into_keys()
+      .filter(|nv| !remaining.jsr.contains_key(nv))
+      .collect();
+    change.specifiers = old_packages
+      .specifiers
+      .into_keys()
+      .filter(|req| !remaining.specifiers.contains_key(req))
+      .collect();
+    change
+  }
+
+  /// Stops the content from changing, such as when the lockfile is
+  /// expected to be up to date on CI.
+  ///
+  /// The `try_` methods (ex. [`Lockfile::try_insert_remote`]) error instead
+  /// of changing the content. Any other change is undone and recorded,
+  /// which [`Lockfile::has_frozen_violation`] reports.
+  pub fn freeze(&mut self) {
+    self.frozen = Some(Box::new(self.content.clone()));
+  }
+
+  pub fn is_frozen(&self) -> bool {
+    self.frozen.is_some()
+  }
+
+  /// Gets if a change was undone because the lockfile is frozen, which
+  /// means the lockfile is out of date.
+  pub fn has_frozen_violation(&self) -> bool {
+    self.has_frozen_violation
+  }
+
+  fn check_frozen(
+    &self,
+    would_change: bool,
+    entry: &str,
+  ) -> Result<(), FrozenError> {
+    if self.frozen.is_some() && would_change {
+      Err(FrozenError {
+        entry: entry.to_string(),
+      })
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Same as [`Lockfile::set_workspace_config`], but errors instead when
+  /// the lockfile is frozen and the config would change it.
+  pub fn try_set_workspace_config(
+    &mut self,
+    options: SetWorkspaceConfigOptions,
+  ) -> Result<WorkspaceConfigChange, FrozenError> {
+    if self.frozen.is_none() {
+      return Ok(self.set_workspace_config(options));
+    }
+    let mut lockfile = self.clone();
+    lockfile.frozen = None;
+    let change = lockfile.set_workspace_config(options);
+    self.check_frozen(
+      lockfile.content.to_value() != self.content.to_value(),
+      "workspace",
+    )?;
+    Ok(change)
+  }
+
+  fn mark_content_changed(&mut self) {
+    if let Some(content) = &self.frozen {
+      self.content = (**content).clone();
+      self.has_frozen_violation = true;
+      return;
+    }
+    self.has_content_changed = true;
+    self.generation += 1;
+  }
+
+  /// Gets the current generation of the content, which is
+  /// incremented each time the content changes.
+  pub fn generation(&self) -> u64 {
+    self.generation
+  }
+
+  /// Gets the bytes that should be written to the disk along with the
+  /// current generation when the content has changed since the provided
+  /// generation of the last write.
+  ///
+  /// This resets `has_content_changed` when returning bytes. Unlike
+  /// `resolve_write_bytes`, this doesn't consider `overwrite`.
+  pub fn resolve_write_bytes_if_newer(
+    &mut self,
+    last_written: u64,
+  ) -> Option<(Vec<u8>, u64)> {
+    if self.generation <= last_written {
+      return None;
+    }
+
+    self.has_content_changed = false;
+    Some((self.output_json_string().into_bytes(), self.generation))
+  }
+
+  /// Gets if `resolve_write_bytes` would provide bytes to write
+  /// without serializing the content.
+  pub fn would_write(&self) -> bool {
+    self.has_content_changed || self.overwrite
+  }
+
+  /// Splits the lockfile into self-contained content for each member of
+  /// the provided workspace, which only has the packages the member
+  /// depends on. The member's config file and package.json dependencies
+  /// become the workspace dependencies of its content.
+  ///
+  /// The remotes and redirects are limited to the modules of the jsr
+  /// packages the member depends on. The dependencies of the workspace
+  /// root aren't included in any member.
+  pub fn split_by_member(
+    &self,
+    workspace: &WorkspaceConfig,
+  ) -> BTreeMap<String, LockfileContent> {
+    workspace
+      .members
+      .iter()
+      .map(|(name, member)| {
+        let reqs = member
+          .dependencies
+          .iter()
+          .chain(member.package_json_deps.iter())
+          .map(|req| req.as_str());
+        let mut content = self.content.subtree_for_roots(reqs);
+        content.workspace.root = WorkspaceMemberConfigContent {
+          dependencies: member.dependencies.clone(),
+          package_json: LockfilePackageJsonContent {
+            dependencies: member.package_json_deps.clone(),
+          },
+        };
+        (name.clone(), content)
+      })
+      .collect()
+  }
+
+  /// Creates content with only the packages and specifiers transitively
+  /// reachable from the provided root package requirements
+  /// (ex. `npm:chalk@5`), without changing the lockfile. The roots become
+  /// the workspace dependencies of the content.
+  ///
+  /// The remotes and redirects are limited to the modules of the jsr
+  /// packages in the subset, the same as [`Lockfile::split_by_member`].
+  pub fn subset<'a>(
+    &self,
+    roots: impl Iterator<Item = &'a str>,
+  ) -> LockfileContent {
+    self.content.subtree_for_roots(roots)
+  }
+
+  /// Gets what would be removed from the packages if the provided root
+  /// package requirements were no longer depended on by the workspace,
+  /// without changing the lockfile.
+  ///
+  /// Remotes aren't included because they're not tracked in the
+  /// package graph and so are never removed by it.
+  pub fn simulate_remove_roots<'a>(
+    &self,
+    roots: impl Iterator<Item = &'a str>,
+  ) -> RemovalPreview {
+    let mut graph = LockfilePackageGraph::from_lockfile(
+      self.content.packages.clone(),
+      Default::default(),
+      self
+        .content
+        .workspace
+        .get_all_dep_reqs()
+        .map(|req| req.as_str()),
+    );
+    graph.remove_root_packages_keeping(
+      roots.map(|root| root.to_string()),
+      self.content.patches.keys().map(|id| id.as_str()),
+    );
+    let mut remaining = PackagesContent::default();
+    graph.populate_packages(&mut remaining, &mut Default::default());
+
+    let packages = &self.content.packages;
+    RemovalPreview {
+      npm_packages: packages
+        .npm
+        .keys()
+        .filter(|id| !remaining.npm.contains_key(*id))
+        .cloned()
+        .collect(),
+      jsr_packages: packages
+        .jsr
+        .keys()
+        .filter(|nv| !remaining.jsr.contains_key(*nv))
+        .cloned()
+        .collect(),
+      specifiers: packages
+        .specifiers
+        .keys()
+        .filter(|req| !remaining.specifiers.contains_key(*req))
+        .cloned()
+        .collect(),
+    }
+  }
+
+  /// Removes a root package requirement (ex. `npm:chalk@5`) from the
+  /// specifiers along with the packages that are no longer reachable
+  /// from the remaining roots.
+  ///
+  /// Packages still used by other roots are kept, as is the specifier
+  /// when a remaining jsr package depends on the requirement.
+  pub fn remove_package_and_orphans(&mut self, req: &str) -> RemovedPackages {
+    let mut removed = self.simulate_remove_roots(std::iter::once(req));
+    let packages = &self.content.packages;
+    let is_jsr_dependency = packages.jsr.iter().any(|(nv, package)| {
+      !removed.jsr_packages.contains(nv) && package.dependencies.contains(req)
+    });
+    if packages.specifiers.contains_key(req) && !is_jsr_dependency {
+      removed.specifiers.insert(req.to_string());
+    }
+
+    let packages = &mut self.content.packages;
+    packages
+      .specifiers
+      .retain(|req, _| !removed.specifiers.contains(req));
+    packages
+      .jsr
+      .retain(|nv, _| !removed.jsr_packages.contains(nv));
+    packages
+      .npm
+      .retain(|id, _| !removed.npm_packages.contains(id));
+    if removed != RemovedPackages::default() {
+      self.mark_content_changed();
+    }
+    removed
+  }
+
+  /// Removes the packages that can't be reached from the workspace
+  /// dependencies along with the specifiers nothing uses anymore.
+  ///
+  /// When the lockfile has no workspace config, every specifier is a root
+  /// so only the packages they don't reference are removed.
+  pub fn prune(&mut self, options: PruneOptions) -> PruneReport {
+    let unreachable = self.content.unreachable_packages();
+    let has_workspace = !self.content.workspace.is_empty();
+    let workspace_reqs = self
+      .content
+      .workspace
+      .get_all_dep_reqs()
+      .cloned()
+      .collect::<HashSet<_>>();
+    let mut report = PruneReport::default();
+
+    let packages = &mut self.content.packages;
+    packages.npm.retain(|id, _| {
+      let keep = !unreachable.contains(&format!("npm:{}", id));
+      if !keep {
+        report.npm_packages.insert(id.clone());
+      }
+      keep
+    });
+    packages.jsr.retain(|nv, _| {
+      let keep = !unreachable.contains(&format!("jsr:{}", nv));
+      if !keep {
+        report.jsr_packages.insert(nv.clone());
+      }
+      keep
+    });
+    if has_workspace {
+      let jsr_reqs = packages
+        .jsr
+        .values()
+        .flat_map(|package| package.dependencies.iter().cloned())
+        .collect::<HashSet<_>>();
+      packages.specifiers.retain(|req, _| {
+        let keep = workspace_reqs.contains(req) || jsr_reqs.contains(req);
+        if !keep {
+          report.specifiers.insert(req.clone());
+        }
+        keep
+      });
+    }
+    if let Some(allowlist) = &options.remote_allowlist {
+      self.content.remote.retain(|url, _| {
+        let keep = allowlist.contains(url);
+        if !keep {
+          report.remote.insert(url.clone());
+        }
+        keep
+      });
+    }
+    if let Some(allowlist) = &options.redirects_allowlist {
+      self.content.redirects.retain(|from, _| {
+        let keep = allowlist.contains(from);
+        if !keep {
+          report.redirects.insert(from.clone());
+        }
+        keep
+      });
+    }
+
+    if report != PruneReport::default() {
+      self.mark_content_changed();
+    }
+    report
+  }
+
+  /// Removes the entries of a fragment created by
+  /// [`LockfileContent::subtract`] from the lockfile.
+  ///
+  /// Only entries that still have the same value are removed, while the
+  /// mismatched ones are reported. Entries that are no longer in the
+  /// lockfile are ignored.
+  pub fn remove_fragment(
+    &mut self,
+    fragment: &LockfileContent,
+  ) -> FragmentRemovalReport {
+    let mut report = FragmentRemovalReport::default();
+    let content = &mut self.content;
+    let removed = [
+      remove_matching(
+        &mut content.packages.specifiers,
+        &fragment.packages.specifiers,
+        &mut report.specifiers,
+      ),
+      remove_matching(
+        &mut content.packages.jsr,
+        &fragment.packages.jsr,
+        &mut report.jsr_packages,
+      ),
+      remove_matching(
+        &mut content.packages.npm,
+        &fragment.packages.npm,
+        &mut report.npm_packages,
+      ),
+      remove_matching(
+        &mut content.redirects,
+        &fragment.redirects,
+        &mut report.redirects,
+      ),
+      remove_matching(
+        &mut content.remote,
+        &fragment.remote,
+        &mut report.remote,
+      ),
+      remove_matching(
+        &mut content.patches,
+        &fragment.patches,
+        &mut report.patches,
+      ),
+      remove_matching(
+        &mut content.comments,
+        &fragment.comments,
+        &mut report.comments,
+      ),
+    ];
+    if removed.contains(&true) {
+      self.mark_content_changed();
+    }
+    report
+  }
+
+  /// Gets the bytes that should be written to the disk.
+  ///
+  /// Ideally when the caller should use an "atomic write"
+  /// when writing this—write to a temporary file beside the
+  /// lockfile, then rename to overwrite. This will make the
+  /// lockfile more resilient when multiple processes are
+  /// writing to it.
+  pub fn resolve_write_bytes(&self) -> Option<Vec<u8>> {
+    if !self.would_write() {
+      return None;
+    }
+
+    Some(self.output_json_string().into_bytes())
+  }
+
+  /// Normalizes the content so it has a stable form on disk. This trims
+  /// whitespace in keys, lowercases hex integrities and integrity
+  /// algorithm names, and removes specifier entries made redundant by
+  /// normalizing their requirement with `specifier_normalization`. The
+  /// workspace dependencies of the root and members are normalized the
+  /// same way so they keep matching the specifiers.
+  ///
+  /// Returns if anything changed.
+  pub fn canonicalize(&mut self) -> bool {
+    fn rekey<V>(
+      map: &mut BTreeMap<String, V>,
+      canonical_key: impl Fn(String) -> String,
+    ) -> bool {
+      if map.keys().all(|key| canonical_key(key.clone()) == *key) {
+        return false;
+      }
+      for (key, value) in std::mem::take(map) {
+        let new_key = canonical_key(key.clone());
+        if new_key == key {
+          // prefer the entry that was already canonical
+          map.insert(new_key, value);
+        } else {
+          map.entry(new_key).or_insert(value);
+        }
+      }
+      true
+    }
+
+    fn canonicalize_integrity(integrity: &mut String) -> bool {
+      let new_integrity = canonical_integrity(integrity);
+      if new_integrity == *integrity {
+        false
+      } else {
+        *integrity = new_integrity;
+        true
+      }
+    }
+
+    fn trim(value: String) -> String {
+      if value.trim() == value {
+        value
+      } else {
+        value.trim().to_string()
+      }
+    }
+
+    fn canonicalize_reqs(
+      reqs: &mut BTreeSet<String>,
+      canonical_req: impl Fn(String) -> String,
+    ) -> bool {
+      let new_reqs = reqs
+        .iter()
+        .map(|req| canonical_req(req.clone()))
+        .collect::<BTreeSet<_>>();
+      if new_reqs == *reqs {
+        false
+      } else {
+        *reqs = new_reqs;
+        true
+      }
+    }
+
+    let normalization = self.specifier_normalization;
+    let canonical_req = |req: String| normalization.normalize(trim(req));
+    let mut changed = false;
+    let packages = &mut self.content.packages;
+    changed |= rekey(&mut packages.specifiers, canonical_req);
+    for id in packages.specifiers.values_mut() {
+      if id.trim() != id {
+        *id = trim(std::mem::take(id));
+        changed = true;
+      }
+    }
+    changed |= rekey(&mut packages.jsr, trim);
+    for package in packages.jsr.values_mut() {
+      changed |= canonicalize_integrity(&mut package.integrity);
+      changed |= canonicalize_reqs(&mut package.dependencies, canonical_req);
+    }
+    changed |= rekey(&mut packages.npm, trim);
+    for package in packages.npm.values_mut() {
+      changed |= canonicalize_integrity(&mut package.integrity);
+      for deps in [
+        &mut package.dependencies,
+        &mut package.optional_dependencies,
+        &mut package.peer_dependencies,
+      ] {
+        changed |= rekey(deps, trim);
+        for id in deps.values_mut() {
+          if id.trim() != id {
+            *id = trim(std::mem::take(id));
+            changed = true;
+          }
+        }
+      }
+    }
+    let workspace = &mut self.content.workspace;
+    let members = std::iter::once(&mut workspace.root)
+      .chain(workspace.members.values_mut());
+    for member in members {
+      changed |= canonicalize_reqs(&mut member.dependencies, canonical_req);
+      changed |=
+        canonicalize_reqs(&mut member.package_json.dependencies, canonical_req);
+    }
+    changed |= rekey(&mut self.content.redirects, trim);
+    changed |= rekey(&mut self.content.remote, trim);
+    for checksum in self.content.remote.values_mut() {
+      changed |= canonicalize_integrity(checksum);
+    }
+
+    if changed {
+      self.mark_content_changed();
+    }
+    changed
+  }
+
+  /// Gets the packages in the order they should be installed.
+  ///
+  /// See [`LockfilePackageGraph::topological_order`].
+  pub fn topological_order(&self) -> Result<Vec<String>, CycleError> {
+    LockfilePackageGraph::from_content(&self.content).topological_order()
+  }
+
+  /// Outputs the package graph of the lockfile in the Graphviz DOT format.
+  pub fn to_dot(&self, options: DotOptions) -> String {
+    graphs::to_dot(&self.content, &options)
+  }
+
+  /// Outputs the package graph of the lockfile as a Mermaid flowchart.
+  pub fn to_mermaid(&self, options: MermaidOptions) -> String {
+    graphs::to_mermaid(&self.content, &options)
+  }
+
+  /// Outputs the package graph of the lockfile as JSON adjacency lists.
+  ///
+  /// The output has the shape `{ "roots": [..], "nodes": { .. }, "missing": [..] }`
+  /// where each node is keyed by its package id (ex. `npm:chalk@5.0.0`) and
+  /// the `"deps"` of a node have the specifiers already resolved. References
+  /// to packages that don't exist in the lockfile are only found in `"missing"`.
+  pub fn graph_json(&self) -> serde_json::Value {
+    graphs::to_graph_json(&self.content)
+  }
+
+  /// Writes the lockfile to `filename` when `resolve_write_bytes` provides
+  /// bytes to write, returning if it was written.
+  ///
+  /// The bytes are written and synced to a temporary file beside the
+  /// lockfile, which is then renamed over the lockfile so a crash never
+  /// leaves a partially written lockfile. The permissions of an existing
+  /// lockfile are maintained.
+  ///
+  /// Once written, [`Lockfile::restore`] compares with the written content
+  /// instead of the content the lockfile was loaded from.
+  pub fn write_atomic(&mut self) -> std::io::Result<bool> {
+    let Some(bytes) = self.resolve_write_bytes() else {
+      return Ok(false);
+    };
+    let file_name = self
+      .filename
+      .file_name()
+      .map(|name| name.to_string_lossy().to_string())
+      .unwrap_or_else(|| "deno.lock".to_string());
+    // unique per write so concurrent writes don't share a temporary file
+    static WRITE_COUNT: AtomicUsize = AtomicUsize::new(0);
+    let temp_path = self.filename.with_file_name(format!(
+      ".{}.{}.{}.tmp",
+      file_name,
+      std::process::id(),
+      WRITE_COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = write_synced(&temp_path, &bytes, &self.filename)
+      .and_then(|_| std::fs::rename(&temp_path, &self.filename));
+    if let Err(err) = result {
+      let _ = std::fs::remove_file(&temp_path);
+      return Err(err);
+    }
+    #[cfg(unix)]
+    if let Some(dir) = self.filename.parent() {
+      // sync the directory so the rename is persisted
+      let dir = if dir.as_os_str().is_empty() {
+        std::path::Path::new(".")
+      } else {
+        dir
+      };
+      if let Ok(dir) = std::fs::File::open(dir) {
+        let _ = dir.sync_all();
+      }
+    }
+
+    self.has_content_changed = false;
+    // the written text is what the content is now compared against
+    self.original_content = String::from_utf8(bytes).ok();
+    Ok(true)
+  }
+
+  /// Removes all the content from the lockfile.
+  ///
+  /// The filename and original content are kept so the lockfile will
+  /// still be written when the repopulated content differs.
+  pub fn clear(&mut self) {
+    if !self.content.is_empty() {
+      self.content = LockfileContent::empty();
+      self.mark_content_changed();
+    }
+  }
+
+  /// Removes the package specifiers, jsr packages, and npm packages
+  /// from the lockfile while keeping the remote modules, redirects, and
+  /// workspace config.
+  pub fn clear_packages(&mut self) {
+    if !self.content.packages.is_empty() {
+      self.content.packages = Default::default();
+      self.mark_content_changed();
+    }
+  }
+
+  /// Removes the npm packages from the lockfile.
+  pub fn clear_npm(&mut self) {
+    if !self.content.packages.npm.is_empty() {
+      self.content.packages.npm.clear();
+      self.mark_content_changed();
+    }
+  }
+
+  /// Removes the jsr packages from the lockfile.
+  pub fn clear_jsr(&mut self) {
+    if !self.content.packages.jsr.is_empty() {
+      self.content.packages.jsr.clear();
+      self.mark_content_changed();
+    }
+  }
+
+  /// Removes the package specifiers from the lockfile.
+  pub fn clear_specifiers(&mut self) {
+    if !self.content.packages.specifiers.is_empty() {
+      self.content.packages.specifiers.clear();
+      self.mark_content_changed();
+    }
+  }
+
+  /// Removes the remote modules from the lockfile returning the
+  /// number removed.
+  pub fn clear_remote(&mut self) -> usize {
+    let removed = self.content.remote.len();
+    if removed > 0 {
+      self.content.remote.clear();
+      self.mark_content_changed();
+    }
+    removed
+  }
+
+  /// Removes the redirects from the lockfile.
+  pub fn clear_redirects(&mut self) {
+    if !self.content.redirects.is_empty() {
+      self.content.redirects.clear();
+      self.mark_content_changed();
+    }
+  }
+
+  pub fn remote(&self) -> &BTreeMap<String, String> {
+    &self.content.remote
+  }
+
+  /// Gets the package requirements of the workspace root and its members
+  /// (ex. `npm:chalk@5`), including those from the package.json files.
+  pub fn workspace_dep_reqs(&self) -> impl Iterator<Item = &str> {
+    self
+      .content
+      .workspace
+      .get_all_dep_reqs()
+      .map(|req| req.as_str())
+  }
+
+  /// Gets if the npm package with the provided id (ex. `chalk@5.0.0`)
+  /// is in the lockfile.
+  pub fn has_npm_package(&self, id: &str) -> bool {
+    self.content.packages.npm.contains_key(id)
+  }
+
+  /// Gets if the jsr package with the provided name and version
+  /// (ex. `@std/path@0.75.0`) is in the lockfile.
+  pub fn has_jsr_package(&self, name: &str) -> bool {
+    self.content.packages.jsr.contains_key(name)
+  }
+
+  /// Gets the package requirements (ex. `jsr:@std/assert@^0.75`) the jsr
+  /// package with the provided name and version depends on, which can be
+  /// resolved with [`Lockfile::resolve_specifier`].
+  pub fn jsr_package_deps(
+    &self,
+    name: &str,
+  ) -> Option<impl Iterator<Item = &str>> {
+    let package = self.content.packages.jsr.get(name)?;
+    Some(package.dependencies.iter().map(|req| req.as_str()))
+  }
+
+  /// Gets the package id a package requirement resolves to
+  /// (ex. `npm:chalk@5.0.0` for `npm:chalk@^5`).
+  pub fn resolve_specifier(&self, req: &str) -> Option<&str> {
+    self
+      .content
+      .packages
+      .specifiers
+      .get(req)
+      .map(|id| id.as_str())
+  }
+
+  /// Gets the information of the package a package requirement
+  /// resolves to.
+  pub fn resolve_to_package(&self, req: &str) -> Option<PackageInfoRef> {
+    let id = self.resolve_specifier(req)?;
+    let packages = &self.content.packages;
+    if let Some(id) = id.strip_prefix("npm:") {
+      packages.npm.get(id).map(PackageInfoRef::Npm)
+    } else if let Some(nv) = id.strip_prefix("jsr:") {
+      packages.jsr.get(nv).map(PackageInfoRef::Jsr)
+    } else {
+      None
+    }
+  }
+
+  /// Inserts a remote specifier into the lockfile replacing the existing package if it exists.
+  ///
+  /// WARNING: It is up to the caller to ensure checksums of remote modules are
+  /// valid before it is inserted here. See [`compute_remote_checksum`].
+  pub fn insert_remote(&mut self, specifier: String, hash: String) {
+    let entry = self.content.remote.entry(specifier);
+    match entry {
+      Entry::Vacant(entry) => {
+        entry.insert(hash);
+        self.mark_content_changed();
+      }
+      Entry::Occupied(mut entry) => {
+        if entry.get() != &hash {
+          entry.insert(hash);
+          self.mark_content_changed();
+        }
+      }
+    }
+  }
+
+  /// Same as [`Lockfile::insert_remote`], but errors instead when the
+  /// lockfile is frozen and the entry would change it.
+  pub fn try_insert_remote(
+    &mut self,
+    specifier: String,
+    hash: String,
+  ) -> Result<(), FrozenError> {
+    self.check_frozen(
+      self.content.remote.get(&specifier) != Some(&hash),
+      &specifier,
+    )?;
+    self.insert_remote(specifier, hash);
+    Ok(())
+  }
+
+  /// Inserts many remote specifiers into the lockfile replacing the
+  /// checksums of existing entries, returning the number of entries that
+  /// were inserted or modified.
+  ///
+  /// WARNING: It is up to the caller to ensure checksums of remote modules are
+  /// valid before they are inserted here.
+  pub fn extend_remote(
+    &mut self,
+    entries: impl IntoIterator<Item = (String, String)>,
+  ) -> usize {
+    let mut changed = 0;
+    for (specifier, hash) in entries {
+      match self.content.remote.entry(specifier) {
+        Entry::Vacant(entry) => {
+          entry.insert(hash);
+          changed += 1;
+        }
+        Entry::Occupied(mut entry) => {
+          if entry.get() != &hash {
+            entry.insert(hash);
+            changed += 1;
+          }
+        }
+      }
+    }
+    if changed > 0 {
+      self.mark_content_changed();
+    }
+    changed
+  }
+
+  /// Replaces the remote modules with the provided ones, which removes
+  /// the modules that aren't provided. Returns the replaced modules, such
+  /// as to report which checksums changed after verifying every module
+  /// again.
+  ///
+  /// The content is only flagged as changed when the modules differ.
+  ///
+  /// WARNING: It is up to the caller to ensure checksums of remote modules are
+  /// valid before they are inserted here.
+  pub fn set_remote(
+    &mut self,
+    remote: BTreeMap<String, String>,
+  ) -> BTreeMap<String, String> {
+    if self.content.remote == remote {
+      return remote;
+    }
+    let previous = std::mem::replace(&mut self.content.remote, remote);
+    self.mark_content_changed();
+    previous
+  }
+
+  /// Removes a remote specifier from the lockfile returning
+  /// its checksum if it existed.
+  pub fn remove_remote(&mut self, specifier: &str) -> Option<String> {
+    let checksum = self.content.remote.remove(specifier);
+    if checksum.is_some() {
+      self.mark_content_changed();
+    }
+    checksum
+  }
+
+  /// Removes the remote URLs starting with the provided prefix,
+  /// returning the number removed.
+  pub fn remove_remote_prefix(&mut self, prefix: &str) -> usize {
+    let urls = self
+      .content
+      .remote
+      .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+      .map(|(url, _)| url)
+      .take_while(|url| url.starts_with(prefix))
+      .cloned()
+      .collect::<Vec<_>>();
+    for url in &urls {
+      self.content.remote.remove(url);
+    }
+    if !urls.is_empty() {
+      self.mark_content_changed();
+    }
+    urls.len()
+  }
+
+  /// Removes the remote entries the predicate returns `false` for,
+  /// returning the number removed.
+  ///
+  /// The predicate receives the URL and its checksum.
+  pub fn retain_remote(
+    &mut self,
+    mut keep: impl FnMut(&str, &str) -> bool,
+  ) -> usize {
+    let len = self.content.remote.len();
+    self
+      .content
+      .remote
+      .retain(|url, checksum| keep(url, checksum));
+    let removed = len - self.content.remote.len();
+    if removed > 0 {
+      self.mark_content_changed();
+    }
+    removed
+  }
+
+  /// Removes the remote URLs with a trailing slash that are also in the
+  /// lockfile without one, returning the number removed.
+  pub fn remove_remote_trailing_slash_dupes(&mut self) -> usize {
+    let dupes = self.content.remote_trailing_slash_dupes();
+    for (_, with_slash) in &dupes {
+      self.content.remote.remove(with_slash);
+    }
+    if !dupes.is_empty() {
+      self.mark_content_changed();
+    }
+    dupes.len()
+  }
+
+  /// Replaces the integrity of an npm package (ex. `chalk@5.0.0`) while
+  /// keeping its dependencies, returning if the integrity changed.
+  ///
+  /// WARNING: It is up to the caller to ensure checksums of packages are
+  /// valid before they are inserted here.
+  pub fn set_npm_integrity(
+    &mut self,
+    id: &str,
+    integrity: String,
+  ) -> Result<bool, MissingPackageError> {
+    let Some(package) = self.content.packages.npm.get_mut(id) else {
+      return Err(MissingPackageError {
+        name: id.to_string(),
+      });
+    };
+    if package.integrity == integrity {
+      return Ok(false);
+    }
+    package.integrity = integrity;
+    self.mark_content_changed();
+    Ok(true)
+  }
+
+  /// Inserts an npm package into the lockfile replacing the existing package if it exists.
+  ///
+  /// WARNING: It is up to the caller to ensure checksums of packages are
+  /// valid before it is inserted here.
+  pub fn insert_npm_package(&mut self, package_info: NpmPackageLockfileInfo) {
+    let (id, package_info) = npm_package_info(package_info);
+    let entry = self.content.packages.npm.entry(id);
+    match entry {
+      Entry::Vacant(entry) => {
+        entry.insert(package_info);
+        self.mark_content_changed();
+      }
+      Entry::Occupied(mut entry) => {
+        if *entry.get() != package_info {
+          entry.insert(package_info);
+          self.mark_content_changed();
+        }
+      }
+    }
+  }
+
+  /// Same as [`Lockfile::insert_npm_package`], but errors instead when
+  /// the lockfile is frozen and the package would change it.
+  pub fn try_insert_npm_package(
+    &mut self,
+    package_info: NpmPackageLockfileInfo,
+  ) -> Result<(), FrozenError> {
+    if self.frozen.is_some() {
+      let (id, info) = npm_package_info(package_info.clone());
+      self.check_frozen(
+        self.content.packages.npm.get(&id) != Some(&info),
+        &format!("npm:{}", id),
+      )?;
+    }
+    self.insert_npm_package(package_info);
+    Ok(())
+  }
+
+  /// Replaces the npm package with the provided name and version with
+  /// a new version, changing the dependents and specifiers of the old
+  /// version to the new one.
+  ///
+  /// The old version is always removed since every reference to it,
+  /// including optional and peer dependencies, is changed to the new one.
+  pub fn update_npm_package(
+    &mut self,
+    name: &str,
+    old_version: &str,
+    new_info: NpmPackageLockfileInfo,
+  ) -> UpdateReport {
+    let old_id = format!("{}@{}", name, old_version);
+    let new_id = new_info.serialized_id.clone();
+    self.insert_npm_package(new_info);
+    let mut report = UpdateReport::default();
+    if old_id == new_id {
+      return report;
+    }
+
+    let packages = &mut self.content.packages;
+    for (id, package) in packages.npm.iter_mut() {
+      for dep_id in package
+        .dependencies
+        .values_mut()
+        .chain(package.optional_dependencies.values_mut())
+        .chain(package.peer_dependencies.values_mut())
+      {
+        if *dep_id == old_id {
+          *dep_id = new_id.clone();
+          report.dependents.insert(id.clone());
+        }
+      }
+    }
+    let old_specifier_id = format!("npm:{}", old_id);
+    for (req, id) in packages.specifiers.iter_mut() {
+      if *id == old_specifier_id {
+        *id = format!("npm:{}", new_id);
+        report.specifiers.insert(req.clone());
+      }
+    }
+    report.removed_old = packages.npm.remove(&old_id).is_some();
+
+    if !report.dependents.is_empty()
+      || !report.specifiers.is_empty()
+      || report.removed_old
+    {
+      self.mark_content_changed();
+    }
+    report
+  }
+
+  /// Removes an npm package (ex. `chalk@5.0.0`) from the lockfile along
+  /// with the specifiers that resolve to it.
+  ///
+  /// This doesn't remove the dependencies of the package.
+  pub fn remove_npm_package(&mut self, id: &str) -> Option<NpmPackageInfo> {
+    let package = self.content.packages.npm.remove(id)?;
+    let specifier_id = format!("npm:{}", id);
+    self
+      .content
+      .packages
+      .specifiers
+      .retain(|_, value| *value != specifier_id);
+    self.mark_content_changed();
+    Some(package)
+  }
+
+  /// Removes a jsr package (ex. `@std/path@0.75.0`) from the lockfile
+  /// along with the specifiers that resolve to it. The requirements of
+  /// those specifiers are also removed from the dependencies of the
+  /// other jsr packages.
+  ///
+  /// This doesn't remove the dependencies of the package.
+  pub fn remove_jsr_package(&mut self, nv: &str) -> Option<JsrPackageInfo> {
+    let packages = &mut self.content.packages;
+    let package = packages.jsr.remove(nv)?;
+    let specifier_id = format!("jsr:{}", nv);
+    let mut removed_reqs = HashSet::new();
+    packages.specifiers.retain(|req, id| {
+      if *id == specifier_id {
+        removed_reqs.insert(req.clone());
+        false
+      } else {
+        true
+      }
+    });
+    for other in packages.jsr.values_mut() {
+      other.dependencies.retain(|req| !removed_reqs.contains(req));
+    }
+    self.mark_content_changed();
+    Some(package)
+  }
+
+  /// Removes every npm or jsr package under a scope (ex. `@scope`, which
+  /// matches `@scope/name` but not `@scopex/name`) along with the
+  /// specifiers that resolve to them.
+  ///
+  /// References to the removed packages are removed from the dependencies
+  /// of the other packages, but the dependencies of the removed packages
+  /// are kept.
+  pub fn remove_scope(
+    &mut self,
+    registry: RegistryKind,
+    scope: &str,
+  ) -> RemovedPackages {
+    let prefix = format!("@{}/", scope.trim_start_matches('@'));
+    let in_scope = |id: &str| npm_id_name(id).starts_with(&prefix);
+    let mut removed = RemovedPackages::default();
+    let packages = &mut self.content.packages;
+    match registry {
+      RegistryKind::Npm => packages.npm.retain(|id, _| {
+        let keep = !in_scope(id);
+        if !keep {
+          removed.npm_packages.insert(id.clone());
+        }
+        keep
+      }),
+      RegistryKind::Jsr => packages.jsr.retain(|nv, _| {
+        let keep = !in_scope(nv);
+        if !keep {
+          removed.jsr_packages.insert(nv.clone());
+        }
+        keep
+      }),
+    }
+
+    packages.specifiers.retain(|req, id| {
+      let is_removed = match id.split_once(':') {
+        Some(("npm", id)) => removed.npm_packages.contains(id),
+        Some(("jsr", nv)) => removed.jsr_packages.contains(nv),
+        _ => false,
+      };
+      if is_removed {
+        removed.specifiers.insert(req.clone());
+      }
+      !is_removed
+    });
+    for package in packages.jsr.values_mut() {
+      package
+        .dependencies
+        .retain(|req| !removed.specifiers.contains(req));
+    }
+    for package in packages.npm.values_mut() {
+      for deps in [
+        &mut package.dependencies,
+        &mut package.optional_dependencies,
+        &mut package.peer_dependencies,
+      ] {
+        deps.retain(|_, id| !removed.npm_packages.contains(id));
+      }
+    }
+
+    if removed != RemovedPackages::default() {
+      self.mark_content_changed();
+    }
+    removed
+  }
+
+  /// Renames a package specifier (ex. `npm:chalk@^5` to `npm:chalk@5`)
+  /// keeping the package id it resolves to.
+  ///
+  /// The jsr package dependencies and the workspace dependencies store
+  /// package requirements, so they're renamed as well.
+  pub fn rename_specifier(
+    &mut self,
+    old_req: &str,
+    new_req: &str,
+  ) -> Result<(), RenameError> {
+    let old_req = self.specifier_normalization.normalize(old_req.to_string());
+    let new_req = self.specifier_normalization.normalize(new_req.to_string());
+    let specifiers = &mut self.content.packages.specifiers;
+    let Some(id) = specifiers.get(&old_req).cloned() else {
+      return Err(RenameError::NotFound(old_req));
+    };
+    if old_req == new_req {
+      return Ok(());
+    }
+    if let Some(existing) = specifiers.get(&new_req) {
+      if *existing != id {
+        return Err(RenameError::Conflict(new_req, existing.clone(), id));
+      }
+    }
+
+    specifiers.remove(&old_req);
+    specifiers.insert(new_req.clone(), id);
+    for package in self.content.packages.jsr.values_mut() {
+      if package.dependencies.remove(&old_req) {
+        package.dependencies.insert(new_req.clone());
+      }
+    }
+    self.content.workspace.rename_dep_req(&old_req, &new_req);
+    self.mark_content_changed();
+    Ok(())
+  }
+
+  /// Merges the package specifiers whose version ranges are spelled
+  /// differently, but are the same (ex. `npm:chalk@5.x` and
+  /// `npm:chalk@^5`), into the canonical spelling (ex. `npm:chalk@^5.0.0`)
+  /// and returns how many were merged. Specifiers that don't have the same
+  /// range as another keep their spelling.
+  ///
+  /// The merged specifiers are renamed with [`Lockfile::rename_specifier`],
+  /// so the workspace dependencies keep matching the specifiers.
+  ///
+  /// Errors without changing the lockfile when specifiers with the same
+  /// canonical spelling resolve to different packages.
+  pub fn normalize_specifiers(&mut self) -> Result<usize, RenameError> {
+    let mut groups = BTreeMap::<String, Vec<(&String, &String)>>::new();
+    for (req, id) in &self.content.packages.specifiers {
+      let canonical_req = version_req::canonicalize_package_req(req);
+      let group = groups.entry(canonical_req.clone()).or_default();
+      if let Some((_, first_id)) = group.first() {
+        if *first_id != id {
+          return Err(RenameError::Conflict(
+            canonical_req,
+            first_id.to_string(),
+            id.clone(),
+          ));
+        }
+      }
+      group.push((req, id));
+    }
+
+    let renames = groups
+      .into_iter()
+      .filter(|(_, group)| group.len() > 1)
+      .flat_map(|(canonical_req, group)| {
+        group
+          .into_iter()
+          .filter(|(req, _)| **req != canonical_req)
+          .map(|(req, _)| (req.clone(), canonical_req.clone()))
+          .collect::<Vec<_>>()
+      })
+      .collect::<Vec<_>>();
+    let len = self.content.packages.specifiers.len();
+    for (req, canonical_req) in renames {
+      self.rename_specifier(&req, &canonical_req)?;
+    }
+    Ok(len - self.content.packages.specifiers.len())
+  }
+
+  /// Renames a package specifier (ex. `npm:chalk@^5`) to the exact
+  /// version it resolves to (ex. `npm:chalk@5.0.1`), returning the
+  /// new requirement. See [`Lockfile::rename_specifier`].
+  pub fn pin_specifier(&mut self, req: &str) -> Result<String, PinError> {
+    let (req, name, version) = self.resolved_req_parts(req)?;
+    let pinned_req = format!("{}@{}", name, version);
+    self.rename_specifier(&req, &pinned_req)?;
+    Ok(pinned_req)
+  }
+
+  /// Renames a package specifier pinned by [`Lockfile::pin_specifier`]
+  /// (ex. `npm:chalk@5.0.1`) to a caret range of the version it
+  /// resolves to (ex. `npm:chalk@^5.0.1`), returning the new requirement.
+  pub fn unpin_specifier(&mut self, req: &str) -> Result<String, PinError> {
+    let (req, name, version) = self.resolved_req_parts(req)?;
+    let pinned_req = format!("{}@{}", name, version);
+    if req != pinned_req {
+      return Err(PinError::NotPinned(req, version.to_string()));
+    }
+    let unpinned_req = format!("{}@^{}", name, version);
+    self.rename_specifier(&req, &unpinned_req)?;
+    Ok(unpinned_req)
+  }
+
+  /// Gets the normalized requirement, the scheme and name of the
+  /// requirement (ex. `npm:chalk`), and the version it resolves to
+  /// without any peer dependency suffix (ex. `5.0.1`).
+  fn resolved_req_parts(
+    &self,
+    req: &str,
+  ) -> Result<(String, String, String), PinError> {
+    let req = self.specifier_normalization.normalize(req.to_string());
+    let Some(id) = self.content.packages.specifiers.get(&req) else {
+      return Err(PinError::NotFound(req));
+    };
+    let invalid_id = || PinError::InvalidId(req.clone(), id.clone());
+    let (_, _, version) = extract_nv_from_id(id).ok_or_else(invalid_id)?;
+    let version = version.split('_').next().unwrap_or(version).to_string();
+    let (scheme, rest) = split_pkg_req(&req).ok_or_else(invalid_id)?;
+    let name = format!("{}{}", scheme, npm_id_name(rest));
+    Ok((req, name, version))
+  }
+
+  /// Removes a package specifier from the lockfile returning the
+  /// package id it resolved to if it existed.
+  ///
+  /// The resolved package is intentionally left in the `npm` or `jsr`
+  /// section because other specifiers may still reference it. Unreferenced
+  /// packages are cleaned up when pruning.
+  pub fn remove_package_specifier(&mut self, req: &str) -> Option<String> {
+    let req = self.specifier_normalization.normalize(req.to_string());
+    let id = self.content.packages.specifiers.remove(&req);
+    if id.is_some() {
+      self.mark_content_changed();
+    }
+    id
+  }
+
+  /// Inserts a package specifier into the lockfile.
+  ///
+  /// The requirement is normalized based on `specifier_normalization`.
+  /// A specifier resolving to a package that isn't in the lockfile is
+  /// inserted regardless and reported by [`Lockfile::validate`].
+  ///
+  /// Accepts a [`PackageReq`] and [`PackageId`] as well as strings.
+  pub fn insert_package_specifier(
+    &mut self,
+    serialized_package_req: impl Into<String>,
+    serialized_package_id: impl Into<String>,
+  ) {
+    let serialized_package_req = self
+      .specifier_normalization
+      .normalize(serialized_package_req.into());
+    let serialized_package_id = serialized_package_id.into();
+    let entry = self
+      .content
+      .packages
+      .specifiers
+      .entry(serialized_package_req);
+    match entry {
+      Entry::Vacant(entry) => {
+        entry.insert(serialized_package_id);
+        self.mark_content_changed();
+      }
+      Entry::Occupied(mut entry) => {
+        if *entry.get() != serialized_package_id {
+          entry.insert(serialized_package_id);
+          self.mark_content_changed();
+        }
+      }
+    }
+  }
+
+  /// Inserts a package specifier into the lockfile when the package it
+  /// resolves to (ex. `npm:chalk@5.0.0`) is in the lockfile.
+  ///
+  /// Use [`Lockfile::verify_specifier_targets`] instead when the packages
+  /// are inserted after the specifiers.
+  pub fn insert_package_specifier_checked(
+    &mut self,
+    serialized_package_req: String,
+    serialized_package_id: String,
+  ) -> Result<(), MissingPackageError> {
+    self.check_specifier_target(&serialized_package_id)?;
+    self
+      .insert_package_specifier(serialized_package_req, serialized_package_id);
+    Ok(())
+  }
+
+  /// Checks that every package specifier resolves to a package in the
+  /// lockfile, erroring with the first one that doesn't.
+  pub fn verify_specifier_targets(&self) -> Result<(), MissingPackageError> {
+    for id in self.content.packages.specifiers.values() {
+      self.check_specifier_target(id)?;
+    }
+    Ok(())
+  }
+
+  /// Checks the packages for problems, such as dependencies that aren't in
+  /// the lockfile, collecting all of them instead of stopping at the first.
+  pub fn validate(&self) -> Vec<LockfileProblem> {
+    let mut problems = Vec::new();
+    let packages = &self.content.packages;
+    for (req, id) in &packages.specifiers {
+      if extract_nv_from_id(id).is_none() {
+        problems.push(LockfileProblem::InvalidSpecifierId {
+          req: req.clone(),
+          id: id.clone(),
+        });
+      } else if !graphs::package_exists(&self.content, id) {
+        problems.push(LockfileProblem::MissingSpecifierTarget {
+          req: req.clone(),
+          id: id.clone(),
+        });
+      }
+    }
+    for (nv, package) in &packages.jsr {
+      let id = format!("jsr:{}", nv);
+      if extract_nv_from_id(&id).is_none() {
+        problems.push(LockfileProblem::MalformedPackageId { id: id.clone() });
+      }
+      for req in &package.dependencies {
+        if !packages.specifiers.contains_key(req) {
+          problems.push(LockfileProblem::MissingJsrDependency {
+            id: id.clone(),
+            req: req.clone(),
+          });
+        }
+      }
+      if integrity_format(&package.integrity) == "unknown" {
+        problems.push(LockfileProblem::UnknownIntegrityFormat {
+          id,
+          integrity: package.integrity.clone(),
+        });
+      }
+    }
+    for (npm_id, package) in &packages.npm {
+      let id = format!("npm:{}", npm_id);
+      if extract_nv_from_id(&id).is_none() {
+        problems.push(LockfileProblem::MalformedPackageId { id: id.clone() });
+      }
+      let dep_ids = package
+        .dependencies
+        .values()
+        .chain(package.optional_dependencies.values())
+        .chain(package.peer_dependencies.values())
+        .collect::<BTreeSet<_>>();
+      for dep_id in dep_ids {
+        if !packages.npm.contains_key(dep_id) {
+          problems.push(LockfileProblem::MissingNpmDependency {
+            id: id.clone(),
+            dep_id: dep_id.clone(),
+          });
+        }
+      }
+      if integrity_format(&package.integrity) == "unknown" {
+        problems.push(LockfileProblem::UnknownIntegrityFormat {
+          id,
+          integrity: package.integrity.clone(),
+        });
+      }
+    }
+    for id in self.content.patches.keys() {
+      if !graphs::package_exists(&self.content, id) {
+        problems
+          .push(LockfileProblem::MissingPatchedPackage { id: id.clone() });
+      }
+    }
+    for section in self.content.misplaced_package_sections() {
+      problems.push(LockfileProblem::MisplacedPackageSection { section });
+    }
+    problems
+  }
+
+  /// Moves the sections that belong under `packages`, but are at the top
+  /// level of the lockfile (ex. `npm`), under `packages`. Entries already
+  /// under `packages` are kept over the misplaced ones.
+  ///
+  /// Returns the names of the moved sections so they can be warned about.
+  /// Sections that don't have the shape of their `packages` counterpart
+  /// are left as is.
+  pub fn repair_misplaced_packages(&mut self) -> Vec<String> {
+    fn move_entries<T: serde::de::DeserializeOwned>(
+      value: &serde_json::Value,
+      target: &mut BTreeMap<String, T>,
+    ) -> bool {
+      let Ok(entries) =
+        serde_json::from_value::<BTreeMap<String, T>>(value.clone())
+      else {
+        return false;
+      };
+      for (key, entry) in entries {
+        target.entry(key).or_insert(entry);
+      }
+      true
+    }
+
+    let mut moved = Vec::new();
+    for section in self.content.misplaced_package_sections() {
+      let value = &self.content.extra.0[&section];
+      let packages = &mut self.content.packages;
+      let is_moved = match section.as_str() {
+        "specifiers" => move_entries(value, &mut packages.specifiers),
+        "jsr" => move_entries(value, &mut packages.jsr),
+        _ => move_entries(value, &mut packages.npm),
+      };
+      if is_moved {
+        self.content.extra.0.remove(&section);
+        moved.push(section);
+      }
+    }
+    if !moved.is_empty() {
+      self.mark_content_changed();
+    }
+    moved
+  }
+
+  fn check_specifier_target(
+    &self,
+    id: &str,
+  ) -> Result<(), MissingPackageError> {
+    if graphs::package_exists(&self.content, id) {
+      Ok(())
+    } else {
+      Err(MissingPackageError {
+        name: id.to_string(),
+      })
+    }
+  }
+
+  /// Replaces all the package specifiers in the lockfile, removing the
+  /// ones not in the provided map.
+  ///
+  /// The requirements are normalized based on `specifier_normalization`.
+  pub fn set_specifiers(&mut self, specifiers: BTreeMap<String, String>) {
+    let specifiers = specifiers
+      .into_iter()
+      .map(|(req, id)| (self.specifier_normalization.normalize(req), id))
+      .collect::<BTreeMap<_, _>>();
+    if specifiers != self.content.packages.specifiers {
+      self.content.packages.specifiers = specifiers;
+      self.mark_content_changed();
+    }
+  }
+
+  /// Inserts the package specifiers into the lockfile, keeping the
+  /// existing ones not in the provided map.
+  pub fn merge_specifiers(&mut self, specifiers: BTreeMap<String, String>) {
+    for (req, id) in specifiers {
+      self.insert_package_specifier(req, id);
+    }
+  }
+
+  /// Inserts a package specifier into the lockfile after validating
+  /// the requirement (ex. `jsr:@std/path@^0.75`) and the package id
+  /// (ex. `jsr:@std/path@0.75.0`) it resolves to.
+  ///
+  /// Errors when the lockfile is frozen and the specifier would change it
+  /// or, with `strict_specifiers`, when the package isn't in the lockfile.
+  pub fn try_insert_package_specifier(
+    &mut self,
+    serialized_package_req: String,
+    serialized_package_id: String,
+  ) -> Result<(), Error> {
+    let serialized_package_req = self
+      .specifier_normalization
+      .normalize(serialized_package_req);
+    let req = PackageReq::parse(&serialized_package_req)?;
+    let id = PackageId::parse(&serialized_package_id)?;
+    self.try_insert_package_req(req, id)
+  }
+
+  /// Same as [`Lockfile::try_insert_package_specifier`], but with a
+  /// requirement and package id that were already parsed.
+  ///
+  /// Errors when the package id is from a different registry than the
+  /// requirement.
+  pub fn try_insert_package_req(
+    &mut self,
+    req: PackageReq,
+    id: PackageId,
+  ) -> Result<(), Error> {
+    if req.kind() != id.kind() {
+      return Err(Error::InvalidPackageSpecifier(
+        id.into(),
+        format!(
+          "Expected the package id to start with {}.",
+          req.kind().scheme()
+        ),
+      ));
+    }
+    let serialized_package_req =
+      self.specifier_normalization.normalize(req.into());
+    let serialized_package_id = String::from(id);
+    if self.strict_specifiers {
+      self.check_specifier_target(&serialized_package_id)?;
+    }
+    self.check_frozen(
+      self
+        .content
+        .packages
+        .specifiers
+        .get(&serialized_package_req)
+        != Some(&serialized_package_id),
+      &serialized_package_req,
+    )?;
+
+    self
+      .insert_package_specifier(serialized_package_req, serialized_package_id);
+    Ok(())
+  }
+
+  /// Inserts a JSR package into the lockfile replacing the existing package's integrity
+  /// if they differ.
+  ///
+  /// Use [`Lockfile::insert_jsr_package`] to insert the package along with
+  /// its dependencies in one step.
+  ///
+  /// The `name` is the id without the `jsr:` scheme (ex. `@std/path@0.75.0`),
+  /// which is the [`PackageId::package_key`] of a parsed id.
+  ///
+  /// WARNING: It is up to the caller to ensure checksums of packages are
+  /// valid before it is inserted here.
+  pub fn insert_package(&mut self, name: String, integrity: String) {
+    let dependencies = self
+      .content
+      .packages
+      .jsr
+      .get(&name)
+      .map(|package| package.dependencies.iter().cloned().collect())
+      .unwrap_or_default();
+    self.insert_jsr_package(JsrPackageLockfileInfo {
+      nv: name,
+      integrity,
+      dependencies,
+    });
+  }
+
+  /// Adds package dependencies of a JSR package. This is only used to track
+  /// when packages can be removed from the lockfile.
+  ///
+  /// WARNING: The dependencies are silently dropped when the package isn't
+  /// in the lockfile. Use [`Lockfile::try_add_package_deps`] to detect this,
+  /// [`Lockfile::add_package_deps_or_insert`] to create the package, or
+  /// [`Lockfile::insert_jsr_package`] when the integrity is known.
+  #[deprecated(
+    note = "Use `try_add_package_deps`, which errors when the package isn't in the lockfile."
+  )]
+  pub fn add_package_deps(
+    &mut self,
+    name: &str,
+    deps: impl Iterator<Item = String>,
+  ) {
+    let _ = self.try_add_package_deps(name, deps);
+  }
+
+  /// Adds package dependencies of a JSR package. This is only used to track
+  /// when packages can be removed from the lockfile.
+  pub fn try_add_package_deps(
+    &mut self,
+    name: &str,
+    deps: impl Iterator<Item = String>,
+  ) -> Result<(), MissingPackageError> {
+    let Some(pkg) = self.content.packages.jsr.get(name) else {
+      return Err(MissingPackageError {
+        name: name.to_string(),
+      });
+    };
+    let info = JsrPackageLockfileInfo {
+      nv: name.to_string(),
+      integrity: pkg.integrity.clone(),
+      dependencies: pkg.dependencies.iter().cloned().chain(deps).collect(),
+    };
+    self.insert_jsr_package(info);
+    Ok(())
+  }
+
+  /// Adds package dependencies of a JSR package, inserting the package
+  /// with an empty integrity when it isn't in the lockfile. The integrity
+  /// should be provided later with [`Lockfile::insert_package`].
+  pub fn add_package_deps_or_insert(
+    &mut self,
+    name: &str,
+    deps: impl Iterator<Item = String>,
+  ) {
+    let (integrity, existing_deps) = match self.content.packages.jsr.get(name) {
+      Some(pkg) => (pkg.integrity.clone(), pkg.dependencies.clone()),
+      None => Default::default(),
+    };
+    self.insert_jsr_package(JsrPackageLockfileInfo {
+      nv: name.to_string(),
+      integrity,
+      dependencies: existing_deps.into_iter().chain(deps).collect(),
+    });
+  }
+
+  /// Inserts a jsr package with its dependencies into the lockfile
+  /// replacing the existing package if it differs.
+  ///
+  /// WARNING: It is up to the caller to ensure checksums of packages are
+  /// valid before it is inserted here.
+  pub fn insert_jsr_package(&mut self, info: JsrPackageLockfileInfo) {
+    let package_info = JsrPackageInfo {
+      integrity: info.integrity,
+      dependencies: info.dependencies.into_iter().collect(),
+    };
+    let entry = self.content.packages.jsr.entry(info.nv);
+    match entry {
+      Entry::Vacant(entry) => {
+        entry.insert(package_info);
+        self.mark_content_changed();
+      }
+      Entry::Occupied(mut entry) => {
+        let existing = entry.get();
+        if existing.integrity != package_info.integrity
+          || existing.dependencies != package_info.dependencies
+        {
+          entry.insert(package_info);
+          self.mark_content_changed();
+        }
+      }
+    }
+  }
+
+  /// Same as [`Lockfile::insert_jsr_package`], but errors instead when
+  /// the lockfile is frozen and the package would change it.
+  pub fn try_insert_jsr_package(
+    &mut self,
+    info: JsrPackageLockfileInfo,
+  ) -> Result<(), FrozenError> {
+    let would_change = match self.content.packages.jsr.get(&info.nv) {
+      Some(package) => {
+        package.integrity != info.integrity
+          || package.dependencies
+            != info.dependencies.iter().cloned().collect::<BTreeSet<_>>()
+      }
+      None => true,
+    };
+    self.check_frozen(would_change, &format!("jsr:{}", info.nv))?;
+    self.insert_jsr_package(info);
+    Ok(())
+  }
+
+  /// Inserts a patch for a package (ex. `npm:chalk@5.0.0`) replacing
+  /// the existing patch.
+  ///
+  /// WARNING: It is up to the caller to ensure checksums of patches are
+  /// valid before they are inserted here.
+  pub fn insert_patch(&mut self, id: String, patch: PatchInfo) {
+    let entry = self.content.patches.entry(id);
+    match entry {
+      Entry::Vacant(entry) => {
+        entry.insert(patch);
+        self.mark_content_changed();
+      }
+      Entry::Occupied(mut entry) => {
+        if *entry.get() != patch {
+          entry.insert(patch);
+          self.mark_content_changed();
+        }
+      }
+    }
+  }
+
+  /// Removes the patch of a package, returning it if it existed.
+  pub fn remove_patch(&mut self, id: &str) -> Option<PatchInfo> {
+    let patch = self.content.patches.remove(id);
+    if patch.is_some() {
+      self.mark_content_changed();
+    }
+    patch
+  }
+
+  /// Inserts a note about a package (ex. `npm:chalk@5.0.0`), such as
+  /// why it's pinned, replacing the existing note.
+  pub fn insert_comment(&mut self, id: String, comment: String) {
+    let entry = self.content.comments.entry(id);
+    match entry {
+      Entry::Vacant(entry) => {
+        entry.insert(comment);
+        self.mark_content_changed();
+      }
+      Entry::Occupied(mut entry) => {
+        if *entry.get() != comment {
+          entry.insert(comment);
+          self.mark_content_changed();
+        }
+      }
+    }
+  }
+
+  /// Removes the note about a package returning it if it existed.
+  pub fn remove_comment(&mut self, id: &str) -> Option<String> {
+    let comment = self.content.comments.remove(id);
+    if comment.is_some() {
+      self.mark_content_changed();
+    }
+    comment
+  }
+
+  /// Gets the redirects whose source starts with the provided prefix
+  /// (ex. `https://deno.land/x/`).
+  pub fn redirects_with_prefix<'a>(
+    &'a self,
+    prefix: &'a str,
+  ) -> impl Iterator<Item = (&'a str, &'a str)> {
+    self
+      .content
+      .redirects
+      .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+      .take_while(move |(from, _)| from.starts_with(prefix))
+      .map(|(from, to)| (from.as_str(), to.as_str()))
+  }
+
+  /// Removes the redirects whose source starts with the provided prefix,
+  /// returning the removed pairs of source and destination.
+  pub fn remove_redirects_with_prefix(
+    &mut self,
+    prefix: &str,
+  ) -> Vec<(String, String)> {
+    let sources = self
+      .redirects_with_prefix(prefix)
+      .map(|(from, _)| from.to_string())
+      .collect::<Vec<_>>();
+    let removed = sources
+      .into_iter()
+      .filter_map(|from| {
+        let to = self.content.redirects.remove(&from)?;
+        Some((from, to))
+      })
+      .collect::<Vec<_>>();
+    if !removed.is_empty() {
+      self.mark_content_changed();
+    }
+    removed
+  }
+
+  pub fn insert_redirect(
+    &mut self,
+    from: String,
+    to: String,
+  ) -> InsertRedirectResult {
+    if from.starts_with("jsr:") && !self.redirects_options.allow_jsr {
+      return InsertRedirectResult::RejectedJsrSpecifier;
+    }
+
+    let entry = self.content.redirects.entry(from);
+    match entry {
+      Entry::Vacant(entry) => {
+        entry.insert(to);
+        self.mark_content_changed();
+        InsertRedirectResult::Inserted
+      }
+      Entry::Occupied(mut entry) => {
+        if *entry.get() != to {
+          entry.insert(to);
+          self.mark_content_changed();
+          InsertRedirectResult::Updated
+        } else {
+          InsertRedirectResult::Unchanged
+        }
+      }
+    }
+  }
+
+  /// Takes a checkpoint of the content that can be restored with
+  /// [`Lockfile::restore`].
+  pub fn snapshot(&self) -> LockfileSnapshot {
+    LockfileSnapshot {
+      content: self.content.clone(),
+    }
+  }
+
+  /// Restores the content of a snapshot.
+  ///
+  /// Whether the content changed is determined by comparing with the
+  /// content the lockfile was loaded from, so restoring a snapshot taken
+  /// before any changes means the lockfile no longer needs to be written.
+  pub fn restore(&mut self, snapshot: LockfileSnapshot) {
+    let content = snapshot.content;
+    if content.to_value() != self.content.to_value() {
+      self.mark_content_changed();
+      if self.frozen.is_some() {
+        return;
+      }
+    }
+    let original = match &self.original_content {
+      Some(text) => {
+        Lockfile::with_lockfile_content(self.filename.clone(), text, false)
+          .ok()
+          .map(|lockfile| lockfile.content)
+      }
+      None => Some(LockfileContent::empty()),
+    };
+    self.has_content_changed = match original {
+      Some(original) => original.to_value() != content.to_value(),
+      None => true,
+    };
+    self.content = content;
+  }
+
+  /// Same as [`Lockfile::insert_redirect`], but errors instead when the
+  /// lockfile is frozen and the redirect would change it.
+  pub fn try_insert_redirect(
+    &mut self,
+    from: String,
+    to: String,
+  ) -> Result<InsertRedirectResult, FrozenError> {
+    let is_rejected =
+      from.starts_with("jsr:") && !self.redirects_options.allow_jsr;
+    self.check_frozen(
+      !is_rejected && self.content.redirects.get(&from) != Some(&to),
+      &from,
+    )?;
+    Ok(self.insert_redirect(from, to))
+  }
+
+  /// Starts changing the lockfile in a way that's undone unless
+  /// [`LockfileTransaction::commit`] is called, such as when resolution
+  /// fails halfway through.
+  pub fn transaction(&mut self) -> LockfileTransaction<'_> {
+    LockfileTransaction {
+      snapshot: Some(TransactionSnapshot {
+        content: self.content.clone(),
+        has_content_changed: self.has_content_changed,
+        generation: self.generation,
+      }),
+      lockfile: self,
+    }
+  }
+}
+
+struct TransactionSnapshot {
+  content: LockfileContent,
+  has_content_changed: bool,
+  generation: u64,
+}
+
+/// Changes to a lockfile that are undone when dropped without being
+/// committed. See [`Lockfile::transaction`].
+pub struct LockfileTransaction<'a> {
+  lockfile: &'a mut Lockfile,
+  snapshot: Option<TransactionSnapshot>,
+}
+
+/// Content of a lockfile at some point in time.
+/// See [`Lockfile::snapshot`].
+#[derive(Debug, Clone)]
+pub struct LockfileSnapshot {
+  content: LockfileContent,
+}
+
+impl<'a> LockfileTransaction<'a> {
+  /// Keeps the changes made during the transaction.
+  pub fn commit(mut self) {
+    self.snapshot = None;
+  }
+}
+
+impl<'a> std::ops::Deref for LockfileTransaction<'a> {
+  type Target = Lockfile;
+
+  fn deref(&self) -> &Lockfile {
+    self.lockfile
+  }
+}
+
+impl<'a> std::ops::DerefMut for LockfileTransaction<'a> {
+  fn deref_mut(&mut self) -> &mut Lockfile {
+    self.lockfile
+  }
+}
+
+impl<'a> Drop for LockfileTransaction<'a> {
+  fn drop(&mut self) {
+    let Some(snapshot) = self.snapshot.take() else {
+      return;
+    };
+    let lockfile = &mut *self.lockfile;
+    // the content is always restored since it may have been changed
+    // directly without moving the generation forward
+    let is_newer = lockfile.generation != snapshot.generation;
+    let was_written = is_newer && !lockfile.has_content_changed;
+    lockfile.content = snapshot.content;
+    lockfile.has_content_changed = snapshot.has_content_changed;
+    // the generation only moves forward so that bytes resolved during the
+    // transaction are never considered newer than the restored content
+    if was_written {
+      // the changes were written, so the restored content needs writing
+      lockfile.mark_content_changed();
+    } else if is_newer {
+      lockfile.generation += 1;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use pretty_assertions::assert_eq;
+
+  const LOCKFILE_JSON: &str = r#"
+{
+  "version": "3",
+  "packages": {
+    "specifiers": {},
+    "npm": {
+      "nanoid@3.3.4": {
+        "integrity": "sha512-MqBkQh/OHTS2egovRtLk45wEyNXwF+cokD+1YPf9u5VfJiRdAiRwB2froX5Co9Rh20xs4siNPm8naNotSD6RBw==",
+        "dependencies": {}
+      },
+      "picocolors@1.0.0": {
+        "integrity": "sha512-foobar",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {
+    "https://deno.land/std@0.71.0/textproto/mod.ts": "3118d7a42c03c242c5a49c2ad91c8396110e14acca1324e7aaefd31a999b71a4",
+    "https://deno.land/std@0.71.0/async/delay.ts": "35957d585a6e3dd87706858fb1d6b551cb278271b03f52c5a2cb70e65e00c26a"
+  }
+}"#;
+
+  fn setup(overwrite: bool) -> Result<Lockfile, Error> {
+    let file_path =
+      std::env::current_dir().unwrap().join("valid_lockfile.json");
+    Lockfile::with_lockfile_content(file_path, LOCKFILE_JSON, overwrite)
+  }
+
+  #[test]
+  fn original_text() {
+    let mut lockfile = setup(false).unwrap();
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/io/util.ts".to_string(),
+      "checksum-1".to_string(),
+    );
+    assert_eq!(lockfile.original_text(), Some(LOCKFILE_JSON));
+
+    // overwriting ignores the content
+    assert_eq!(setup(true).unwrap().original_text(), None);
+  }
+
+  #[test]
+  fn default() {
+    let lockfile = Lockfile::default();
+    assert_eq!(lockfile.filename, PathBuf::new());
+    assert!(!lockfile.overwrite);
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.as_json_string(),
+      Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false)
+        .as_json_string()
+    );
+    assert!(LockfileContent::default().is_empty());
+    assert_eq!(LockfileContent::default().version(), "3");
+  }
+
+  #[test]
+  fn original_version() {
+    let lockfile = setup(false).unwrap();
+    assert_eq!(lockfile.original_version(), LockfileVersion::V3);
+    assert_eq!(lockfile.original_version().to_string(), "3");
+
+    let lockfile = Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false);
+    assert_eq!(lockfile.original_version(), LockfileVersion::V3);
+  }
+
+  #[test]
+  fn future_version_unsupported() {
+    let file_path = PathBuf::from("lockfile.json");
+    assert_eq!(
+      Lockfile::with_lockfile_content(
+        file_path,
+        "{ \"version\": \"2000\" }",
+        false
+      )
+      .err()
+      .unwrap().to_string(),
+      "Unsupported lockfile version '2000'. Try upgrading Deno or recreating the lockfile.".to_string()
+    );
+  }
+
+  #[test]
+  fn new_valid_lockfile() {
+    let lockfile = setup(false).unwrap();
+
+    let remote = lockfile.content.remote;
+    let keys: Vec<String> = remote.keys().cloned().collect();
+    let expected_keys = vec![
+      String::from("https://deno.land/std@0.71.0/async/delay.ts"),
+      String::from("https://deno.land/std@0.71.0/textproto/mod.ts"),
+    ];
+
+    assert_eq!(keys.len(), 2);
+    assert_eq!(keys, expected_keys);
+  }
+
+  #[test]
+  fn with_lockfile_content_for_valid_lockfile() {
+    let file_path = PathBuf::from("/foo");
+    let result =
+      Lockfile::with_lockfile_content(file_path, LOCKFILE_JSON, false).unwrap();
+
+    let remote = result.content.remote;
+    let keys: Vec<String> = remote.keys().cloned().collect();
+    let expected_keys = vec![
+      String::from("https://deno.land/std@0.71.0/async/delay.ts"),
+      String::from("https://deno.land/std@0.71.0/textproto/mod.ts"),
+    ];
+
+    assert_eq!(keys.len(), 2);
+    assert_eq!(keys, expected_keys);
+  }
+
+  #[test]
+  fn new_lockfile_from_file_and_insert() {
+    let mut lockfile = setup(false).unwrap();
+
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/io/util.ts".to_string(),
+      "checksum-1".to_string(),
+    );
+
+    let remote = lockfile.content.remote;
+    let keys: Vec<String> = remote.keys().cloned().collect();
+    let expected_keys = vec![
+      String::from("https://deno.land/std@0.71.0/async/delay.ts"),
+      String::from("https://deno.land/std@0.71.0/io/util.ts"),
+      String::from("https://deno.land/std@0.71.0/textproto/mod.ts"),
+    ];
+    assert_eq!(keys.len(), 3);
+    assert_eq!(keys, expected_keys);
+  }
+
+  #[test]
+  fn new_lockfile_and_write() {
+    let mut lockfile = setup(true).unwrap();
+
+    // true since overwrite was true
+    assert!(lockfile.would_write());
+    assert!(lockfile.resolve_write_bytes().is_some());
+
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/textproto/mod.ts".to_string(),
+      "checksum-1".to_string(),
+    );
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/io/util.ts".to_string(),
+      "checksum-2".to_string(),
+    );
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/async/delay.ts".to_string(),
+      "checksum-3".to_string(),
+    );
+
+    let bytes = lockfile.resolve_write_bytes().unwrap();
+    let contents_json =
+      serde_json::from_slice::<serde_json::Value>(&bytes).unwrap();
+    let object = contents_json["remote"].as_object().unwrap();
+
+    assert_eq!(
+      object
+        .get("https://deno.land/std@0.71.0/textproto/mod.ts")
+        .and_then(|v| v.as_str()),
+      Some("checksum-1")
+    );
+
+    // confirm that keys are sorted alphabetically
+    let mut keys = object.keys().map(|k| k.as_str());
+    assert_eq!(
+      keys.next(),
+      Some("https://deno.land/std@0.71.0/async/delay.ts")
+    );
+    assert_eq!(keys.next(), Some("https://deno.land/std@0.71.0/io/util.ts"));
+    assert_eq!(
+      keys.next(),
+      Some("https://deno.land/std@0.71.0/textproto/mod.ts")
+    );
+    assert!(keys.next().is_none());
+  }
+
+  #[test]
+  fn set_filename() {
+    let lockfile = setup(false)
+      .unwrap()
+      .with_filename(PathBuf::from("/bar/deno.lock"));
+    assert_eq!(lockfile.filename, PathBuf::from("/bar/deno.lock"));
+    assert!(!lockfile.has_content_changed);
+
+    let mut lockfile = lockfile;
+    lockfile.set_filename(PathBuf::from("/baz/deno.lock"));
+    assert_eq!(lockfile.filename, PathBuf::from("/baz/deno.lock"));
+
+    // writes go to the new path
+    let dir = std::env::temp_dir()
+      .join(format!("deno_lockfile_set_filename_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file_path = dir.join("deno.lock");
+    lockfile.set_filename(file_path.clone());
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/async/delay.ts".to_string(),
+      "checksum".to_string(),
+    );
+    assert!(lockfile.write_atomic().unwrap());
+    assert_eq!(
+      std::fs::read_to_string(&file_path).unwrap(),
+      lockfile.as_json_string()
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn write_atomic() {
+    let dir = std::env::temp_dir()
+      .join(format!("deno_lockfile_write_atomic_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file_path = dir.join("deno.lock");
+    let mut lockfile =
+      Lockfile::with_lockfile_content(file_path.clone(), LOCKFILE_JSON, false)
+        .unwrap();
+
+    // no changes
+    assert!(!lockfile.write_atomic().unwrap());
+    assert!(!file_path.exists());
+
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/io/util.ts".to_string(),
+      "checksum-1".to_string(),
+    );
+    assert!(lockfile.write_atomic().unwrap());
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(
+      std::fs::read_to_string(&file_path).unwrap(),
+      lockfile.as_json_string()
+    );
+
+    // changes are now relative to the written content
+    let snapshot = lockfile.snapshot();
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/io/util.ts".to_string(),
+      "checksum-other".to_string(),
+    );
+    lockfile.restore(snapshot);
+    assert!(!lockfile.has_content_changed);
+
+    // concurrent writes don't collide
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/io/util.ts".to_string(),
+      "checksum-concurrent".to_string(),
+    );
+    let handles = (0..8)
+      .map(|_| {
+        let mut lockfile = lockfile.clone();
+        std::thread::spawn(move || lockfile.write_atomic().unwrap())
+      })
+      .collect::<Vec<_>>();
+    for handle in handles {
+      assert!(handle.join().unwrap());
+    }
+    assert!(lockfile.write_atomic().unwrap());
+
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      std::fs::set_permissions(
+        &file_path,
+        std::fs::Permissions::from_mode(0o640),
+      )
+      .unwrap();
+      lockfile.insert_remote(
+        "https://deno.land/std@0.71.0/io/util.ts".to_string(),
+        "checksum-2".to_string(),
+      );
+      assert!(lockfile.write_atomic().unwrap());
+      let mode = std::fs::metadata(&file_path).unwrap().permissions().mode();
+      assert_eq!(mode & 0o777, 0o640);
+    }
+
+    // no temporary files left behind
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn check_or_insert_lockfile() {
+    let mut lockfile = setup(false).unwrap();
+
+    // none since overwrite was false and there's no changes
+    assert!(!lockfile.would_write());
+    assert!(lockfile.resolve_write_bytes().is_none());
+
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/textproto/mod.ts".to_string(),
+      "checksum-1".to_string(),
+    );
+    assert!(lockfile.has_content_changed);
+
+    lockfile.has_content_changed = false;
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/textproto/mod.ts".to_string(),
+      "checksum-1".to_string(),
+    );
+    assert!(!lockfile.has_content_changed);
+
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/textproto/mod.ts".to_string(),
+      "checksum-new".to_string(),
+    );
+    assert!(lockfile.has_content_changed);
+    lockfile.has_content_changed = false;
+
+    // Not present in lockfile yet, should be inserted and check passed.
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/http/file_server.ts".to_string(),
+      "checksum-1".to_string(),
+    );
+    assert!(lockfile.has_content_changed);
+
+    // true since there were changes
+    assert!(lockfile.would_write());
+    assert!(lockfile.has_content_changed);
+    assert!(lockfile.resolve_write_bytes().is_some());
+  }
+
+  #[test]
+  fn remove_remote() {
+    let mut lockfile = setup(false).unwrap();
+
+    assert_eq!(
+      lockfile.remove_remote("https://deno.land/std@0.71.0/io/util.ts"),
+      None
+    );
+    assert!(!lockfile.has_content_changed);
+
+    assert_eq!(
+      lockfile.remove_remote("https://deno.land/std@0.71.0/async/delay.ts"),
+      Some(
+        "35957d585a6e3dd87706858fb1d6b551cb278271b03f52c5a2cb70e65e00c26a"
+          .to_string()
+      )
+    );
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.remote().keys().collect::<Vec<_>>(),
+      vec!["https://deno.land/std@0.71.0/textproto/mod.ts"]
+    );
+  }
+
+  #[test]
+  fn resolve_write_bytes_if_newer() {
+    let mut lockfile = setup(false).unwrap();
+    assert_eq!(lockfile.generation(), 0);
+    assert!(lockfile.resolve_write_bytes_if_newer(0).is_none());
+
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/io/util.ts".to_string(),
+      "checksum-1".to_string(),
+    );
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/io/util.ts".to_string(),
+      "checksum-1".to_string(),
+    );
+    assert_eq!(lockfile.generation(), 1);
+    let (bytes, generation) = lockfile.resolve_write_bytes_if_newer(0).unwrap();
+    assert_eq!(bytes, lockfile.as_json_string().into_bytes());
+    assert_eq!(generation, 1);
+    assert!(!lockfile.has_content_changed);
+    assert!(lockfile.resolve_write_bytes_if_newer(generation).is_none());
+
+    lockfile.insert_package("@scope/a@1.0.0".to_string(), "a".to_string());
+    lockfile.insert_redirect("https://a".to_string(), "https://b".to_string());
+    assert_eq!(lockfile.generation(), 3);
+    let (_, generation) =
+      lockfile.resolve_write_bytes_if_newer(generation).unwrap();
+    assert_eq!(generation, 3);
+    assert!(lockfile.resolve_write_bytes_if_newer(generation).is_none());
+  }
+
+  #[test]
+  fn effective_workspace_deps() {
+    let lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "remote": {},
+  "workspace": {
+    "dependencies": ["jsr:@scope/a@1"],
+    "packageJson": {
+      "dependencies": ["npm:chalk@5"]
+    },
+    "members": {
+      "b": {
+        "dependencies": ["npm:nanoid@3"]
+      }
+    }
+  }
+}"#,
+      false,
+    )
+    .unwrap();
+    let options = |no_config: bool, no_npm: bool| SetWorkspaceConfigOptions {
+      config: WorkspaceConfig {
+        root: WorkspaceMemberConfig {
+          dependencies: BTreeSet::from(["jsr:@scope/c@1".to_string()]),
+          package_json_deps: Default::default(),
+        },
+        members: Default::default(),
+      },
+      no_config,
+      no_npm,
+    };
+    let deps = |reqs: &[&str]| {
+      reqs
+        .iter()
+        .map(|req| req.to_string())
+        .collect::<BTreeSet<_>>()
+    };
+
+    assert_eq!(
+      lockfile.effective_workspace_deps(&options(false, false)),
+      deps(&["jsr:@scope/c@1"])
+    );
+    assert_eq!(
+      lockfile.effective_workspace_deps(&options(false, true)),
+      deps(&["jsr:@scope/c@1", "npm:chalk@5"])
+    );
+    assert_eq!(
+      lockfile.effective_workspace_deps(&options(true, false)),
+      deps(&["jsr:@scope/c@1", "npm:chalk@5", "npm:nanoid@3"])
+    );
+    assert!(!lockfile.has_content_changed);
+  }
+
+  #[test]
+  fn clear_workspace() {
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "npm:chalk@5": "npm:chalk@5.0.0",
+      "npm:nanoid@3": "npm:nanoid@3.3.4"
+    },
+    "npm": {
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {}
+      },
+      "nanoid@3.3.4": {
+        "integrity": "sha512-nanoid",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {},
+  "workspace": {
+    "dependencies": ["npm:chalk@5"],
+    "members": {
+      "a": {
+        "packageJson": {
+          "dependencies": ["npm:nanoid@3"]
+        }
+      }
+    }
+  }
+}"#,
+      false,
+    )
+    .unwrap();
+    lockfile.clear_workspace();
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.as_json_string(),
+      r#"{
+  "version": "3",
+  "remote": {}
+}
+"#
+    );
+
+    let mut lockfile =
+      Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false);
+    lockfile.clear_workspace();
+    assert!(!lockfile.has_content_changed);
+  }
+
+  #[test]
+  fn add_workspace_dependency() {
+    let mut lockfile = setup(false).unwrap();
+    assert!(lockfile.add_workspace_dependency(
+      None,
+      "npm:nanoid@3".to_string(),
+      false
+    ));
+    assert!(lockfile.has_content_changed);
+    lockfile.has_content_changed = false;
+    assert!(!lockfile.add_workspace_dependency(
+      None,
+      "npm:nanoid@3".to_string(),
+      false
+    ));
+    assert!(!lockfile.has_content_changed);
+    assert!(lockfile.add_workspace_dependency(
+      Some("a"),
+      "npm:nanoid@3".to_string(),
+      true
+    ));
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.workspace_dep_reqs().collect::<Vec<_>>(),
+      vec!["npm:nanoid@3", "npm:nanoid@3"]
+    );
+    assert_eq!(
+      lockfile.content.workspace.members["a"]
+        .package_json
+        .dependencies,
+      BTreeSet::from(["npm:nanoid@3".to_string()])
+    );
+
+    // don't cause an empty lockfile to be written
+    let mut lockfile =
+      Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false);
+    assert!(lockfile.add_workspace_dependency(
+      Some("a"),
+      "npm:nanoid@3".to_string(),
+      false
+    ));
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(lockfile.workspace_dep_reqs().count(), 1);
+  }
+
+  #[test]
+  fn move_workspace_dependency() {
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "jsr:@std/path@^0.75": "jsr:@std/path@0.75.0",
+      "npm:chalk@5": "npm:chalk@5.0.0"
+    },
+    "jsr": {
+      "@std/path@0.75.0": {
+        "integrity": "sha256-path"
+      }
+    },
+    "npm": {
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {},
+  "workspace": {
+    "dependencies": ["jsr:@std/path@^0.75"],
+    "packageJson": {
+      "dependencies": ["npm:chalk@5"]
+    },
+    "members": {
+      "a": {}
+    }
+  }
+}"#,
+      false,
+    )
+    .unwrap();
+    let original_packages = lockfile.content.packages.clone();
+
+    assert_eq!(
+      lockfile.move_workspace_dependency(
+        "jsr:@std/path@^0.75",
+        WorkspaceMemberRef::Member("a"),
+        WorkspaceMemberRef::Root,
+      ),
+      Err(MoveError {
+        dep: "jsr:@std/path@^0.75".to_string(),
+        member: "workspace member 'a'".to_string(),
+      })
+    );
+    assert!(lockfile
+      .move_workspace_dependency(
+        "jsr:@std/path@^0.75",
+        WorkspaceMemberRef::Member("c"),
+        WorkspaceMemberRef::Root,
+      )
+      .is_err());
+    assert!(!lockfile.has_content_changed);
+
+    lockfile
+      .move_workspace_dependency(
+        "jsr:@std/path@^0.75",
+        WorkspaceMemberRef::Root,
+        WorkspaceMemberRef::Member("a"),
+      )
+      .unwrap();
+    assert!(lockfile.has_content_changed);
+    lockfile
+      .move_workspace_dependency(
+        "npm:chalk@5",
+        WorkspaceMemberRef::Root,
+        WorkspaceMemberRef::Member("b"),
+      )
+      .unwrap();
+    // nothing was pruned
+    assert_eq!(
+      serde_json::to_value(&lockfile.content.packages).unwrap(),
+      serde_json::to_value(original_packages).unwrap()
+    );
+    assert_eq!(
+      serde_json::to_value(&lockfile.content.workspace).unwrap(),
+      serde_json::json!({
+        "members": {
+          "a": {
+            "dependencies": ["jsr:@std/path@^0.75"]
+          },
+          "b": {
+            "packageJson": {
+              "dependencies": ["npm:chalk@5"]
+            }
+          }
+        }
+      })
+    );
+
+    // moving to the same place does nothing
+    lockfile.has_content_changed = false;
+    lockfile
+      .move_workspace_dependency(
+        "npm:chalk@5",
+        WorkspaceMemberRef::Member("b"),
+        WorkspaceMemberRef::Member("b"),
+      )
+      .unwrap();
+    assert!(!lockfile.has_content_changed);
+  }
+
+  #[test]
+  fn remove_workspace_member() {
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "npm:chalk@5": "npm:chalk@5.0.0",
+      "npm:nanoid@3": "npm:nanoid@3.3.4"
+    },
+    "npm": {
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {}
+      },
+      "nanoid@3.3.4": {
+        "integrity": "sha512-nanoid",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {},
+  "workspace": {
+    "members": {
+      "a": {
+        "dependencies": ["npm:chalk@5", "npm:nanoid@3"]
+      },
+      "b": {
+        "dependencies": ["npm:chalk@5"]
+      }
+    }
+  }
+}"#,
+      false,
+    )
+    .unwrap();
+    assert!(!lockfile.remove_workspace_member("c"));
+    assert!(!lockfile.has_content_changed);
+
+    assert!(lockfile.remove_workspace_member("a"));
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.as_json_string(),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "npm:chalk@5": "npm:chalk@5.0.0"
+    },
+    "npm": {
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {},
+  "workspace": {
+    "members": {
+      "b": {
+        "dependencies": [
+          "npm:chalk@5"
+        ]
+      }
+    }
+  }
+}
+"#
+    );
+
+    // doesn't cause an empty lockfile to be created
+    let mut lockfile =
+      Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false);
+    assert!(!lockfile.remove_workspace_member("a"));
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(lockfile.generation(), 0);
+  }
+
+  #[test]
+  fn workspace_dep_reqs() {
+    let lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "remote": {},
+  "workspace": {
+    "dependencies": ["jsr:@scope/a@1"],
+    "packageJson": {
+      "dependencies": ["npm:chalk@5"]
+    },
+    "members": {
+      "b": {
+        "dependencies": ["npm:nanoid@3"]
+      }
+    }
+  }
+}"#,
+      false,
+    )
+    .unwrap();
+    assert_eq!(
+      lockfile.workspace_dep_reqs().collect::<Vec<_>>(),
+      vec!["npm:chalk@5", "jsr:@scope/a@1", "npm:nanoid@3"]
+    );
+    let lockfile = setup(false).unwrap();
+    assert_eq!(lockfile.workspace_dep_reqs().count(), 0);
+  }
+
+  #[test]
+  fn content_to_value() {
+    let lockfile = setup(false).unwrap();
+    let value = lockfile.content.to_value();
+    assert_eq!(
+      value,
+      serde_json::from_str::<serde_json::Value>(&lockfile.as_json_string())
+        .unwrap()
+    );
+    assert_eq!(value["version"], "3");
+  }
+
+  #[test]
+  fn has_package() {
+    let mut lockfile = setup(false).unwrap();
+    assert!(lockfile.has_npm_package("nanoid@3.3.4"));
+    assert!(!lockfile.has_npm_package("chalk@5.0.0"));
+    assert!(!lockfile.has_jsr_package("@std/path@0.75.0"));
+    lockfile
+      .insert_package("@std/path@0.75.0".to_string(), "sha512-abc".to_string());
+    assert!(lockfile.has_jsr_package("@std/path@0.75.0"));
+  }
+
+  #[test]
+  fn jsr_package_deps() {
+    let mut lockfile = setup(false).unwrap();
+    assert!(lockfile.jsr_package_deps("@std/path@0.75.0").is_none());
+    lockfile.insert_jsr_package(JsrPackageLockfileInfo {
+      nv: "@std/path@0.75.0".to_string(),
+      integrity: "sha512-abc".to_string(),
+      dependencies: vec![
+        "npm:nanoid@3".to_string(),
+        "jsr:@std/assert@^0.75".to_string(),
+      ],
+    });
+    lockfile.insert_package_specifier(
+      "npm:nanoid@3".to_string(),
+      "npm:nanoid@3.3.4".to_string(),
+    );
+    assert_eq!(
+      lockfile
+        .jsr_package_deps("@std/path@0.75.0")
+        .unwrap()
+        .collect::<Vec<_>>(),
+      vec!["jsr:@std/assert@^0.75", "npm:nanoid@3"]
+    );
+    assert_eq!(
+      lockfile
+        .jsr_package_deps("@std/path@0.75.0")
+        .unwrap()
+        .filter_map(|req| lockfile.resolve_specifier(req))
+        .collect::<Vec<_>>(),
+      vec!["npm:nanoid@3.3.4"]
+    );
+  }
+
+  #[test]
+  fn resolve_specifier() {
+    let mut lockfile = setup(false).unwrap();
+    lockfile.insert_package_specifier(
+      "npm:nanoid@^3".to_string(),
+      "npm:nanoid@3.3.4".to_string(),
+    );
+    lockfile.insert_package_specifier(
+      "jsr:@std/path@^0.75".to_string(),
+      "jsr:@std/path@0.75.0".to_string(),
+    );
+    assert_eq!(
+      lockfile.resolve_specifier("npm:nanoid@^3"),
+      Some("npm:nanoid@3.3.4")
+    );
+    assert_eq!(lockfile.resolve_specifier("npm:chalk@^5"), None);
+
+    let package = lockfile.resolve_to_package("npm:nanoid@^3").unwrap();
+    assert!(matches!(package, PackageInfoRef::Npm(_)));
+    assert_eq!(
+      package.integrity(),
+      lockfile.content.packages.npm["nanoid@3.3.4"].integrity
+    );
+    // the package isn't in the lockfile yet
+    assert!(lockfile.resolve_to_package("jsr:@std/path@^0.75").is_none());
+    lockfile
+      .insert_package("@std/path@0.75.0".to_string(), "sha512-abc".to_string());
+    let package = lockfile.resolve_to_package("jsr:@std/path@^0.75").unwrap();
+    assert!(matches!(package, PackageInfoRef::Jsr(_)));
+    assert_eq!(package.integrity(), "sha512-abc");
+  }
+
+  #[test]
+  fn pin_specifier() {
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "jsr:@scope/a@1": "jsr:@scope/a@1.0.0",
+      "npm:chalk@5.0.1": "npm:chalk@5.0.1",
+      "npm:react-dom@^18": "npm:react-dom@18.2.0_react@18.2.0"
+    },
+    "jsr": {
+      "@scope/a@1.0.0": {
+        "integrity": "a"
+      },
+      "@scope/b@1.0.0": {
+        "integrity": "b",
+        "dependencies": ["jsr:@scope/a@1"]
+      }
+    },
+    "npm": {
+      "chalk@5.0.1": {
+        "integrity": "sha512-chalk",
+        "dependencies": {}
+      },
+      "react-dom@18.2.0_react@18.2.0": {
+        "integrity": "sha512-react-dom",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {}
+}"#,
+      false,
+    )
+    .unwrap();
+
+    // already exact
+    assert_eq!(
+      lockfile.pin_specifier("npm:chalk@5.0.1"),
+      Ok("npm:chalk@5.0.1".to_string())
+    );
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.pin_specifier("npm:chalk@4"),
+      Err(PinError::NotFound("npm:chalk@4".to_string()))
+    );
+
+    assert_eq!(
+      lockfile.pin_specifier("jsr:@scope/a@1"),
+      Ok("jsr:@scope/a@1.0.0".to_string())
+    );
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.pin_specifier("npm:react-dom@^18"),
+      Ok("npm:react-dom@18.2.0".to_string())
+    );
+    assert_eq!(
+      lockfile.content.packages.specifiers,
+      BTreeMap::from([
+        (
+          "jsr:@scope/a@1.0.0".to_string(),
+          "jsr:@scope/a@1.0.0".to_string()
+        ),
+        ("npm:chalk@5.0.1".to_string(), "npm:chalk@5.0.1".to_string()),
+        (
+          "npm:react-dom@18.2.0".to_string(),
+          "npm:react-dom@18.2.0_react@18.2.0".to_string()
+        ),
+      ])
+    );
+    assert_eq!(
+      lockfile.content.packages.jsr["@scope/b@1.0.0"].dependencies,
+      BTreeSet::from(["jsr:@scope/a@1.0.0".to_string()])
+    );
+
+    // unpinning uses a caret range
+    assert_eq!(
+      lockfile.unpin_specifier("jsr:@scope/a@1.0.0"),
+      Ok("jsr:@scope/a@^1.0.0".to_string())
+    );
+    assert_eq!(
+      lockfile.content.packages.jsr["@scope/b@1.0.0"].dependencies,
+      BTreeSet::from(["jsr:@scope/a@^1.0.0".to_string()])
+    );
+    assert_eq!(
+      lockfile.unpin_specifier("jsr:@scope/a@^1.0.0"),
+      Err(PinError::NotPinned(
+        "jsr:@scope/a@^1.0.0".to_string(),
+        "1.0.0".to_string()
+      ))
+    );
+  }
+
+  #[test]
+  fn normalize_specifiers() {
+    let mut lockfile = setup(false).unwrap();
+    for (req, id) in [
+      ("npm:nanoid@^3", "npm:nanoid@3.3.4"),
+      ("npm:nanoid@^3.0.0", "npm:nanoid@3.3.4"),
+      ("npm:nanoid@3.x", "npm:nanoid@3.3.4"),
+      ("npm:picocolors@>= 1", "npm:picocolors@1.0.0"),
+      ("jsr:@std/path@~0.75", "jsr:@std/path@0.75.0"),
+      ("jsr:@std/fs@0.75.0", "jsr:@std/fs@0.75.0"),
+    ] {
+      lockfile.insert_package_specifier(req.to_string(), id.to_string());
+    }
+    lockfile.insert_jsr_package(JsrPackageLockfileInfo {
+      nv: "@std/fs@0.75.0".to_string(),
+      integrity: "sha512-fs".to_string(),
+      dependencies: vec![
+        "jsr:@std/path@~0.75".to_string(),
+        "npm:nanoid@3.x".to_string(),
+      ],
+    });
+    lockfile.has_content_changed = false;
+
+    assert_eq!(lockfile.normalize_specifiers(), Ok(2));
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.content.packages.specifiers,
+      BTreeMap::from([
+        (
+          "jsr:@std/fs@0.75.0".to_string(),
+          "jsr:@std/fs@0.75.0".to_string()
+        ),
+        (
+          "jsr:@std/path@~0.75".to_string(),
+          "jsr:@std/path@0.75.0".to_string()
+        ),
+        (
+          "npm:nanoid@^3.0.0".to_string(),
+          "npm:nanoid@3.3.4".to_string()
+        ),
+        (
+          "npm:picocolors@>= 1".to_string(),
+          "npm:picocolors@1.0.0".to_string()
+        ),
+      ])
+    );
+    assert_eq!(
+      lockfile.content.packages.jsr["@std/fs@0.75.0"].dependencies,
+      BTreeSet::from([
+        "jsr:@std/path@~0.75".to_string(),
+        "npm:nanoid@^3.0.0".to_string(),
+      ])
+    );
+
+    lockfile.has_content_changed = false;
+    assert_eq!(lockfile.normalize_specifiers(), Ok(0));
+    assert!(!lockfile.has_content_changed);
+
+    // same requirement resolving to different packages
+    lockfile.insert_package_specifier(
+      "npm:nanoid@^3.0".to_string(),
+      "npm:nanoid@3.3.5".to_string(),
+    );
+    let specifiers = lockfile.content.packages.specifiers.clone();
+    let err = lockfile.normalize_specifiers().unwrap_err();
+    assert_eq!(
+      err,
+      RenameError::Conflict(
+        "npm:nanoid@^3.0.0".to_string(),
+        "npm:nanoid@3.3.5".to_string(),
+        "npm:nanoid@3.3.4".to_string(),
+      )
+    );
+    assert_eq!(lockfile.content.packages.specifiers, specifiers);
+  }
+
+  #[test]
+  fn rename_specifier() {
+    let mut lockfile = setup(false).unwrap();
+    lockfile.insert_package_specifier(
+      "jsr:@std/path@^0.75".to_string(),
+      "jsr:@std/path@0.75.0".to_string(),
+    );
+    lockfile.insert_package_specifier(
+      "jsr:@std/fs@0.75".to_string(),
+      "jsr:@std/fs@0.75.0".to_string(),
+    );
+    lockfile
+      .insert_package("@std/fs@0.75.0".to_string(), "sha512-fs".to_string());
+    lockfile
+      .try_add_package_deps(
+        "@std/fs@0.75.0",
+        ["jsr:@std/path@^0.75".to_string()].into_iter(),
+      )
+      .unwrap();
+    lockfile.has_content_changed = false;
+
+    assert_eq!(
+      lockfile.rename_specifier("jsr:@std/assert@1", "jsr:@std/assert@^1"),
+      Err(RenameError::NotFound("jsr:@std/assert@1".to_string()))
+    );
+    assert_eq!(
+      lockfile.rename_specifier("jsr:@std/path@^0.75", "jsr:@std/fs@0.75"),
+      Err(RenameError::Conflict(
+        "jsr:@std/fs@0.75".to_string(),
+        "jsr:@std/fs@0.75.0".to_string(),
+        "jsr:@std/path@0.75.0".to_string(),
+      ))
+    );
+    assert!(!lockfile.has_content_changed);
+
+    lockfile
+      .rename_specifier("jsr:@std/path@^0.75", "jsr:@std/path@0.75")
+      .unwrap();
+    assert!(lockfile.has_content_changed);
+    let packages = &lockfile.content.packages;
+    assert_eq!(
+      packages.specifiers.get("jsr:@std/path@0.75").unwrap(),
+      "jsr:@std/path@0.75.0"
+    );
+    assert!(!packages.specifiers.contains_key("jsr:@std/path@^0.75"));
+    assert_eq!(
+      packages.jsr["@std/fs@0.75.0"].dependencies,
+      BTreeSet::from(["jsr:@std/path@0.75".to_string()])
+    );
+  }
+
+  #[test]
+  fn update_npm_package() {
+    let mut lockfile =
+      Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false);
+    let package = |id: &str, deps: &[(&str, &str)]| NpmPackageLockfileInfo {
+      serialized_id: id.to_string(),
+      integrity: format!("sha512-{}", id),
+      dependencies: deps
+        .iter()
+        .map(|(name, id)| NpmPackageDependencyLockfileInfo {
+          name: name.to_string(),
+          id: id.to_string(),
+        })
+        .collect(),
+      ..Default::default()
+    };
+    lockfile.insert_npm_package(package("ansi-styles@4.1.0", &[]));
+    lockfile.insert_npm_package(package(
+      "chalk@5.0.0",
+      &[("ansi-styles", "ansi-styles@4.1.0")],
+    ));
+    lockfile.insert_npm_package(package(
+      "colors@1.0.0",
+      &[("ansi", "ansi-styles@4.1.0")],
+    ));
+    lockfile.insert_package_specifier(
+      "npm:ansi-styles@4".to_string(),
+      "npm:ansi-styles@4.1.0".to_string(),
+    );
+    lockfile.has_content_changed = false;
+
+    // same version
+    let report = lockfile.update_npm_package(
+      "ansi-styles",
+      "4.1.0",
+      package("ansi-styles@4.1.0", &[]),
+    );
+    assert_eq!(report, UpdateReport::default());
+    assert!(!lockfile.has_content_changed);
+
+    let report = lockfile.update_npm_package(
+      "ansi-styles",
+      "4.1.0",
+      package("ansi-styles@4.2.0", &[]),
+    );
+    assert_eq!(
+      report,
+      UpdateReport {
+        dependents: BTreeSet::from([
+          "chalk@5.0.0".to_string(),
+          "colors@1.0.0".to_string()
+        ]),
+        specifiers: BTreeSet::from(["npm:ansi-styles@4".to_string()]),
+        removed_old: true,
+      }
+    );
+    assert!(lockfile.has_content_changed);
+    let packages = &lockfile.content.packages;
+    assert_eq!(
+      packages.npm.keys().collect::<Vec<_>>(),
+      vec!["ansi-styles@4.2.0", "chalk@5.0.0", "colors@1.0.0"]
+    );
+    assert_eq!(
+      packages.npm["chalk@5.0.0"].dependencies["ansi-styles"],
+      "ansi-styles@4.2.0"
+    );
+    assert_eq!(
+      packages.npm["colors@1.0.0"].dependencies["ansi"],
+      "ansi-styles@4.2.0"
+    );
+    assert_eq!(
+      packages.specifiers["npm:ansi-styles@4"],
+      "npm:ansi-styles@4.2.0"
+    );
+  }
+
+  #[test]
+  fn set_specifiers() {
+    let mut lockfile = setup(false).unwrap();
+    let specifiers = BTreeMap::from([
+      ("npm:nanoid".to_string(), "npm:nanoid@3.3.4".to_string()),
+      (
+        "npm:picocolors".to_string(),
+        "npm:picocolors@1.0.0".to_string(),
+      ),
+    ]);
+    lockfile.set_specifiers(specifiers.clone());
+    assert!(lockfile.has_content_changed);
+
+    // identical
+    lockfile.has_content_changed = false;
+    lockfile.set_specifiers(specifiers.clone());
+    assert!(!lockfile.has_content_changed);
+
+    // only removes a key
+    lockfile.set_specifiers(BTreeMap::from([(
+      "npm:nanoid".to_string(),
+      "npm:nanoid@3.3.4".to_string(),
+    )]));
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      lockfile
+        .content
+        .packages
+        .specifiers
+        .keys()
+        .collect::<Vec<_>>(),
+      vec!["npm:nanoid"]
+    );
+
+    // merging keeps the other keys
+    lockfile.has_content_changed = false;
+    lockfile.merge_specifiers(BTreeMap::from([(
+      "npm:nanoid".to_string(),
+      "npm:nanoid@3.3.4".to_string(),
+    )]));
+    assert!(!lockfile.has_content_changed);
+    lockfile.merge_specifiers(BTreeMap::from([(
+      "npm:picocolors".to_string(),
+      "npm:picocolors@1.0.0".to_string(),
+    )]));
+    assert!(lockfile.has_content_changed);
+    assert_eq!(lockfile.content.packages.specifiers, specifiers);
+  }
+
+  #[test]
+  fn redirects_with_prefix() {
+    let mut lockfile = setup(false).unwrap();
+    for (from, to) in [
+      (
+        "https://deno.land/x/a/mod.ts",
+        "https://deno.land/x/a@1.0.0/mod.ts",
+      ),
+      (
+        "https://deno.land/x/b/mod.ts",
+        "https://deno.land/x/b@1.0.0/mod.ts",
+      ),
+      ("https://esm.sh/c", "https://esm.sh/c@1.0.0"),
+    ] {
+      lockfile.insert_redirect(from.to_string(), to.to_string());
+    }
+    assert_eq!(
+      lockfile
+        .redirects_with_prefix("https://deno.land/x/")
+        .collect::<Vec<_>>(),
+      vec![
+        (
+          "https://deno.land/x/a/mod.ts",
+          "https://deno.land/x/a@1.0.0/mod.ts"
+        ),
+        (
+          "https://deno.land/x/b/mod.ts",
+          "https://deno.land/x/b@1.0.0/mod.ts"
+        ),
+      ]
+    );
+    assert_eq!(lockfile.redirects_with_prefix("https://jsr.io/").count(), 0);
+    assert_eq!(lockfile.redirects_with_prefix("").count(), 3);
+  }
+
+  #[test]
+  fn remove_redirects_with_prefix() {
+    let mut lockfile = setup(false).unwrap();
+    for (from, to) in [
+      (
+        "https://deno.land/x/a",
+        "https://deno.land/x/a@1.0.0/mod.ts",
+      ),
+      (
+        "https://deno.land/x/a/mod.ts",
+        "https://deno.land/x/a@1.0.0/mod.ts",
+      ),
+      (
+        "https://deno.land/x/a0",
+        "https://deno.land/x/a0@1.0.0/mod.ts",
+      ),
+      ("https://deno.land/x/", "https://deno.land/x/index.ts"),
+      ("https://esm.sh/c", "https://esm.sh/c@1.0.0"),
+    ] {
+      lockfile.insert_redirect(from.to_string(), to.to_string());
+    }
+    lockfile.has_content_changed = false;
+
+    assert!(lockfile
+      .remove_redirects_with_prefix("https://jsr.io/")
+      .is_empty());
+    assert!(!lockfile.has_content_changed);
+
+    // a prefix that is a full key removes only the keys starting with it
+    assert_eq!(
+      lockfile.remove_redirects_with_prefix("https://deno.land/x/a/"),
+      vec![(
+        "https://deno.land/x/a/mod.ts".to_string(),
+        "https://deno.land/x/a@1.0.0/mod.ts".to_string()
+      )]
+    );
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.remove_redirects_with_prefix("https://deno.land/x/a0"),
+      vec![(
+        "https://deno.land/x/a0".to_string(),
+        "https://deno.land/x/a0@1.0.0/mod.ts".to_string()
+      )]
+    );
+    assert_eq!(
+      lockfile.content.redirects.keys().collect::<Vec<_>>(),
+      vec![
+        "https://deno.land/x/",
+        "https://deno.land/x/a",
+        "https://esm.sh/c"
+      ]
+    );
+    assert_eq!(
+      lockfile
+        .remove_redirects_with_prefix("https://deno.land/")
+        .len(),
+      2
+    );
+    assert_eq!(
+      lockfile.content.redirects.keys().collect::<Vec<_>>(),
+      vec!["https://esm.sh/c"]
+    );
+  }
+
+  #[test]
+  fn remove_package_specifier() {
+    let mut lockfile = setup(false).unwrap();
+    lockfile.insert_package_specifier(
+      "npm:nanoid".to_string(),
+      "npm:nanoid@3.3.4".to_string(),
+    );
+    lockfile.has_content_changed = false;
+
+    assert_eq!(lockfile.remove_package_specifier("npm:chalk"), None);
+    assert!(!lockfile.has_content_changed);
+
+    assert_eq!(
+      lockfile.remove_package_specifier("npm:nanoid"),
+      Some("npm:nanoid@3.3.4".to_string())
+    );
+    assert!(lockfile.has_content_changed);
+    assert!(!lockfile
+      .content
+      .packages
+      .specifiers
+      .contains_key("npm:nanoid"));
+    // the resolved package is kept
+    assert!(lockfile.content.packages.npm.contains_key("nanoid@3.3.4"));
+  }
+
+  #[test]
+  fn clear() {
+    let mut lockfile = setup(false).unwrap();
+    lockfile.clear();
+    assert!(lockfile.has_content_changed);
+    assert!(lockfile.content.is_empty());
+    assert_eq!(lockfile.content.version, "3");
+
+    lockfile.has_content_changed = false;
+    lockfile.clear();
+    assert!(!lockfile.has_content_changed);
+  }
+
+  #[test]
+  fn clear_packages() {
+    let mut lockfile = setup(false).unwrap();
+    let remote = lockfile.remote().clone();
+    lockfile.insert_redirect(
+      "https://deno.land/x/a/mod.ts".to_string(),
+      "https://deno.land/x/a@1.0.0/mod.ts".to_string(),
+    );
+    lockfile.clear_packages();
+    assert!(lockfile.has_content_changed);
+    assert!(lockfile.content.packages.is_empty());
+    assert_eq!(lockfile.remote(), &remote);
+    assert_eq!(lockfile.content.redirects.len(), 1);
+
+    lockfile.has_content_changed = false;
+    lockfile.clear_packages();
+    assert!(!lockfile.has_content_changed);
+  }
+
+  #[test]
+  fn clear_sections() {
+    let mut lockfile = setup(false).unwrap();
+    lockfile.insert_package_specifier(
+      "npm:nanoid".to_string(),
+      "npm:nanoid@3.3.4".to_string(),
+    );
+    lockfile
+      .insert_package("@std/path@0.75.0".to_string(), "sha512-abc".to_string());
+    lockfile.insert_redirect(
+      "https://deno.land/x/a/mod.ts".to_string(),
+      "https://deno.land/x/a@1.0.0/mod.ts".to_string(),
+    );
+    let remote = lockfile.remote().clone();
+
+    lockfile.has_content_changed = false;
+    lockfile.clear_npm();
+    assert!(lockfile.has_content_changed);
+    assert!(lockfile.content.packages.npm.is_empty());
+    assert_eq!(lockfile.content.packages.jsr.len(), 1);
+
+    lockfile.has_content_changed = false;
+    lockfile.clear_jsr();
+    assert!(lockfile.has_content_changed);
+    assert!(lockfile.content.packages.jsr.is_empty());
+    assert_eq!(lockfile.content.packages.specifiers.len(), 1);
+
+    lockfile.has_content_changed = false;
+    lockfile.clear_specifiers();
+    assert!(lockfile.has_content_changed);
+    assert!(lockfile.content.packages.specifiers.is_empty());
+    assert_eq!(lockfile.content.redirects.len(), 1);
+
+    lockfile.has_content_changed = false;
+    lockfile.clear_redirects();
+    assert!(lockfile.has_content_changed);
+    assert!(lockfile.content.redirects.is_empty());
+    assert_eq!(lockfile.remote(), &remote);
+
+    // already empty
+    lockfile.has_content_changed = false;
+    lockfile.clear_npm();
+    lockfile.clear_jsr();
+    lockfile.clear_specifiers();
+    lockfile.clear_redirects();
+    assert!(!lockfile.has_content_changed);
+  }
+
+  #[test]
+  fn clear_remote() {
+    let mut lockfile = setup(false).unwrap();
+    let len = lockfile.remote().len();
+    assert_eq!(lockfile.clear_remote(), len);
+    assert!(lockfile.has_content_changed);
+    assert!(lockfile.remote().is_empty());
+
+    // the empty remote section is still written in this version
+    let text = lockfile.as_json_string();
+    assert_eq!(
+      text,
+      r#"{
+  "version": "3",
+  "packages": {
+    "npm": {
+      "nanoid@3.3.4": {
+        "integrity": "sha512-MqBkQh/OHTS2egovRtLk45wEyNXwF+cokD+1YPf9u5VfJiRdAiRwB2froX5Co9Rh20xs4siNPm8naNotSD6RBw==",
+        "dependencies": {}
+      },
+      "picocolors@1.0.0": {
+        "integrity": "sha512-foobar",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {}
+}
+"#
+    );
+
+    // the original content doesn't bring back the removed entries
+    assert!(lockfile.original_text().unwrap().contains("https://"));
+    let lockfile =
+      Lockfile::with_lockfile_content(lockfile.filename.clone(), &text, false)
+        .unwrap();
+    assert!(lockfile.remote().is_empty());
+
+    let mut lockfile = lockfile;
+    assert_eq!(lockfile.clear_remote(), 0);
+    assert!(!lockfile.has_content_changed);
+  }
+
+  #[test]
+  fn extend_remote() {
+    let mut lockfile = setup(false).unwrap();
+    let len = lockfile.remote().len();
+    let (url, checksum) = lockfile
+      .remote()
+      .iter()
+      .map(|(url, checksum)| (url.clone(), checksum.clone()))
+      .next()
+      .unwrap();
+
+    assert_eq!(lockfile.extend_remote(Vec::new()), 0);
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(lockfile.extend_remote([(url.clone(), checksum)]), 0);
+    assert!(!lockfile.has_content_changed);
+
+    let other_url = lockfile.remote().keys().nth(1).unwrap().clone();
+    let other_checksum = lockfile.remote()[&other_url].clone();
+    let changed = lockfile.extend_remote([
+      (other_url.clone(), other_checksum),
+      (url.clone(), "changed".to_string()),
+      (
+        "https://deno.land/x/new/mod.ts".to_string(),
+        "new".to_string(),
+      ),
+    ]);
+    assert_eq!(changed, 2);
+    assert!(lockfile.has_content_changed);
+    assert_eq!(lockfile.remote().len(), len + 1);
+    assert_eq!(lockfile.remote()[&url], "changed");
+  }
+
+  #[test]
+  fn set_npm_integrity() {
+    let mut lockfile = setup(false).unwrap();
+    let integrity = lockfile.content.packages.npm["nanoid@3.3.4"]
+      .integrity
+      .clone();
+    assert_eq!(
+      lockfile.set_npm_integrity("nanoid@3.3.4", integrity),
+      Ok(false)
+    );
+    assert!(!lockfile.has_content_changed);
+
+    assert_eq!(
+      lockfile.set_npm_integrity("nanoid@3.3.4", "sha512-new".to_string()),
+      Ok(true)
+    );
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.content.packages.npm["nanoid@3.3.4"].integrity,
+      "sha512-new"
+    );
+
+    assert_eq!(
+      lockfile.set_npm_integrity("nanoid@9.9.9", "sha512-new".to_string()),
+      Err(MissingPackageError {
+        name: "nanoid@9.9.9".to_string()
+      })
+    );
+  }
+
+  #[test]
+  fn set_remote() {
+    let mut lockfile = setup(false).unwrap();
+    let remote = lockfile.remote().clone();
+
+    lockfile.set_remote(remote.clone());
+    assert!(!lockfile.has_content_changed);
+
+    let mut removed = remote.clone();
+    let url = removed.keys().next().unwrap().clone();
+    removed.remove(&url);
+    lockfile.set_remote(removed.clone());
+    assert!(lockfile.has_content_changed);
+    assert_eq!(lockfile.remote(), &removed);
+
+    lockfile.has_content_changed = false;
+    let mut modified = removed;
+    *modified.values_mut().next().unwrap() = "changed".to_string();
+    lockfile.set_remote(modified.clone());
+    assert!(lockfile.has_content_changed);
+    assert_eq!(lockfile.remote(), &modified);
+  }
+
+  #[test]
+  fn set_remote_returns_replaced() {
+    let mut lockfile = setup(false).unwrap();
+    let remote = lockfile.remote().clone();
+
+    assert_eq!(lockfile.set_remote(remote.clone()), remote);
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(lockfile.generation(), 0);
+
+    // stale modules are dropped
+    let mut verified = BTreeMap::new();
+    let url = remote.keys().next().unwrap().clone();
+    verified.insert(url.clone(), "new-checksum".to_string());
+    assert_eq!(lockfile.set_remote(verified.clone()), remote);
+    assert!(lockfile.has_content_changed);
+    assert_eq!(lockfile.remote(), &verified);
+    assert_eq!(lockfile.generation(), 1);
+  }
+
+  #[test]
+  fn retain_remote() {
+    let mut lockfile = setup(false).unwrap();
+    let len = lockfile.remote().len();
+    assert!(len > 1);
+
+    assert_eq!(lockfile.retain_remote(|_, _| true), 0);
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(lockfile.remote().len(), len);
+
+    let removed =
+      lockfile.retain_remote(|url, _| !url.ends_with("/async/delay.ts"));
+    assert_eq!(removed, 1);
+    assert!(lockfile.has_content_changed);
+    assert_eq!(lockfile.remote().len(), len - 1);
+
+    lockfile.has_content_changed = false;
+    assert_eq!(lockfile.retain_remote(|_, _| false), len - 1);
+    assert!(lockfile.has_content_changed);
+    assert!(lockfile.remote().is_empty());
+  }
+
+  #[test]
+  fn package_count() {
+    let mut lockfile = setup(false).unwrap();
+    lockfile
+      .insert_package("@std/path@0.75.0".to_string(), "sha512-abc".to_string());
+    let stats = lockfile.content.package_count();
+    assert_eq!(
+      stats,
+      LockfileStats {
+        remote: 2,
+        npm_packages: 2,
+        jsr_packages: 1,
+        specifiers: 0,
+        redirects: 0,
+      }
+    );
+    assert_eq!(
+      stats.to_string(),
+      "2 remote modules, 2 npm packages, 1 jsr package, 0 specifiers, 0 redirects"
+    );
+  }
+
+  #[test]
+  fn content_accessors() {
+    let lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "remote": {
+    "https://deno.land/x/a/mod.ts": "checksum"
+  },
+  "workspace": {
+    "dependencies": ["jsr:@scope/a@1"],
+    "members": {
+      "b": {
+        "packageJson": {
+          "dependencies": ["npm:chalk@5"]
+        }
+      }
+    }
+  }
+}"#,
+      false,
+    )
+    .unwrap();
+    let content = &lockfile.content;
+    assert_eq!(content.version(), "3");
+    assert_eq!(content.remote()["https://deno.land/x/a/mod.ts"], "checksum");
+    let workspace = content.workspace();
+    assert_eq!(
+      workspace.root().dependencies(),
+      &BTreeSet::from(["jsr:@scope/a@1".to_string()])
+    );
+    assert!(workspace.root().package_json_deps().is_empty());
+    assert_eq!(
+      workspace.members()["b"].package_json_deps(),
+      &BTreeSet::from(["npm:chalk@5".to_string()])
+    );
+  }
+
+  #[test]
+  fn workspace_config_package_json_round_trip() {
+    let text = r#"{
+  "version": "3",
+  "remote": {},
+  "workspace": {
+    "dependencies": [
+      "jsr:@scope/a@1"
+    ],
+    "packageJson": {
+      "dependencies": [
+        "npm:chalk@5"
+      ]
+    },
+    "members": {
+      "b": {
+        "packageJson": {
+          "dependencies": [
+            "npm:nanoid@3",
+            "npm:picocolors@1"
+          ]
+        }
+      },
+      "c": {
+        "dependencies": [
+          "jsr:@scope/c@1"
+        ]
+      }
+    }
+  }
+}
+"#;
+    let lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      text,
+      false,
+    )
+    .unwrap();
+    let config = WorkspaceConfig::from(lockfile.content.workspace.clone());
+    assert_eq!(
+      config.root.package_json_deps,
+      BTreeSet::from(["npm:chalk@5".to_string()])
+    );
+    assert_eq!(
+      config.members["b"],
+      WorkspaceMemberConfig {
+        dependencies: Default::default(),
+        package_json_deps: BTreeSet::from([
+          "npm:nanoid@3".to_string(),
+          "npm:picocolors@1".to_string(),
+        ]),
+      }
+    );
+    assert_eq!(
+      config.members["c"].dependencies,
+      BTreeSet::from(["jsr:@scope/c@1".to_string()])
+    );
+    assert!(config.members["c"].package_json_deps.is_empty());
+
+    // converting back gives the same section
+    let content = WorkspaceConfigContent::from(config.clone());
+    assert_eq!(
+      serde_json::to_value(content).unwrap(),
+      serde_json::to_value(&lockfile.content.workspace).unwrap(),
+    );
+
+    // setting the config on an empty lockfile writes the same text
+    let mut new_lockfile =
+      Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false);
+    new_lockfile.set_workspace_config(SetWorkspaceConfigOptions {
+      no_config: false,
+      no_npm: false,
+      config: config.clone(),
+    });
+    assert_eq!(new_lockfile.as_json_string(), text);
+
+    // and setting it again doesn't change anything
+    let mut lockfile = lockfile;
+    lockfile.set_workspace_config(SetWorkspaceConfigOptions {
+      no_config: false,
+      no_npm: false,
+      config,
+    });
+    assert!(!lockfile.has_content_changed);
+  }
+
+  #[test]
+  fn remove_remote_prefix() {
+    let mut lockfile = setup(false).unwrap();
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.01/mod.ts".to_string(),
+      "checksum-1".to_string(),
+    );
+    lockfile.insert_remote(
+      "https://deno.land/std@0.72.0/mod.ts".to_string(),
+      "checksum-2".to_string(),
+    );
+    lockfile.has_content_changed = false;
+
+    assert_eq!(lockfile.remove_remote_prefix("https://deno.land/x/"), 0);
+    assert!(!lockfile.has_content_changed);
+
+    assert_eq!(
+      lockfile.remove_remote_prefix("https://deno.land/std@0.71.0/"),
+      2
+    );
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.remote().keys().collect::<Vec<_>>(),
+      vec![
+        "https://deno.land/std@0.71.01/mod.ts",
+        "https://deno.land/std@0.72.0/mod.ts",
+      ]
+    );
+  }
+
+  #[test]
+  fn remote_trailing_slash_dupes() {
+    let mut lockfile = setup(false).unwrap();
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/async/delay.ts/".to_string(),
+      "checksum-1".to_string(),
+    );
+    lockfile.insert_remote(
+      "https://deno.land/x/other/".to_string(),
+      "checksum-2".to_string(),
+    );
+    assert_eq!(
+      lockfile.content.remote_trailing_slash_dupes(),
+      vec![(
+        "https://deno.land/std@0.71.0/async/delay.ts".to_string(),
+        "https://deno.land/std@0.71.0/async/delay.ts/".to_string(),
+      )]
+    );
+
+    lockfile.has_content_changed = false;
+    assert_eq!(lockfile.remove_remote_trailing_slash_dupes(), 1);
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.remote().keys().collect::<Vec<_>>(),
+      vec![
+        "https://deno.land/std@0.71.0/async/delay.ts",
+        "https://deno.land/std@0.71.0/textproto/mod.ts",
+        "https://deno.land/x/other/",
+      ]
+    );
+
+    lockfile.has_content_changed = false;
+    assert_eq!(lockfile.remove_remote_trailing_slash_dupes(), 0);
+    assert!(!lockfile.has_content_changed);
+  }
+
+  #[test]
+  fn insert_npm_package_optional_and_peer_deps() {
+    let mut lockfile =
+      Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false);
+    let dep = |name: &str, id: &str| NpmPackageDependencyLockfileInfo {
+      name: name.to_string(),
+      id: id.to_string(),
+    };
+    lockfile.insert_npm_package(NpmPackageLockfileInfo {
+      serialized_id: "a@1.0.0".to_string(),
+      integrity: "sha512-a".to_string(),
+      dependencies: vec![dep("b", "b@1.0.0"), dep("c", "c@1.0.0")],
+      optional_dependencies: vec![dep("b", "b@1.0.0")],
+      peer_dependencies: vec![dep("c", "c@1.0.0")],
+    });
+    lockfile.insert_npm_package(NpmPackageLockfileInfo {
+      serialized_id: "b@1.0.0".to_string(),
+      integrity: "sha512-b".to_string(),
+      dependencies: vec![],
+      ..Default::default()
+    });
+    let text = lockfile.as_json_string();
+    assert_eq!(
+      text,
+      r#"{
+  "version": "3",
+  "packages": {
+    "npm": {
+      "a@1.0.0": {
+        "integrity": "sha512-a",
+        "dependencies": {
+          "b": "b@1.0.0",
+          "c": "c@1.0.0"
+        },
+        "optionalDependencies": {
+          "b": "b@1.0.0"
+        },
+        "peerDependencies": {
+          "c": "c@1.0.0"
+        }
+      },
+      "b@1.0.0": {
+        "integrity": "sha512-b",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {}
+}
+"#
+    );
+    let loaded =
+      Lockfile::with_lockfile_content(lockfile.filename.clone(), &text, false)
+        .unwrap();
+    assert_eq!(loaded.content.packages.npm, lockfile.content.packages.npm);
+  }
+
+  #[test]
+  fn hand_edited_lockfile_text() {
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{"remote": {
+    "https://deno.land/x/b/mod.ts": "b",
+    "https://deno.land/x/a/mod.ts": "a"
+  },
+      "version": "3" }"#,
+      false,
+    )
+    .unwrap();
+    let expected = r#"{
+  "version": "3",
+  "remote": {
+    "https://deno.land/x/a/mod.ts": "a",
+    "https://deno.land/x/b/mod.ts": "b"
+  }
+}
+"#;
+    assert_eq!(lockfile.as_json_string(), expected);
+    assert!(!lockfile.has_content_changed);
+    assert!(lockfile.resolve_write_bytes().is_none());
+
+    lockfile.has_content_changed = true;
+    assert_eq!(lockfile.as_json_string(), expected);
+  }
+
+  #[test]
+  fn dependency_order_does_not_affect_output() {
+    let load = |text: &str| {
+      Lockfile::with_lockfile_content(
+        PathBuf::from("/foo/deno.lock"),
+        text,
+        false,
+      )
+      .unwrap()
+    };
+    let sorted = load(
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "npm:a@1": "npm:a@1.0.0",
+      "npm:b@1": "npm:b@1.0.0"
+    },
+    "npm": {
+      "a@1.0.0": {
+        "integrity": "sha512-a",
+        "dependencies": {
+          "b": "b@1.0.0",
+          "c": "c@1.0.0",
+          "d": "d@1.0.0"
+        },
+        "optionalDependencies": {
+          "c": "c@1.0.0",
+          "d": "d@1.0.0"
+        }
+      }
+    }
+  },
+  "remote": {}
+}"#,
+    );
+    let shuffled = load(
+      r#"{
+  "remote": {},
+  "packages": {
+    "npm": {
+      "a@1.0.0": {
+        "optionalDependencies": {
+          "d": "d@1.0.0",
+          "c": "c@1.0.0"
+        },
+        "dependencies": {
+          "d": "d@1.0.0",
+          "b": "b@1.0.0",
+          "c": "c@1.0.0"
+        },
+        "integrity": "sha512-a"
+      }
+    },
+    "specifiers": {
+      "npm:b@1": "npm:b@1.0.0",
+      "npm:a@1": "npm:a@1.0.0"
+    }
+  },
+  "version": "3"
+}"#,
+    );
+    assert_eq!(shuffled.as_json_string(), sorted.as_json_string());
+  }
+
+  #[test]
+  fn snapshot_restore() {
+    let mut lockfile = setup(false).unwrap();
+    let snapshot = lockfile.snapshot();
+    lockfile.clear_npm();
+    lockfile.insert_remote(
+      "https://deno.land/x/new/mod.ts".to_string(),
+      "new".to_string(),
+    );
+    let changed = lockfile.snapshot();
+    assert!(lockfile.resolve_write_bytes().is_some());
+
+    lockfile.restore(snapshot.clone());
+    assert!(!lockfile.has_content_changed);
+    assert!(lockfile.resolve_write_bytes().is_none());
+    assert_eq!(lockfile.content.packages.npm.len(), 2);
+
+    // content different from the loaded content
+    let generation = lockfile.generation();
+    lockfile.restore(changed);
+    assert!(lockfile.has_content_changed);
+    assert!(lockfile.content.packages.npm.is_empty());
+    assert!(lockfile.generation() > generation);
+
+    lockfile.restore(snapshot);
+    assert!(!lockfile.has_content_changed);
+
+    let mut lockfile =
+      Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false);
+    let snapshot = lockfile.snapshot();
+    lockfile.clear_npm();
+    lockfile.insert_package_specifier(
+      "npm:chalk@5".to_string(),
+      "npm:chalk@5.0.0".to_string(),
+    );
+    lockfile.restore(snapshot);
+    assert!(lockfile.resolve_write_bytes().is_none());
+  }
+
+  #[test]
+  fn frozen() {
+    let mut lockfile = setup(false).unwrap();
+    let (url, hash) = lockfile.remote().iter().next().unwrap();
+    let (url, hash) = (url.clone(), hash.clone());
+    let text = lockfile.as_json_string();
+    lockfile.freeze();
+    assert!(lockfile.is_frozen());
+
+    // unchanged entries are fine
+    lockfile.try_insert_remote(url.clone(), hash).unwrap();
+    let package = NpmPackageLockfileInfo {
+      serialized_id: "nanoid@3.3.4".to_string(),
+      integrity: lockfile.content.packages.npm["nanoid@3.3.4"]
+        .integrity
+        .clone(),
+      dependencies: Vec::new(),
+      ..Default::default()
+    };
+    lockfile.try_insert_npm_package(package.clone()).unwrap();
+    assert_eq!(
+      lockfile.try_insert_redirect(
+        "jsr:@std/path".to_string(),
+        "jsr:@std/path@0.75.0".to_string()
+      ),
+      Ok(InsertRedirectResult::RejectedJsrSpecifier)
+    );
+    lockfile
+      .try_set_workspace_config(SetWorkspaceConfigOptions {
+        config: Default::default(),
+        no_config: false,
+        no_npm: false,
+      })
+      .unwrap();
+    assert!(!lockfile.has_content_changed);
+
+    // changes error
+    assert_eq!(
+      lockfile.try_insert_remote(url.clone(), "changed".to_string()),
+      Err(FrozenError { entry: url.clone() })
+    );
+    assert_eq!(
+      lockfile.try_insert_npm_package(NpmPackageLockfileInfo {
+        integrity: "sha512-changed".to_string(),
+        ..package
+      }),
+      Err(FrozenError {
+        entry: "npm:nanoid@3.3.4".to_string()
+      })
+    );
+    assert_eq!(
+      lockfile.try_insert_jsr_package(JsrPackageLockfileInfo {
+        nv: "@std/path@0.75.0".to_string(),
+        integrity: "sha512-path".to_string(),
+        dependencies: Vec::new(),
+      }),
+      Err(FrozenError {
+        entry: "jsr:@std/path@0.75.0".to_string()
+      })
+    );
+    assert_eq!(
+      lockfile.try_insert_redirect(
+        "https://deno.land/x/a/mod.ts".to_string(),
+        "https://deno.land/x/a@1.0.0/mod.ts".to_string(),
+      ),
+      Err(FrozenError {
+        entry: "https://deno.land/x/a/mod.ts".to_string()
+      })
+    );
+    let err = lockfile
+      .try_insert_package_specifier(
+        "npm:nanoid@3".to_string(),
+        "npm:nanoid@3.3.4".to_string(),
+      )
+      .unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "The lockfile is frozen, but 'npm:nanoid@3' would change it. The lockfile is out of date."
+    );
+    assert_eq!(
+      lockfile.try_set_workspace_config(SetWorkspaceConfigOptions {
+        config: WorkspaceConfig {
+          root: WorkspaceMemberConfig {
+            dependencies: BTreeSet::from(["npm:nanoid@3".to_string()]),
+            package_json_deps: Default::default(),
+          },
+          members: Default::default(),
+        },
+        no_config: false,
+        no_npm: false,
+      }),
+      Err(FrozenError {
+        entry: "workspace".to_string()
+      })
+    );
+    assert!(!lockfile.has_content_changed);
+    assert!(!lockfile.has_frozen_violation());
+    assert_eq!(lockfile.as_json_string(), text);
+
+    // the other methods undo their changes
+    let generation = lockfile.generation();
+    lockfile.insert_remote(url.clone(), "changed".to_string());
+    assert!(lockfile.has_frozen_violation());
+    lockfile.insert_package_specifier(
+      "npm:nanoid@3".to_string(),
+      "npm:nanoid@3.3.4".to_string(),
+    );
+    lockfile.clear_npm();
+    lockfile.set_remote(Default::default());
+    lockfile.set_workspace_config(SetWorkspaceConfigOptions {
+      config: Default::default(),
+      no_config: true,
+      no_npm: false,
+    });
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(lockfile.generation(), generation);
+    assert_eq!(lockfile.as_json_string(), text);
+  }
+
+  #[test]
+  fn transaction() {
+    let mut lockfile = setup(false).unwrap();
+    let text = lockfile.as_json_string();
+
+    let mut transaction = lockfile.transaction();
+    transaction.insert_remote(
+      "https://deno.land/x/new/mod.ts".to_string(),
+      "new".to_string(),
+    );
+    transaction.clear_npm();
+    assert!(transaction.has_content_changed);
+    drop(transaction);
+    assert_eq!(lockfile.as_json_string(), text);
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(lockfile.generation(), 3);
+
+    // nothing changed
+    drop(lockfile.transaction());
+    assert_eq!(lockfile.generation(), 3);
+
+    let mut transaction = lockfile.transaction();
+    transaction.clear_npm();
+    transaction.commit();
+    assert!(lockfile.has_content_changed);
+    assert!(lockfile.content.packages.npm.is_empty());
+
+    // restores the changed flag when it was already set
+    let text = lockfile.as_json_string();
+    let mut transaction = lockfile.transaction();
+    transaction.clear_remote();
+    drop(transaction);
+    assert_eq!(lockfile.as_json_string(), text);
+    assert!(lockfile.has_content_changed);
+  }
+
+  #[test]
+  fn transaction_restores_direct_changes() {
+    let mut lockfile = setup(false).unwrap();
+    let text = lockfile.as_json_string();
+    let generation = lockfile.generation();
+
+    let mut transaction = lockfile.transaction();
+    transaction.content.redirects.insert(
+      "https://deno.land/x/a/mod.ts".to_string(),
+      "https://deno.land/x/a@1.0.0/mod.ts".to_string(),
+    );
+    transaction.has_content_changed = true;
+    drop(transaction);
+    assert_eq!(lockfile.as_json_string(), text);
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(lockfile.generation(), generation);
+  }
+
+  #[test]
+  fn comments() {
+    let text = r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "npm:chalk@5": "npm:chalk@5.0.0"
+    },
+    "npm": {
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {},
+  "$comments": {
+    "npm:chalk@5.0.0": "pinned until the esm migration"
+  }
+}
+"#;
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      text,
+      false,
+    )
+    .unwrap();
+    assert_eq!(
+      lockfile.content.comments["npm:chalk@5.0.0"],
+      "pinned until the esm migration"
+    );
+    assert!(lockfile.content.extra().is_empty());
+    assert_eq!(lockfile.as_json_string(), text);
+    assert!(lockfile.content.unreachable_packages().is_empty());
+
+    lockfile.insert_comment(
+      "npm:chalk@5.0.0".to_string(),
+      "pinned until the esm migration".to_string(),
+    );
+    assert!(!lockfile.has_content_changed);
+    lockfile.insert_comment("npm:chalk@5.0.0".to_string(), "other".to_string());
+    assert!(lockfile.has_content_changed);
+
+    lockfile.has_content_changed = false;
+    assert_eq!(lockfile.remove_comment("npm:nanoid@3.3.4"), None);
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.remove_comment("npm:chalk@5.0.0"),
+      Some("other".to_string())
+    );
+    assert!(lockfile.has_content_changed);
+    assert!(!lockfile.as_json_string().contains("$comments"));
+  }
+
+  #[test]
+  fn patches() {
+    let text = r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "npm:chalk@5": "npm:chalk@5.0.0"
+    },
+    "npm": {
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {}
+      },
+      "nanoid@3.3.4": {
+        "integrity": "sha512-nanoid",
+        "dependencies": {}
+      },
+      "picocolors@1.0.0": {
+        "integrity": "sha512-picocolors",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {},
+  "workspace": {
+    "dependencies": [
+      "npm:chalk@5"
+    ]
+  },
+  "patches": {
+    "npm:nanoid@3.3.4": {
+      "integrity": "sha512-patched",
+      "location": "./patches/nanoid"
+    }
+  }
+}
+"#;
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      text,
+      false,
+    )
+    .unwrap();
+    assert!(lockfile.content.extra().is_empty());
+    assert_eq!(lockfile.as_json_string(), text);
+    assert_eq!(
+      lockfile.content.unreachable_packages(),
+      BTreeSet::from(["npm:picocolors@1.0.0".to_string()])
+    );
+
+    // patched packages are kept when pruning
+    let report = lockfile.prune(Default::default());
+    assert_eq!(
+      report.npm_packages,
+      BTreeSet::from(["picocolors@1.0.0".to_string()])
+    );
+    assert!(lockfile.content.packages.npm.contains_key("nanoid@3.3.4"));
+    lockfile.set_workspace_config(SetWorkspaceConfigOptions {
+      config: Default::default(),
+      no_config: false,
+      no_npm: false,
+    });
+    assert_eq!(
+      lockfile.content.packages.npm.keys().collect::<Vec<_>>(),
+      vec!["nanoid@3.3.4"]
+    );
+
+    lockfile.has_content_changed = false;
+    let patch = PatchInfo {
+      integrity: "sha512-patched".to_string(),
+      location: "./patches/nanoid".to_string(),
+    };
+    lockfile.insert_patch("npm:nanoid@3.3.4".to_string(), patch.clone());
+    assert!(!lockfile.has_content_changed);
+    let other = PatchInfo {
+      integrity: "sha512-other".to_string(),
+      ..patch
+    };
+    lockfile.insert_patch("npm:nanoid@3.3.4".to_string(), other.clone());
+    assert!(lockfile.has_content_changed);
+
+    lockfile.has_content_changed = false;
+    assert_eq!(lockfile.remove_patch("npm:chalk@5.0.0"), None);
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(lockfile.remove_patch("npm:nanoid@3.3.4"), Some(other));
+    assert!(lockfile.has_content_changed);
+    assert!(!lockfile.as_json_string().contains("patches"));
+  }
+
+  #[test]
+  fn patched_package_dependencies_are_kept() {
+    let text = r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "npm:a@1": "npm:a@1.0.0"
+    },
+    "npm": {
+      "a@1.0.0": {
+        "integrity": "sha512-a",
+        "dependencies": {
+          "b": "b@1.0.0"
+        }
+      },
+      "b@1.0.0": {
+        "integrity": "sha512-b",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {},
+  "workspace": {
+    "dependencies": [
+      "npm:a@1"
+    ]
+  },
+  "patches": {
+    "npm:a@1.0.0": {
+      "integrity": "sha512-patched",
+      "location": "./patches/a"
+    },
+    "npm:ghost@1.0.0": {
+      "integrity": "sha512-ghost",
+      "location": "./patches/ghost"
+    }
+  }
+}
+"#;
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      text,
+      false,
+    )
+    .unwrap();
+    // patches without a package entry are missing from the graph
+    let graph = lockfile.graph_json();
+    assert_eq!(graph["missing"], serde_json::json!(["npm:ghost@1.0.0"]));
+    assert!(!graph["roots"]
+      .as_array()
+      .unwrap()
+      .contains(&serde_json::json!("npm:ghost@1.0.0")));
+    lockfile.remove_patch("npm:ghost@1.0.0");
+
+    lockfile.set_workspace_config(SetWorkspaceConfigOptions {
+      config: Default::default(),
+      no_config: false,
+      no_npm: false,
+    });
+    assert_eq!(
+      lockfile.content.packages.npm.keys().collect::<Vec<_>>(),
+      vec!["a@1.0.0", "b@1.0.0"]
+    );
+    assert!(lockfile.content.unreachable_packages().is_empty());
+    assert!(lockfile.validate().is_empty());
+  }
+
+  #[test]
+  fn insert_jsr_package() {
+    let mut lockfile =
+      Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false);
+    let info = |integrity: &str, deps: &[&str]| JsrPackageLockfileInfo {
+      nv: "@std/path@0.75.0".to_string(),
+      integrity: integrity.to_string(),
+      dependencies: deps.iter().map(|dep| dep.to_string()).collect(),
+    };
+    lockfile.insert_jsr_package(info("a", &["jsr:@std/assert@1"]));
+    assert!(lockfile.has_content_changed);
+
+    // unchanged
+    lockfile.has_content_changed = false;
+    lockfile.insert_jsr_package(info("a", &["jsr:@std/assert@1"]));
+    assert!(!lockfile.has_content_changed);
+
+    // only the dependencies
+    lockfile.insert_jsr_package(info("a", &["jsr:@std/fs@1"]));
+    assert!(lockfile.has_content_changed);
+    // only the integrity
+    lockfile.has_content_changed = false;
+    lockfile.insert_jsr_package(info("b", &["jsr:@std/fs@1"]));
+    assert!(lockfile.has_content_changed);
+    // both
+    lockfile.has_content_changed = false;
+    lockfile.insert_jsr_package(info("c", &["jsr:@std/fs@1", "npm:chalk@5"]));
+    assert!(lockfile.has_content_changed);
+    let package = &lockfile.content.packages.jsr["@std/path@0.75.0"];
+    assert_eq!(package.integrity, "c");
+    assert_eq!(
+      package.dependencies,
+      BTreeSet::from(["jsr:@std/fs@1".to_string(), "npm:chalk@5".to_string()])
+    );
+
+    // the old methods keep the other field
+    lockfile.has_content_changed = false;
+    lockfile.insert_package("@std/path@0.75.0".to_string(), "c".to_string());
+    assert!(!lockfile.has_content_changed);
+    lockfile.insert_package("@std/path@0.75.0".to_string(), "d".to_string());
+    assert!(lockfile.has_content_changed);
+    lockfile
+      .try_add_package_deps(
+        "@std/path@0.75.0",
+        ["jsr:@std/assert@1".to_string()].into_iter(),
+      )
+      .unwrap();
+    let package = &lockfile.content.packages.jsr["@std/path@0.75.0"];
+    assert_eq!(package.integrity, "d");
+    assert_eq!(package.dependencies.len(), 3);
+  }
+
+  #[test]
+  fn check_or_insert_lockfile_npm() {
+    let mut lockfile = setup(false).unwrap();
+
+    // already in lockfile
+    let npm_package = NpmPackageLockfileInfo {
+      serialized_id: "nanoid@3.3.4".to_string(),
+      integrity: "sha512-MqBkQh/OHTS2egovRtLk45wEyNXwF+cokD+1YPf9u5VfJiRdAiRwB2froX5Co9Rh20xs4siNPm8naNotSD6RBw==".to_string(),
+      dependencies: vec![],
+      ..Default::default()
+    };
+    lockfile.insert_npm_package(npm_package);
+    assert!(!lockfile.has_content_changed);
+
+    // insert package that exists already, but has slightly different properties
+    let npm_package = NpmPackageLockfileInfo {
+      serialized_id: "picocolors@1.0.0".to_string(),
+      integrity: "sha512-1fygroTLlHu66zi26VoTDv8yRgm0Fccecssto+MhsZ0D/DGW2sm8E8AjW7NU5VVTRt5GxbeZ5qBuJr+HyLYkjQ==".to_string(),
+      dependencies: vec![],
+      ..Default::default()
+    };
+    lockfile.insert_npm_package(npm_package);
+    assert!(lockfile.has_content_changed);
+
+    lockfile.has_content_changed = false;
+    let npm_package = NpmPackageLockfileInfo {
+      serialized_id: "source-map-js@1.0.2".to_string(),
+      integrity: "sha512-R0XvVJ9WusLiqTCEiGCmICCMplcCkIwwR11mOSD9CR5u+IXYdiseeEuXCVAjS54zqwkLcPNnmU4OeJ6tUrWhDw==".to_string(),
+      dependencies: vec![],
+      ..Default::default()
+    };
+    // Not present in lockfile yet, should be inserted
+    lockfile.insert_npm_package(npm_package.clone());
+    assert!(lockfile.has_content_changed);
+    lockfile.has_content_changed = false;
+
+    // this one should not say the lockfile has changed because it's the same
+    lockfile.insert_npm_package(npm_package);
+    assert!(!lockfile.has_content_changed);
+
+    let npm_package = NpmPackageLockfileInfo {
+      serialized_id: "source-map-js@1.0.2".to_string(),
+      integrity: "sha512-foobar".to_string(),
+      dependencies: vec![],
+      ..Default::default()
+    };
+    // Now present in lockfile, should be changed due to different integrity
+    lockfile.insert_npm_package(npm_package);
+    assert!(lockfile.has_content_changed);
+  }
+
+  #[test]
+  fn from_resolution() {
+    let graph = ResolutionGraph {
+      specifiers: BTreeMap::from([
+        (
+          "jsr:@std/path@^0.75".to_string(),
+          "jsr:@std/path@0.75.0".to_string(),
+        ),
+        ("npm:chalk@5".to_string(), "npm:chalk@5.0.0".to_string()),
+        ("npm:missing@1".to_string(), "npm:missing@1.0.0".to_string()),
+      ]),
+      jsr_packages: vec![JsrPackageLockfileInfo {
+        nv: "@std/path@0.75.0".to_string(),
+        integrity: "09154a97e18c".to_string(),
+        dependencies: vec![
+          "npm:chalk@5".to_string(),
+          "jsr:@std/assert@^0.75".to_string(),
+        ],
+      }],
+      npm_packages: vec![
+        NpmPackageLockfileInfo {
+          serialized_id: "chalk@5.0.0".to_string(),
+          integrity: "sha512-chalk".to_string(),
+          dependencies: vec![
+            NpmPackageDependencyLockfileInfo {
+              name: "ansi-styles".to_string(),
+              id: "ansi-styles@4.1.0".to_string(),
+            },
+            NpmPackageDependencyLockfileInfo {
+              name: "missing".to_string(),
+              id: "missing@1.0.0".to_string(),
+            },
+          ],
+          ..Default::default()
+        },
+        NpmPackageLockfileInfo {
+          serialized_id: "ansi-styles@4.1.0".to_string(),
+          integrity: "sha512-ansi".to_string(),
+          dependencies: vec![],
+          ..Default::default()
+        },
+      ],
+      redirects: BTreeMap::from([(
+        "https://deno.land/x/a/mod.ts".to_string(),
+        "https://deno.land/x/a@1.0.0/mod.ts".to_string(),
+      )]),
+      remotes: BTreeMap::from([(
+        "https://deno.land/x/a@1.0.0/mod.ts".to_string(),
+        "checksum".to_string(),
+      )]),
+    };
+    let err = LockfileContent::from_resolution(&graph).unwrap_err();
+    assert_eq!(
+      err.problems,
+      vec![
+        LockfileProblem::MissingSpecifierTarget {
+          req: "npm:missing@1".to_string(),
+          id: "npm:missing@1.0.0".to_string(),
+        },
+        LockfileProblem::MissingJsrDependency {
+          id: "jsr:@std/path@0.75.0".to_string(),
+          req: "jsr:@std/assert@^0.75".to_string(),
+        },
+        LockfileProblem::MissingNpmDependency {
+          id: "npm:chalk@5.0.0".to_string(),
+          dep_id: "missing@1.0.0".to_string(),
+        },
+      ]
+    );
+    assert_eq!(
+      err.to_string(),
+      "Unable to create the lockfile from the resolution. Specifier 'npm:missing@1' resolves to missing package 'npm:missing@1.0.0'. Package 'jsr:@std/path@0.75.0' depends on 'jsr:@std/assert@^0.75', which has no specifier. Package 'npm:chalk@5.0.0' depends on missing package 'missing@1.0.0'."
+    );
+
+    let mut graph = graph;
+    graph.specifiers.remove("npm:missing@1");
+    graph.jsr_packages[0].dependencies.pop();
+    graph.npm_packages[0].dependencies.pop();
+    let mut lockfile =
+      Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false);
+    lockfile.content = LockfileContent::from_resolution(&graph).unwrap();
+    assert_eq!(
+      lockfile.graph_json()["missing"],
+      serde_json::Value::Array(Vec::new())
+    );
+    assert!(lockfile
+      .content
+      .inconsistent_dependency_declarations()
+      .is_empty());
+    assert_eq!(
+      lockfile.as_json_string(),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "jsr:@std/path@^0.75": "jsr:@std/path@0.75.0",
+      "npm:chalk@5": "npm:chalk@5.0.0"
+    },
+    "jsr": {
+      "@std/path@0.75.0": {
+        "integrity": "09154a97e18c",
+        "dependencies": [
+          "npm:chalk@5"
+        ]
+      }
+    },
+    "npm": {
+      "ansi-styles@4.1.0": {
+        "integrity": "sha512-ansi",
+        "dependencies": {}
+      },
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {
+          "ansi-styles": "ansi-styles@4.1.0"
+        }
+      }
+    }
+  },
+  "redirects": {
+    "https://deno.land/x/a/mod.ts": "https://deno.land/x/a@1.0.0/mod.ts"
+  },
+  "remote": {
+    "https://deno.land/x/a@1.0.0/mod.ts": "checksum"
+  }
+}
+"#
+    );
+  }
+
+  #[test]
+  fn insert_npm_package_with_alias() {
+    let mut lockfile =
+      Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false);
+    let dep = |name: &str, id: &str| NpmPackageDependencyLockfileInfo {
+      name: name.to_string(),
+      id: id.to_string(),
+    };
+    lockfile.insert_npm_package(NpmPackageLockfileInfo {
+      serialized_id: "a@1.0.0".to_string(),
+      integrity: "sha512-a".to_string(),
+      dependencies: vec![
+        dep("chalk", "chalk@5.0.0"),
+        dep("chalk-v4", "chalk@4.1.2"),
+      ],
+      optional_dependencies: vec![dep("chalk-v4", "chalk@4.1.2")],
+      peer_dependencies: Vec::new(),
+    });
+    let text = lockfile.as_json_string();
+    assert_eq!(
+      text,
+      r#"{
+  "version": "3",
+  "packages": {
+    "npm": {
+      "a@1.0.0": {
+        "integrity": "sha512-a",
+        "dependencies": {
+          "chalk": "chalk@5.0.0",
+          "chalk-v4": "chalk@4.1.2"
+        },
+        "optionalDependencies": {
+          "chalk-v4": "chalk@4.1.2"
+        }
+      }
+    }
+  },
+  "remote": {}
+}
+"#
+    );
+
+    let loaded =
+      Lockfile::with_lockfile_content(lockfile.filename.clone(), &text, false)
+        .unwrap();
+    assert_eq!(loaded.content.packages.npm, lockfile.content.packages.npm);
+    assert_eq!(
+      loaded.content.packages.npm["a@1.0.0"].dependencies["chalk-v4"],
+      "chalk@4.1.2"
+    );
+    assert!(loaded
+      .content
+      .inconsistent_dependency_declarations()
+      .is_empty());
+  }
+
+  #[test]
+  fn npm_alias_of_other_package_round_trips() {
+    // "chalk" is an alias of chalk-fork while the real chalk is only
+    // depended on by the workspace
+    let text = r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "npm:a@1": "npm:a@1.0.0",
+      "npm:chalk@5": "npm:chalk@5.0.0"
+    },
+    "npm": {
+      "a@1.0.0": {
+        "integrity": "sha512-a",
+        "dependencies": {
+          "chalk": "chalk-fork@1.0.0",
+          "nanoid": "nanoid@3.3.4"
+        }
+      },
+      "chalk-fork@1.0.0": {
+        "integrity": "sha512-chalk-fork",
+        "dependencies": {}
+      },
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {}
+      },
+      "nanoid@3.3.4": {
+        "integrity": "sha512-nanoid",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {},
+  "workspace": {
+    "dependencies": [
+      "npm:a@1",
+      "npm:chalk@5"
+    ]
+  }
+}
+"#;
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      text,
+      false,
+    )
+    .unwrap();
+    assert_eq!(lockfile.as_json_string(), text);
+    assert!(lockfile.content.unreachable_packages().is_empty());
+
+    // removing the real chalk keeps the aliased package
+    lockfile.set_workspace_config(SetWorkspaceConfigOptions {
+      no_config: false,
+      no_npm: false,
+      config: WorkspaceConfig {
+        root: WorkspaceMemberConfig {
+          dependencies: BTreeSet::from(["npm:a@1".to_string()]),
+          package_json_deps: Default::default(),
+        },
+        members: Default::default(),
+      },
+    });
+    assert_eq!(
+      lockfile.content.packages.npm.keys().collect::<Vec<_>>(),
+      vec!["a@1.0.0", "chalk-fork@1.0.0", "nanoid@3.3.4"]
+    );
+    assert_eq!(
+      lockfile.content.packages.npm["a@1.0.0"].dependencies["chalk"],
+      "chalk-fork@1.0.0"
+    );
+
+    // and the stored form round trips exactly
+    let text = lockfile.as_json_string();
+    let loaded =
+      Lockfile::with_lockfile_content(lockfile.filename.clone(), &text, false)
+        .unwrap();
+    assert_eq!(loaded.as_json_string(), text);
+    assert_eq!(loaded.content.packages.npm, lockfile.content.packages.npm);
+  }
+
+  #[test]
+  fn npm_alias_from_version_4_round_trips() {
+    let v4_text = r#"{
+      "version": "4",
+      "specifiers": { "npm:a@1": "1.0.0" },
+      "npm": {
+        "a@1.0.0": {
+          "integrity": "sha512-a",
+          "dependencies": ["string-width@npm:string-width@4.2.3", "string-width-cjs@npm:string-width@4.2.3"]
+        },
+        "string-width@4.2.3": { "integrity": "sha512-sw" }
+      },
+      "remote": {}
+    }"#;
+    let lockfile = load_version_4(v4_text);
+    assert_eq!(
+      lockfile.content.packages.npm["a@1.0.0"].dependencies,
+      BTreeMap::from([
+        ("string-width".to_string(), "string-width@4.2.3".to_string()),
+        (
+          "string-width-cjs".to_string(),
+          "string-width@4.2.3".to_string()
+        ),
+      ])
+    );
+    let text = lockfile.as_json_string();
+    let loaded =
+      Lockfile::with_lockfile_content(lockfile.filename.clone(), &text, false)
+        .unwrap();
+    assert_eq!(loaded.as_json_string(), text);
+    assert!(loaded
+      .content
+      .inconsistent_dependency_declarations()
+      .is_empty());
+  }
+
+  #[test]
+  fn validate() {
+    let lockfile = setup(false).unwrap();
+    assert_eq!(lockfile.validate(), Vec::new());
+
+    let lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "jsr:@scope/a@1": "jsr:@scope/a@1.0.0",
+      "jsr:@scope/b@1": "jsr:@scope/b@1.0.0",
+      "npm:chalk@5": "chalk@5.0.0"
+    },
+    "jsr": {
+      "@scope/a@1.0.0": {
+        "integrity": "not valid",
+        "dependencies": ["jsr:@scope/c@1"]
+      }
+    },
+    "npm": {
+      "chalk": {
+        "integrity": "sha512-chalk",
+        "dependencies": {
+          "ansi-styles": "ansi-styles@4.1.0"
+        },
+        "optionalDependencies": {
+          "fsevents": "fsevents@2.3.2"
+        },
+        "peerDependencies": {
+          "react": "react@18.2.0"
+        }
+      }
+    }
+  },
+  "remote": {},
+  "patches": {
+    "npm:nanoid@3.3.4": {
+      "integrity": "sha512-patched",
+      "location": "./patches/nanoid"
+    }
+  }
+}"#,
+      false,
+    )
+    .unwrap();
+    let problems = lockfile.validate();
+    assert_eq!(
+      problems,
+      vec![
+        LockfileProblem::MissingSpecifierTarget {
+          req: "jsr:@scope/b@1".to_string(),
+          id: "jsr:@scope/b@1.0.0".to_string(),
+        },
+        LockfileProblem::InvalidSpecifierId {
+          req: "npm:chalk@5".to_string(),
+          id: "chalk@5.0.0".to_string(),
+        },
+        LockfileProblem::MissingJsrDependency {
+          id: "jsr:@scope/a@1.0.0".to_string(),
+          req: "jsr:@scope/c@1".to_string(),
+        },
+        LockfileProblem::UnknownIntegrityFormat {
+          id: "jsr:@scope/a@1.0.0".to_string(),
+          integrity: "not valid".to_string(),
+        },
+        LockfileProblem::MalformedPackageId {
+          id: "npm:chalk".to_string(),
+        },
+        LockfileProblem::MissingNpmDependency {
+          id: "npm:chalk".to_string(),
+          dep_id: "ansi-styles@4.1.0".to_string(),
+        },
+        LockfileProblem::MissingNpmDependency {
+          id: "npm:chalk".to_string(),
+          dep_id: "fsevents@2.3.2".to_string(),
+        },
+        LockfileProblem::MissingNpmDependency {
+          id: "npm:chalk".to_string(),
+          dep_id: "react@18.2.0".to_string(),
+        },
+        LockfileProblem::MissingPatchedPackage {
+          id: "npm:nanoid@3.3.4".to_string(),
+        },
+      ]
+    );
+    assert_eq!(
+      problems[0].to_string(),
+      "Specifier 'jsr:@scope/b@1' resolves to missing package 'jsr:@scope/b@1.0.0'."
+    );
+  }
+
+  #[test]
+  fn validate_optional_peer_and_patched_packages() {
+    let lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "npm:chalk@5": "npm:chalk@5.0.0"
+    },
+    "npm": {
+      "chalk@5.0.0_react@18.2.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {
+          "fsevents": "fsevents@2.3.2",
+          "react": "react@18.2.0"
+        },
+        "optionalDependencies": {
+          "fsevents": "fsevents@2.3.2"
+        },
+        "peerDependencies": {
+          "react": "react@18.2.0"
+        }
+      },
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {}
+      },
+      "fsevents@2.3.2": {
+        "integrity": "sha512-fsevents",
+        "dependencies": {}
+      },
+      "react@18.2.0": {
+        "integrity": "sha512-react",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {},
+  "patches": {
+    "npm:react@18.2.0": {
+      "integrity": "sha512-patched",
+      "location": "./patches/react"
+    }
+  }
+}"#,
+      false,
+    )
+    .unwrap();
+    assert_eq!(lockfile.validate(), Vec::new());
+  }
+
+  #[test]
+  fn inconsistent_dependency_declarations() {
+    let lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "packages": {
+    "npm": {
+      "@scope/a@1.0.0_b@1.0.0": {
+        "integrity": "sha512-a",
+        "dependencies": {
+          "b": "b@1.0.0",
+          "b-alias": "b@1.0.0"
+        }
+      },
+      "b@1.0.0": {
+        "integrity": "sha512-b",
+        "dependencies": {}
+      },
+      "c@1.0.0": {
+        "integrity": "sha512-c",
+        "dependencies": {
+          "@scope/a": "@scope/a@1.0.0_b@1.0.0",
+          "missing": "missing@1.0.0"
+        }
+      },
+      "d@1.0.0": {
+        "integrity": "sha512-d",
+        "dependencies": {
+          "not a name": "b@1.0.0"
+        }
+      }
+    }
+  },
+  "remote": {}
+}"#,
+      false,
+    )
+    .unwrap();
+    assert_eq!(
+      lockfile.content.inconsistent_dependency_declarations(),
+      vec!["d@1.0.0".to_string()]
+    );
+  }
+
+  #[test]
+  fn redirects_to_host() {
+    let mut lockfile =
+      Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false);
+    let redirects = [
+      (
+        "https://deno.land/x/a/mod.ts",
+        "https://cdn.example.com/a@1/mod.ts",
+      ),
+      (
+        "https://deno.land/x/b/mod.ts",
+        "https://deno.land/x/b@2/mod.ts",
+      ),
+      (
+        "https://deno.land/x/c/mod.ts",
+        "https://CDN.example.com:8080/c@3",
+      ),
+      ("https://deno.land/x/d/mod.ts", "cdn.example.com/not-a-url"),
+      (
+        "https://deno.land/x/e/mod.ts",
+        "https://user@cdn.example.com/e@4/mod.ts",
+      ),
+    ];
+    for (from, to) in redirects {
+      lockfile.insert_redirect(from.to_string(), to.to_string());
+    }
+    let to_cdn = lockfile.content.redirects_to_host("cdn.example.com");
+    assert_eq!(
+      to_cdn
+        .iter()
+        .map(|(from, _)| from.as_str())
+        .collect::<Vec<_>>(),
+      vec![
+        "https://deno.land/x/a/mod.ts",
+        "https://deno.land/x/c/mod.ts",
+        "https://deno.land/x/e/mod.ts",
+      ]
+    );
+    assert_eq!(
+      lockfile.content.redirects_to_host("deno.land"),
+      vec![(
+        &"https://deno.land/x/b/mod.ts".to_string(),
+        &"https://deno.land/x/b@2/mod.ts".to_string()
+      )]
+    );
+    assert!(lockfile.content.redirects_to_host("other.com").is_empty());
+  }
+
+  #[test]
+  fn preserves_unknown_top_level_keys() {
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "remote": {},
+  "newSection": {
+    "value": [1, 2]
+  },
+  "another": "text"
+}"#,
+      false,
+    )
+    .unwrap();
+    assert_eq!(
+      lockfile.content.extra().keys().collect::<Vec<_>>(),
+      vec!["another", "newSection"]
+    );
+    lockfile.insert_redirect(
+      "https://deno.land/x/a/mod.ts".to_string(),
+      "https://deno.land/x/a@1.0.0/mod.ts".to_string(),
+    );
+    assert_eq!(
+      lockfile.as_json_string(),
+      r#"{
+  "version": "3",
+  "redirects": {
+    "https://deno.land/x/a/mod.ts": "https://deno.land/x/a@1.0.0/mod.ts"
+  },
+  "remote": {},
+  "another": "text",
+  "newSection": {
+    "value": [
+      1,
+      2
+    ]
+  }
+}
+"#
+    );
+  }
+
+  #[test]
+  fn remove_npm_package() {
+    let mut lockfile = setup(false).unwrap();
+    lockfile.insert_package_specifier(
+      "npm:nanoid@3".to_string(),
+      "npm:nanoid@3.3.4".to_string(),
+    );
+    lockfile.insert_package_specifier(
+      "npm:nanoid@^3.3".to_string(),
+      "npm:nanoid@3.3.4".to_string(),
+    );
+    lockfile.insert_package_specifier(
+      "npm:picocolors".to_string(),
+      "npm:picocolors@1.0.0".to_string(),
+    );
+    lockfile.has_content_changed = false;
+
+    assert!(lockfile.remove_npm_package("nanoid@1.0.0").is_none());
+    assert!(!lockfile.has_content_changed);
+
+    let package = lockfile.remove_npm_package("nanoid@3.3.4").unwrap();
+    assert_eq!(package.integrity, "sha512-MqBkQh/OHTS2egovRtLk45wEyNXwF+cokD+1YPf9u5VfJiRdAiRwB2froX5Co9Rh20xs4siNPm8naNotSD6RBw==");
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.content.packages.npm.keys().collect::<Vec<_>>(),
+      vec!["picocolors@1.0.0"]
+    );
+    assert_eq!(
+      lockfile.content.packages.specifiers,
+      BTreeMap::from([(
+        "npm:picocolors".to_string(),
+        "npm:picocolors@1.0.0".to_string()
+      )])
+    );
+  }
+
+  #[test]
+  fn remove_jsr_package() {
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "jsr:@scope/a@1": "jsr:@scope/a@1.0.0",
+      "jsr:@scope/b@1": "jsr:@scope/b@1.0.0",
+      "jsr:@scope/b@^1.0": "jsr:@scope/b@1.0.0",
+      "npm:chalk@5": "npm:chalk@5.0.0"
+    },
+    "jsr": {
+      "@scope/a@1.0.0": {
+        "integrity": "a",
+        "dependencies": ["jsr:@scope/b@^1.0", "npm:chalk@5"]
+      },
+      "@scope/b@1.0.0": {
+        "integrity": "b",
+        "dependencies": ["npm:chalk@5"]
+      }
+    },
+    "npm": {
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {}
+}"#,
+      false,
+    )
+    .unwrap();
+
+    assert!(lockfile.remove_jsr_package("@scope/b@2.0.0").is_none());
+    assert!(!lockfile.has_content_changed);
+
+    let package = lockfile.remove_jsr_package("@scope/b@1.0.0").unwrap();
+    assert_eq!(package.integrity, "b");
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.as_json_string(),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "jsr:@scope/a@1": "jsr:@scope/a@1.0.0",
+      "npm:chalk@5": "npm:chalk@5.0.0"
+    },
+    "jsr": {
+      "@scope/a@1.0.0": {
+        "integrity": "a",
+        "dependencies": [
+          "npm:chalk@5"
+        ]
+      }
+    },
+    "npm": {
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {}
+}
+"#
+    );
+  }
+
+  #[test]
+  fn remove_scope() {
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "jsr:@evil/a@1": "jsr:@evil/a@1.0.0",
+      "jsr:@scope/b@1": "jsr:@scope/b@1.0.0",
+      "npm:@evil/c@1": "npm:@evil/c@1.0.0",
+      "npm:@evilx/d@1": "npm:@evilx/d@1.0.0",
+      "npm:chalk@5": "npm:chalk@5.0.0"
+    },
+    "jsr": {
+      "@evil/a@1.0.0": {
+        "integrity": "a",
+        "dependencies": ["npm:chalk@5"]
+      },
+      "@scope/b@1.0.0": {
+        "integrity": "b",
+        "dependencies": ["jsr:@evil/a@1", "npm:@evil/c@1"]
+      }
+    },
+    "npm": {
+      "@evil/c@1.0.0": {
+        "integrity": "sha512-c",
+        "dependencies": {}
+      },
+      "@evilx/d@1.0.0": {
+        "integrity": "sha512-d",
+        "dependencies": {
+          "@evil/c": "@evil/c@1.0.0"
+        }
+      },
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {
+          "c-alias": "@evil/c@1.0.0"
+        },
+        "optionalDependencies": {
+          "c-alias": "@evil/c@1.0.0"
+        }
+      }
+    }
+  },
+  "remote": {}
+}"#,
+      false,
+    )
+    .unwrap();
+
+    let removed = lockfile.remove_scope(RegistryKind::Npm, "@other");
+    assert_eq!(removed, RemovedPackages::default());
+    assert!(!lockfile.has_content_changed);
+
+    let removed = lockfile.remove_scope(RegistryKind::Npm, "@evil");
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      removed,
+      RemovedPackages {
+        npm_packages: BTreeSet::from(["@evil/c@1.0.0".to_string()]),
+        jsr_packages: BTreeSet::new(),
+        specifiers: BTreeSet::from(["npm:@evil/c@1".to_string()]),
+      }
+    );
+    let removed = lockfile.remove_scope(RegistryKind::Jsr, "evil");
+    assert_eq!(
+      removed,
+      RemovedPackages {
+        npm_packages: BTreeSet::new(),
+        jsr_packages: BTreeSet::from(["@evil/a@1.0.0".to_string()]),
+        specifiers: BTreeSet::from(["jsr:@evil/a@1".to_string()]),
+      }
+    );
+    assert_eq!(
+      lockfile.as_json_string(),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "jsr:@scope/b@1": "jsr:@scope/b@1.0.0",
+      "npm:@evilx/d@1": "npm:@evilx/d@1.0.0",
+      "npm:chalk@5": "npm:chalk@5.0.0"
+    },
+    "jsr": {
+      "@scope/b@1.0.0": {
+        "integrity": "b"
+      }
+    },
+    "npm": {
+      "@evilx/d@1.0.0": {
+        "integrity": "sha512-d",
+        "dependencies": {}
+      },
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {}
+}
+"#
+    );
+  }
+
+  #[test]
+  fn lockfile_with_redirects() {
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "redirects": {
+    "https://deno.land/x/std/mod.ts": "https://deno.land/std@0.190.0/mod.ts"
+  },
+  "remote": {}
+}"#,
+      false,
+    )
+    .unwrap();
+    lockfile.content.redirects.insert(
+      "https://deno.land/x/other/mod.ts".to_string(),
+      "https://deno.land/x/other@0.1.0/mod.ts".to_string(),
+    );
+    assert_eq!(
+      lockfile.as_json_string(),
+      r#"{
+  "version": "3",
+  "redirects": {
+    "https://deno.land/x/other/mod.ts": "https://deno.land/x/other@0.1.0/mod.ts",
+    "https://deno.land/x/std/mod.ts": "https://deno.land/std@0.190.0/mod.ts"
+  },
+  "remote": {}
+}
+"#,
+    );
+  }
+
+  #[test]
+  fn test_insert_redirect() {
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "redirects": {
+    "https://deno.land/x/std/mod.ts": "https://deno.land/std@0.190.0/mod.ts"
+  },
+  "remote": {}
+}"#,
+      false,
+    )
+    .unwrap();
+    assert_eq!(
+      lockfile.insert_redirect(
+        "https://deno.land/x/std/mod.ts".to_string(),
+        "https://deno.land/std@0.190.0/mod.ts".to_string(),
+      ),
+      InsertRedirectResult::Unchanged
+    );
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.insert_redirect(
+        "jsr:@std/path".to_string(),
+        "jsr:@std/path@0.75.0".to_string(),
+      ),
+      InsertRedirectResult::RejectedJsrSpecifier
+    );
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.insert_redirect(
+        "https://deno.land/x/std/mod.ts".to_string(),
+        "https://deno.land/std@0.190.1/mod.ts".to_string(),
+      ),
+      InsertRedirectResult::Updated
+    );
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.insert_redirect(
+        "https://deno.land/x/std/other.ts".to_string(),
+        "https://deno.land/std@0.190.1/other.ts".to_string(),
+      ),
+      InsertRedirectResult::Inserted
+    );
+    assert_eq!(
+      lockfile.as_json_string(),
+      r#"{
+  "version": "3",
+  "redirects": {
+    "https://deno.land/x/std/mod.ts": "https://deno.land/std@0.190.1/mod.ts",
+    "https://deno.land/x/std/other.ts": "https://deno.land/std@0.190.1/other.ts"
+  },
+  "remote": {}
+}
+"#,
+    );
+  }
+
+  #[test]
+  fn insert_jsr_redirect_when_allowed() {
+    let mut lockfile =
+      Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false);
+    lockfile.redirects_options.allow_jsr = true;
+    assert_eq!(
+      lockfile.insert_redirect(
+        "jsr:@std/path".to_string(),
+        "jsr:@std/path@0.75.0".to_string(),
+      ),
+      InsertRedirectResult::Inserted
+    );
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.content.redirects.get("jsr:@std/path").unwrap(),
+      "jsr:@std/path@0.75.0"
+    );
+  }
+
+  #[test]
+  fn test_insert_jsr() {
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "jsr:path": "jsr:@std/path@0.75.0"
+    }
+  },
+  "remote": {}
+}"#,
+      false,
+    )
+    .unwrap();
+    lockfile.insert_package_specifier(
+      "jsr:path".to_string(),
+      "jsr:@std/path@0.75.0".to_string(),
+    );
+    assert!(!lockfile.has_content_changed);
+    lockfile.insert_package_specifier(
+      "jsr:path".to_string(),
+      "jsr:@std/path@0.75.1".to_string(),
+    );
+    assert!(lockfile.has_content_changed);
+    lockfile.insert_package_specifier(
+      "jsr:@foo/bar@^2".to_string(),
+      "jsr:@foo/bar@2.1.2".to_string(),
+    );
+    assert_eq!(
+      lockfile.as_json_string(),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "jsr:@foo/bar@^2": "jsr:@foo/bar@2.1.2",
+      "jsr:path": "jsr:@std/path@0.75.1"
+    }
+  },
+  "remote": {}
+}
+"#,
+    );
+  }
+
+  #[test]
+  fn try_insert_package_specifier() {
+    let mut lockfile =
+      Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false);
+    lockfile
+      .try_insert_package_specifier(
+        "jsr:@std/path@^0.75".to_string(),
+        "jsr:@std/path@0.75.0".to_string(),
+      )
+      .unwrap();
+    lockfile
+      .try_insert_package_specifier(
+        "npm:@types/node".to_string(),
+        "npm:@types/node@18.0.0".to_string(),
+      )
+      .unwrap();
+    assert!(lockfile.has_content_changed);
+    lockfile.has_content_changed = false;
+
+    let invalid = [
+      ("jsr;foo", "jsr:foo@1.0.0"),
+      ("npm:", "npm:foo@1.0.0"),
+      ("npm:foo bar", "npm:foo@1.0.0"),
+      ("jsr:foo", "jsr;foo@1.0.0"),
+      ("jsr:foo", "jsr:foo"),
+      ("jsr:foo", "jsr:foo@"),
+      ("jsr:@scope/foo", "jsr:@scope/foo"),
+      ("jsr:foo", "npm:foo@1.0.0"),
+    ];
+    for (req, id) in invalid {
+      let result =
+        lockfile.try_insert_package_specifier(req.to_string(), id.to_string());
+      assert!(
+        matches!(result, Err(Error::InvalidPackageSpecifier(..))),
+        "{} -> {}",
+        req,
+        id
+      );
+    }
+    assert_eq!(
+      lockfile
+        .try_insert_package_specifier(
+          "jsr;foo".to_string(),
+          "jsr:foo@1.0.0".to_string()
+        )
+        .unwrap_err()
+        .to_string(),
+      "Invalid package specifier 'jsr;foo'. Expected a package requirement starting with npm: or jsr:."
+    );
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(lockfile.content.packages.specifiers.len(), 2);
+  }
+
+  #[test]
+  fn insert_package_specifier_checked() {
+    let mut lockfile = setup(false).unwrap();
+    lockfile
+      .insert_package_specifier_checked(
+        "npm:nanoid@3".to_string(),
+        "npm:nanoid@3.3.4".to_string(),
+      )
+      .unwrap();
+    let missing = MissingPackageError {
+      name: "npm:chalk@5.0.0".to_string(),
+    };
+    assert_eq!(
+      lockfile.insert_package_specifier_checked(
+        "npm:chalk@5".to_string(),
+        "npm:chalk@5.0.0".to_string(),
+      ),
+      Err(missing.clone())
+    );
+    assert_eq!(
+      lockfile.insert_package_specifier_checked(
+        "jsr:@std/path@^0.75".to_string(),
+        "jsr:@std/path@0.75.0".to_string(),
+      ),
+      Err(MissingPackageError {
+        name: "jsr:@std/path@0.75.0".to_string()
+      })
+    );
+    assert_eq!(lockfile.content.packages.specifiers.len(), 1);
+
+    // opt-in for the validating method
+    lockfile
+      .try_insert_package_specifier(
+        "npm:chalk@5".to_string(),
+        "npm:chalk@5.0.0".to_string(),
+      )
+      .unwrap();
+    assert_eq!(lockfile.verify_specifier_targets(), Err(missing.clone()));
+    lockfile.content.packages.specifiers.remove("npm:chalk@5");
+    lockfile.strict_specifiers = true;
+    let err = lockfile
+      .try_insert_package_specifier(
+        "npm:chalk@5".to_string(),
+        "npm:chalk@5.0.0".to_string(),
+      )
+      .unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "Package 'npm:chalk@5.0.0' was not found in the lockfile."
+    );
+
+    assert!(!lockfile
+      .content
+      .packages
+      .specifiers
+      .contains_key("npm:chalk@5"));
+
+    // inserted and reported by the other method since the package
+    // may be inserted after the specifier
+    lockfile.insert_package_specifier(
+      "npm:chalk@5".to_string(),
+      "npm:chalk@5.0.0".to_string(),
+    );
+    assert_eq!(
+      lockfile.validate(),
+      vec![LockfileProblem::MissingSpecifierTarget {
+        req: "npm:chalk@5".to_string(),
+        id: "npm:chalk@5.0.0".to_string(),
+      }]
+    );
+    assert_eq!(lockfile.verify_specifier_targets(), Err(missing));
+    lockfile.insert_npm_package(NpmPackageLockfileInfo {
+      serialized_id: "chalk@5.0.0".to_string(),
+      integrity: "sha512-chalk".to_string(),
+      dependencies: Vec::new(),
+      ..Default::default()
+    });
+    assert_eq!(lockfile.verify_specifier_targets(), Ok(()));
+  }
+
+  #[test]
+  fn insert_package_specifier_normalization() {
+    let mut lockfile =
+      Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false);
+    lockfile.insert_package_specifier(
+      "npm:React@18".to_string(),
+      "npm:react@18.2.0".to_string(),
+    );
+    lockfile.insert_package_specifier(
+      "npm:react@18".to_string(),
+      "npm:react@18.2.0".to_string(),
+    );
+    // off by default
+    assert_eq!(lockfile.content.packages.specifiers.len(), 2);
+
+    let mut lockfile =
+      Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false);
+    lockfile.specifier_normalization = SpecifierNormalization {
+      lowercase_names: true,
+      trim_whitespace: true,
+    };
+    lockfile.insert_package_specifier(
+      " npm:React@18-Beta ".to_string(),
+      "npm:react@18.2.0".to_string(),
+    );
+    lockfile.has_content_changed = false;
+    lockfile.insert_package_specifier(
+      "npm:react@18-Beta".to_string(),
+      "npm:react@18.2.0".to_string(),
+    );
+    assert!(!lockfile.has_content_changed);
+    lockfile
+      .try_insert_package_specifier(
+        "jsr:@Std/Path".to_string(),
+        "jsr:@std/path@0.75.0".to_string(),
+      )
+      .unwrap();
+    assert_eq!(
+      lockfile.content.packages.specifiers,
+      BTreeMap::from([
+        (
+          "jsr:@std/path".to_string(),
+          "jsr:@std/path@0.75.0".to_string()
+        ),
+        (
+          "npm:react@18-Beta".to_string(),
+          "npm:react@18.2.0".to_string()
+        ),
+      ])
+    );
+  }
+
+  #[test]
+  fn canonicalize() {
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      " jsr:@std/path": "jsr:@std/path@0.75.0 ",
+      "jsr:@std/path": "jsr:@std/path@0.75.0",
+      "npm:React@18": "npm:react@18.2.0"
+    },
+    "jsr": {
+      "@std/path@0.75.0 ": {
+        "integrity": "09154A97E18C",
+        "dependencies": ["npm:React@18 "]
+      }
+    },
+    "npm": {
+      "react@18.2.0": {
+        "integrity": "SHA512-AbCd==",
+        "dependencies": {
+          " loose-envify": "loose-envify@1.4.0"
+        }
+      }
+    }
+  },
+  "redirects": {
+    "https://deno.land/x/a ": "https://deno.land/x/a@1.0.0"
+  },
+  "remote": {
+    "https://deno.land/x/a@1.0.0": "ABCDEF"
+  },
+  "workspace": {
+    "dependencies": [" jsr:@std/path"],
+    "members": {
+      "a": {
+        "packageJson": {
+          "dependencies": ["npm:React@18"]
+        }
+      }
+    }
+  }
+}"#,
+      false,
+    )
+    .unwrap();
+    lockfile.specifier_normalization.lowercase_names = true;
+    assert!(lockfile.canonicalize());
+    assert!(lockfile.has_content_changed);
+    assert_eq!(
+      lockfile.as_json_string(),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "jsr:@std/path": "jsr:@std/path@0.75.0",
+      "npm:react@18": "npm:react@18.2.0"
+    },
+    "jsr": {
+      "@std/path@0.75.0": {
+        "integrity": "09154a97e18c",
+        "dependencies": [
+          "npm:react@18"
+        ]
+      }
+    },
+    "npm": {
+      "react@18.2.0": {
+        "integrity": "sha512-AbCd==",
+        "dependencies": {
+          "loose-envify": "loose-envify@1.4.0"
+        }
+      }
+    }
+  },
+  "redirects": {
+    "https://deno.land/x/a": "https://deno.land/x/a@1.0.0"
+  },
+  "remote": {
+    "https://deno.land/x/a@1.0.0": "abcdef"
+  },
+  "workspace": {
+    "dependencies": [
+      "jsr:@std/path"
+    ],
+    "members": {
+      "a": {
+        "packageJson": {
+          "dependencies": [
+            "npm:react@18"
+          ]
+        }
+      }
+    }
+  }
+}
+"#
+    );
+
+    // nothing to change the second time
+    lockfile.has_content_changed = false;
+    assert!(!lockfile.canonicalize());
+    assert!(!lockfile.has_content_changed);
+  }
+
+  #[test]
+  fn remove_package_and_orphans() {
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "jsr:@scope/a@1": "jsr:@scope/a@1.0.0",
+      "npm:ansi-styles@4": "npm:ansi-styles@4.1.0",
+      "npm:chalk@5": "npm:chalk@5.0.0"
+    },
+    "jsr": {
+      "@scope/a@1.0.0": {
+        "integrity": "a",
+        "dependencies": ["npm:ansi-styles@4"]
+      }
+    },
+    "npm": {
+      "ansi-styles@4.1.0": {
+        "integrity": "sha512-ansi",
+        "dependencies": {}
+      },
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {
+          "ansi-styles": "ansi-styles@4.1.0",
+          "supports-color": "supports-color@1.0.0"
+        }
+      },
+      "supports-color@1.0.0": {
+        "integrity": "sha512-supports",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {},
+  "workspace": {
+    "dependencies": ["jsr:@scope/a@1", "npm:chalk@5"]
+  }
+}"#,
+      false,
+    )
+    .unwrap();
+
+    let removed = lockfile.remove_package_and_orphans("npm:chalk@5");
+    assert_eq!(
+      removed,
+      RemovedPackages {
+        npm_packages: BTreeSet::from([
+          "chalk@5.0.0".to_string(),
+          "supports-color@1.0.0".to_string(),
+        ]),
+        jsr_packages: BTreeSet::new(),
+        specifiers: BTreeSet::from(["npm:chalk@5".to_string()]),
+      }
+    );
+    assert!(lockfile.has_content_changed);
+    let packages = &lockfile.content.packages;
+    // shared with the jsr package so it's kept
+    assert_eq!(
+      packages.npm.keys().collect::<Vec<_>>(),
+      vec!["ansi-styles@4.1.0"]
+    );
+    assert_eq!(
+      packages.specifiers.keys().collect::<Vec<_>>(),
+      vec!["jsr:@scope/a@1", "npm:ansi-styles@4"]
+    );
+
+    // the jsr package still depends on the requirement
+    lockfile.has_content_changed = false;
+    let removed = lockfile.remove_package_and_orphans("npm:ansi-styles@4");
+    assert_eq!(removed, RemovedPackages::default());
+    assert!(!lockfile.has_content_changed);
+  }
+
+  #[test]
+  fn subtract_and_remove_fragment() {
+    let base = setup(false).unwrap();
+    let mut derived = base.clone();
+    derived.insert_package_specifier(
+      "jsr:@std/path@^0.75".to_string(),
+      "jsr:@std/path@0.75.0".to_string(),
+    );
+    derived.insert_jsr_package(JsrPackageLockfileInfo {
+      nv: "@std/path@0.75.0".to_string(),
+      integrity: "sha512-path".to_string(),
+      dependencies: vec!["npm:nanoid@3".to_string()],
+    });
+    derived.insert_redirect(
+      "https://deno.land/x/a/mod.ts".to_string(),
+      "https://deno.land/x/a@1.0.0/mod.ts".to_string(),
+    );
+    let url = base.remote().keys().next().unwrap().clone();
+    derived.insert_remote(url.clone(), "changed".to_string());
+
+    let fragment = derived.content.subtract(&base.content);
+    assert_eq!(
+      fragment.to_value(),
+      serde_json::json!({
+        "version": "3",
+        "packages": {
+          "specifiers": {
+            "jsr:@std/path@^0.75": "jsr:@std/path@0.75.0"
+          },
+          "jsr": {
+            "@std/path@0.75.0": {
+              "integrity": "sha512-path",
+              "dependencies": ["npm:nanoid@3"]
+            }
+          }
+        },
+        "redirects": {
+          "https://deno.land/x/a/mod.ts": "https://deno.land/x/a@1.0.0/mod.ts"
+        },
+        "remote": {
+          url.clone(): "changed"
+        }
+      })
+    );
+    assert!(base.content.subtract(&base.content).is_empty());
+
+    // base + fragment is the derived lockfile
+    let mut merged = base.clone();
+    let packages = &mut merged.content.packages;
+    packages
+      .specifiers
+      .extend(fragment.packages.specifiers.clone());
+    packages.jsr.extend(fragment.packages.jsr.clone());
+    packages.npm.extend(fragment.packages.npm.clone());
+    merged.content.redirects.extend(fragment.redirects.clone());
+    merged.content.remote.extend(fragment.remote.clone());
+    assert_eq!(merged.as_json_string(), derived.as_json_string());
+
+    // only entries with the same value are removed
+    derived.insert_redirect(
+      "https://deno.land/x/a/mod.ts".to_string(),
+      "https://deno.land/x/a@2.0.0/mod.ts".to_string(),
+    );
+    derived.has_content_changed = false;
+    let report = derived.remove_fragment(&fragment);
+    assert_eq!(
+      report,
+      FragmentRemovalReport {
+        redirects: BTreeSet::from(["https://deno.land/x/a/mod.ts".to_string()]),
+        ..Default::default()
+      }
+    );
+    assert!(derived.has_content_changed);
+    assert!(derived.content.packages.specifiers.is_empty());
+    assert!(derived.content.packages.jsr.is_empty());
+    assert!(!derived.remote().contains_key(&url));
+    assert_eq!(derived.content.redirects.len(), 1);
+
+    derived.has_content_changed = false;
+    derived.remove_fragment(&fragment);
+    assert!(!derived.has_content_changed);
+  }
+
+  #[test]
+  fn prune() {
+    let text = r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "jsr:@scope/a@1": "jsr:@scope/a@1.0.0",
+      "jsr:@scope/old@1": "jsr:@scope/old@1.0.0",
+      "npm:chalk@5": "npm:chalk@5.0.0",
+      "npm:color-name@1": "npm:color-name@1.0.0"
+    },
+    "jsr": {
+      "@scope/a@1.0.0": {
+        "integrity": "a",
+        "dependencies": ["npm:chalk@5"]
+      },
+      "@scope/old@1.0.0": {
+        "integrity": "old",
+        "dependencies": ["npm:color-name@1"]
+      }
+    },
+    "npm": {
+      "ansi-styles@4.1.0": {
+        "integrity": "sha512-ansi",
+        "dependencies": {
+          "color-name": "color-name@1.0.0"
+        }
+      },
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {
+          "ansi-styles": "ansi-styles@4.1.0",
+          "color-name": "color-name@1.0.0"
+        }
+      },
+      "color-name@1.0.0": {
+        "integrity": "sha512-color",
+        "dependencies": {}
+      },
+      "old-dep@1.0.0": {
+        "integrity": "sha512-old",
+        "dependencies": {
+          "color-name": "color-name@1.0.0"
+        }
+      }
+    }
+  },
+  "redirects": {
+    "https://deno.land/x/a/mod.ts": "https://deno.land/x/a@1.0.0/mod.ts",
+    "https://deno.land/x/old/mod.ts": "https://deno.land/x/old@1.0.0/mod.ts"
+  },
+  "remote": {
+    "https://deno.land/x/a@1.0.0/mod.ts": "a",
+    "https://deno.land/x/old@1.0.0/mod.ts": "old"
+  },
+  "workspace": {
+    "dependencies": ["jsr:@scope/a@1"]
+  }
+}"#;
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      text,
+      false,
+    )
+    .unwrap();
+
+    let report = lockfile.prune(Default::default());
+    assert_eq!(
+      report,
+      PruneReport {
+        npm_packages: BTreeSet::from(["old-dep@1.0.0".to_string()]),
+        jsr_packages: BTreeSet::from(["@scope/old@1.0.0".to_string()]),
+        specifiers: BTreeSet::from([
+          "jsr:@scope/old@1".to_string(),
+          "npm:color-name@1".to_string(),
+        ]),
+        remote: BTreeSet::new(),
+        redirects: BTreeSet::new(),
+      }
+    );
+    assert!(lockfile.has_content_changed);
+    // the diamond shared dependency is kept
+    assert_eq!(
+      lockfile.content.packages.npm.keys().collect::<Vec<_>>(),
+      vec!["ansi-styles@4.1.0", "chalk@5.0.0", "color-name@1.0.0"]
+    );
+    assert!(lockfile.content.unreachable_packages().is_empty());
+
+    lockfile.has_content_changed = false;
+    assert_eq!(lockfile.prune(Default::default()), PruneReport::default());
+    assert!(!lockfile.has_content_changed);
+
+    let report = lockfile.prune(PruneOptions {
+      remote_allowlist: Some(BTreeSet::from([
+        "https://deno.land/x/a@1.0.0/mod.ts".to_string(),
+      ])),
+      redirects_allowlist: Some(BTreeSet::from([
+        "https://deno.land/x/a/mod.ts".to_string(),
+      ])),
+    });
+    assert_eq!(
+      report.remote,
+      BTreeSet::from(["https://deno.land/x/old@1.0.0/mod.ts".to_string()])
+    );
+    assert_eq!(
+      report.redirects,
+      BTreeSet::from(["https://deno.land/x/old/mod.ts".to_string()])
+    );
+    assert!(lockfile.has_content_changed);
+  }
+
+  #[test]
+  fn simulate_remove_roots() {
+    let lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "jsr:@scope/a@1": "jsr:@scope/a@1.0.0",
+      "jsr:@scope/b@1": "jsr:@scope/b@1.0.0",
+      "npm:chalk@5": "npm:chalk@5.0.0"
+    },
+    "jsr": {
+      "@scope/a@1.0.0": {
+        "integrity": "a",
+        "dependencies": ["jsr:@scope/b@1", "npm:chalk@5"]
+      },
+      "@scope/b@1.0.0": {
+        "integrity": "b"
+      }
+    },
+    "npm": {
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {
+    "https://deno.land/x/a@1.0.0/mod.ts": "checksum"
+  },
+  "workspace": {
+    "dependencies": ["jsr:@scope/a@1", "npm:chalk@5"]
+  }
+}"#,
+      false,
+    )
+    .unwrap();
+    let text_before = lockfile.as_json_string();
+
+    let preview =
+      lockfile.simulate_remove_roots(["jsr:@scope/a@1"].into_iter());
+    assert_eq!(
+      preview,
+      RemovalPreview {
+        npm_packages: BTreeSet::new(),
+        jsr_packages: BTreeSet::from([
+          "@scope/a@1.0.0".to_string(),
+          "@scope/b@1.0.0".to_string(),
+        ]),
+        specifiers: BTreeSet::from([
+          "jsr:@scope/a@1".to_string(),
+          "jsr:@scope/b@1".to_string(),
+        ]),
+      }
+    );
+    let preview = lockfile.simulate_remove_roots(["npm:chalk@5"].into_iter());
+    assert_eq!(preview, RemovalPreview::default());
+
+    assert_eq!(lockfile.as_json_string(), text_before);
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(lockfile.generation(), 0);
+  }
+
+  #[test]
+  fn subset() {
+    let lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "jsr:@scope/a@1": "jsr:@scope/a@1.0.0",
+      "npm:chalk@5": "npm:chalk@5.0.0",
+      "npm:nanoid@3": "npm:nanoid@3.3.4"
+    },
+    "jsr": {
+      "@scope/a@1.0.0": {
+        "integrity": "a",
+        "dependencies": ["npm:chalk@5"]
+      }
+    },
+    "npm": {
+      "ansi-styles@4.1.0": {
+        "integrity": "sha512-ansi",
+        "dependencies": {}
+      },
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {
+          "ansi-styles": "ansi-styles@4.1.0"
+        }
+      },
+      "nanoid@3.3.4": {
+        "integrity": "sha512-nanoid",
+        "dependencies": {}
+      }
+    }
+  },
+  "redirects": {
+    "https://deno.land/x/a/mod.ts": "https://deno.land/x/a@1.0.0/mod.ts",
+    "https://jsr.io/@scope/a/mod.ts": "https://jsr.io/@scope/a/1.0.0/mod.ts"
+  },
+  "remote": {
+    "https://deno.land/x/a@1.0.0/mod.ts": "checksum",
+    "https://jsr.io/@scope/a/1.0.0/mod.ts": "checksum-a"
+  }
+}"#,
+      false,
+    )
+    .unwrap();
+    let original = lockfile.as_json_string();
+    let content = lockfile.subset(["jsr:@scope/a@1"].into_iter());
+    // doesn't change the lockfile
+    assert_eq!(lockfile.as_json_string(), original);
+    assert!(!lockfile.has_content_changed);
+
+    let mut subset_lockfile =
+      Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false);
+    subset_lockfile.content = content;
+    assert_eq!(
+      subset_lockfile.as_json_string(),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "jsr:@scope/a@1": "jsr:@scope/a@1.0.0",
+      "npm:chalk@5": "npm:chalk@5.0.0"
+    },
+    "jsr": {
+      "@scope/a@1.0.0": {
+        "integrity": "a",
+        "dependencies": [
+          "npm:chalk@5"
+        ]
+      }
+    },
+    "npm": {
+      "ansi-styles@4.1.0": {
+        "integrity": "sha512-ansi",
+        "dependencies": {}
+      },
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {
+          "ansi-styles": "ansi-styles@4.1.0"
+        }
+      }
+    }
+  },
+  "redirects": {
+    "https://jsr.io/@scope/a/mod.ts": "https://jsr.io/@scope/a/1.0.0/mod.ts"
+  },
+  "remote": {
+    "https://jsr.io/@scope/a/1.0.0/mod.ts": "checksum-a"
+  },
+  "workspace": {
+    "dependencies": [
+      "jsr:@scope/a@1"
+    ]
+  }
+}
+"#
+    );
+    assert!(subset_lockfile.content.unreachable_packages().is_empty());
+  }
+
+  #[test]
+  fn split_by_member() {
+    let lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "jsr:@scope/a@1": "jsr:@scope/a@1.0.0",
+      "jsr:@scope/b@1": "jsr:@scope/b@1.0.0",
+      "npm:chalk@5": "npm:chalk@5.0.0",
+      "npm:nanoid@3": "npm:nanoid@3.3.4"
+    },
+    "jsr": {
+      "@scope/a@1.0.0": {
+        "integrity": "a",
+        "dependencies": ["jsr:@scope/b@1", "npm:chalk@5"]
+      },
+      "@scope/b@1.0.0": {
+        "integrity": "b"
+      }
+    },
+    "npm": {
+      "ansi-styles@4.1.0": {
+        "integrity": "sha512-ansi",
+        "dependencies": {}
+      },
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {
+          "ansi-styles": "ansi-styles@4.1.0"
+        }
+      },
+      "nanoid@3.3.4": {
+        "integrity": "sha512-nanoid",
+        "dependencies": {}
+      }
+    }
+  },
+  "redirects": {
+    "https://deno.land/x/mod.ts": "https://deno.land/x/mod@1.0.0/mod.ts",
+    "https://jsr.io/@scope/a/mod.ts": "https://jsr.io/@scope/a/1.0.0/mod.ts",
+    "jsr:@scope/b": "jsr:@scope/b@1.0.0"
+  },
+  "remote": {
+    "https://deno.land/x/mod@1.0.0/mod.ts": "checksum-x",
+    "https://jsr.io/@scope/a/1.0.0/mod.ts": "checksum-a",
+    "https://jsr.io/@scope/b/1.0.0/mod.ts": "checksum-b"
+  },
+  "workspace": {
+    "members": {
+      "a": {
+        "dependencies": ["jsr:@scope/a@1"]
+      },
+      "b": {
+        "packageJson": {
+          "dependencies": ["npm:nanoid@3", "npm:chalk@5"]
+        }
+      }
+    }
+  }
+}"#,
+      false,
+    )
+    .unwrap();
+    let workspace = WorkspaceConfig {
+      root: Default::default(),
+      members: BTreeMap::from([
+        (
+          "a".to_string(),
+          WorkspaceMemberConfig {
+            dependencies: BTreeSet::from(["jsr:@scope/a@1".to_string()]),
+            package_json_deps: Default::default(),
+          },
+        ),
+        (
+          "b".to_string(),
+          WorkspaceMemberConfig {
+            dependencies: Default::default(),
+            package_json_deps: BTreeSet::from([
+              "npm:nanoid@3".to_string(),
+              "npm:chalk@5".to_string(),
+            ]),
+          },
+        ),
+      ]),
+    };
+    let split = lockfile.split_by_member(&workspace);
+    assert_eq!(split.keys().collect::<Vec<_>>(), vec!["a", "b"]);
+    for content in split.values() {
+      let mut member_lockfile =
+        Lockfile::new_empty(PathBuf::from("/foo/deno.lock"), false);
+      member_lockfile.content = content.clone();
+      assert!(content.unreachable_packages().is_empty());
+      assert_eq!(
+        member_lockfile.graph_json()["missing"],
+        serde_json::Value::Array(Vec::new())
+      );
+    }
+
+    let a = &split["a"];
+    assert_eq!(
+      a.packages.jsr.keys().collect::<Vec<_>>(),
+      vec!["@scope/a@1.0.0", "@scope/b@1.0.0"]
+    );
+    assert_eq!(
+      a.packages.npm.keys().collect::<Vec<_>>(),
+      vec!["ansi-styles@4.1.0", "chalk@5.0.0"]
+    );
+    assert_eq!(
+      a.packages.specifiers.keys().collect::<Vec<_>>(),
+      vec!["jsr:@scope/a@1", "jsr:@scope/b@1", "npm:chalk@5"]
+    );
+    assert_eq!(
+      a.remote.keys().collect::<Vec<_>>(),
+      vec![
+        "https://jsr.io/@scope/a/1.0.0/mod.ts",
+        "https://jsr.io/@scope/b/1.0.0/mod.ts"
+      ]
+    );
+    assert_eq!(
+      a.redirects.keys().collect::<Vec<_>>(),
+      vec!["https://jsr.io/@scope/a/mod.ts", "jsr:@scope/b"]
+    );
+    assert_eq!(
+      a.workspace.root().dependencies,
+      BTreeSet::from(["jsr:@scope/a@1".to_string()])
+    );
+    assert!(a.workspace.root().package_json.is_empty());
+    let b = &split["b"];
+    assert!(b.packages.jsr.is_empty());
+    assert!(b.remote.is_empty());
+    assert!(b.redirects.is_empty());
+    assert!(b.workspace.root().dependencies.is_empty());
+    assert_eq!(
+      b.workspace.root().package_json.dependencies,
+      BTreeSet::from(["npm:chalk@5".to_string(), "npm:nanoid@3".to_string()])
+    );
+    assert_eq!(
+      b.packages.npm.keys().collect::<Vec<_>>(),
+      vec!["ansi-styles@4.1.0", "chalk@5.0.0", "nanoid@3.3.4"]
+    );
+    assert_eq!(
+      b.packages.specifiers.keys().collect::<Vec<_>>(),
+      vec!["npm:chalk@5", "npm:nanoid@3"]
+    );
+  }
+
+  #[test]
+  fn read_version_1() {
+    let content: &str = r#"{
+      "https://deno.land/std@0.71.0/textproto/mod.ts": "3118d7a42c03c242c5a49c2ad91c8396110e14acca1324e7aaefd31a999b71a4",
+      "https://deno.land/std@0.71.0/async/delay.ts": "35957d585a6e3dd87706858fb1d6b551cb278271b03f52c5a2cb70e65e00c26a"
+    }"#;
+    let file_path = PathBuf::from("lockfile.json");
+    let lockfile =
+      Lockfile::with_lockfile_content(file_path, content, false).unwrap();
+    assert_eq!(lockfile.content.version, "3");
+    assert_eq!(lockfile.original_version(), LockfileVersion::V1);
+    assert_eq!(lockfile.content.remote.len(), 2);
+  }
+
+  #[test]
+  fn read_version_2() {
+    let content: &str = r#"{
+      "version": "2",
+      "remote": {
+        "https://deno.land/std@0.71.0/textproto/mod.ts": "3118d7a42c03c242c5a49c2ad91c8396110e14acca1324e7aaefd31a999b71a4",
+        "https://deno.land/std@0.71.0/async/delay.ts": "35957d585a6e3dd87706858fb1d6b551cb278271b03f52c5a2cb70e65e00c26a"
+      },
+      "npm": {
+        "specifiers": {
+          "nanoid": "nanoid@3.3.4"
+        },
+        "packages": {
+          "nanoid@3.3.4": {
+            "integrity": "sha512-MqBkQh/OHTS2egovRtLk45wEyNXwF+cokD+1YPf9u5VfJiRdAiRwB2froX5Co9Rh20xs4siNPm8naNotSD6RBw==",
+            "dependencies": {}
+          },
+          "picocolors@1.0.0": {
+            "integrity": "sha512-foobar",
+            "dependencies": {}
+          }
+        }
+      }
+    }"#;
+    let file_path = PathBuf::from("lockfile.json");
+    let lockfile =
+      Lockfile::with_lockfile_content(file_path, content, false).unwrap();
+    assert_eq!(lockfile.content.version, "3");
+    assert_eq!(lockfile.original_version(), LockfileVersion::V2);
+    assert_eq!(lockfile.content.packages.npm.len(), 2);
+    assert_eq!(
+      lockfile.content.packages.specifiers,
+      BTreeMap::from([(
+        "npm:nanoid".to_string(),
+        "npm:nanoid@3.3.4".to_string()
+      )])
+    );
+    assert_eq!(lockfile.content.remote.len(), 2);
+  }
+
+  #[test]
+  fn integrity_format_summary_preserved_by_transform() {
+    let v2_content: &str = r#"{
+      "version": "2",
+      "remote": {},
+      "npm": {
+        "specifiers": {
+          "nanoid": "nanoid@3.3.4"
+        },
+        "packages": {
+          "nanoid@3.3.4": {
+            "integrity": "sha512-MqBkQh/OHTS2egovRtLk45wEyNXwF+cokD+1YPf9u5VfJiRdAiRwB2froX5Co9Rh20xs4siNPm8naNotSD6RBw==",
+            "dependencies": {}
+          },
+          "picocolors@1.0.0": {
+            "integrity": "sha1-foobar",
+            "dependencies": {}
+          },
+          "other@1.0.0": {
+            "integrity": "sha512-other",
+            "dependencies": {}
+          }
+        }
+      }
+    }"#;
+    let expected =
+      BTreeMap::from([("sha1".to_string(), 1), ("sha512".to_string(), 2)]);
+    let v2_json: transforms::JsonMap =
+      serde_json::from_str(v2_content).unwrap();
+    let mut before_content = LockfileContent::empty();
+    before_content.packages.npm =
+      serde_json::from_value(v2_json["npm"]["packages"].clone()).unwrap();
+    assert_eq!(before_content.integrity_format_summary(), expected);
+
+    let lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("lockfile.json"),
+      v2_content,
+      false,
+    )
+    .unwrap();
+    assert_eq!(lockfile.content.integrity_format_summary(), expected);
+
+    let mut lockfile = lockfile;
+    lockfile.insert_package(
+      "@std/path@0.75.0".to_string(),
+      "09154a97e18c".to_string(),
+    );
+    lockfile.insert_package("@std/fs@0.75.0".to_string(), "".to_string());
+    assert_eq!(
+      lockfile.content.integrity_format_summary(),
+      BTreeMap::from([
+        ("hex".to_string(), 1),
+        ("sha1".to_string(), 1),
+        ("sha512".to_string(), 2),
+        ("unknown".to_string(), 1),
+      ])
+    );
+  }
+
+  #[test]
+  fn insert_package_deps_changes_empty_insert() {
+    let content: &str = r#"{
+      "version": "2",
+      "remote": {}
+    }"#;
+    let file_path = PathBuf::from("lockfile.json");
+    let mut lockfile =
+      Lockfile::with_lockfile_content(file_path, content, false).unwrap();
+
+    assert!(!lockfile.has_content_changed);
+    lockfile.insert_package("dep".to_string(), "integrity".to_string());
+    // has changed even though it was empty
+    assert!(lockfile.has_content_changed);
+
+    // now try inserting the same package
+    lockfile.has_content_changed = false;
+    lockfile.insert_package("dep".to_string(), "integrity".to_string());
+    assert!(!lockfile.has_content_changed);
+
+    // now with new deps
+    lockfile
+      .try_add_package_deps("dep", vec!["dep2".to_string()].into_iter())
+      .unwrap();
+    assert!(lockfile.has_content_changed);
+  }
+
+  #[test]
+  fn add_package_deps_missing_package() {
+    let mut lockfile = setup(false).unwrap();
+    let err = lockfile
+      .try_add_package_deps("@std/path@0.75.0", ["dep".to_string()].into_iter())
+      .unwrap_err();
+    assert_eq!(
+      err,
+      MissingPackageError {
+        name: "@std/path@0.75.0".to_string()
+      }
+    );
+    assert_eq!(
+      err.to_string(),
+      "Package '@std/path@0.75.0' was not found in the lockfile."
+    );
+    assert!(!lockfile.has_content_changed);
+    assert!(lockfile.content.packages.jsr.is_empty());
+
+    #[allow(deprecated)]
+    lockfile
+      .add_package_deps("@std/path@0.75.0", ["dep".to_string()].into_iter());
+    assert!(!lockfile.has_content_changed);
+
+    lockfile.add_package_deps_or_insert(
+      "@std/path@0.75.0",
+      ["dep".to_string()].into_iter(),
+    );
+    assert!(lockfile.has_content_changed);
+    let package = &lockfile.content.packages.jsr["@std/path@0.75.0"];
+    assert_eq!(package.integrity, "");
+    assert_eq!(package.dependencies, BTreeSet::from(["dep".to_string()]));
+
+    // the integrity can be provided later
+    lockfile.insert_package("@std/path@0.75.0".to_string(), "a".to_string());
+    let package = &lockfile.content.packages.jsr["@std/path@0.75.0"];
+    assert_eq!(package.integrity, "a");
+    assert_eq!(package.dependencies.len(), 1);
+  }
+
+  fn load_version_4(text: &str) -> Lockfile {
+    let value = transform4_to_3(serde_json::from_str(text).unwrap()).unwrap();
+    Lockfile::with_lockfile_content(
+      PathBuf::from("/foo/deno.lock"),
+      &value.to_string(),
+      false,
+    )
+    .unwrap()
+  }
+
+  #[test]
+  fn downgrades_version_4() {
+    let content: &str = r#"{
+      "version": "4",
+      "specifiers": { "npm:nanoid@3": "3.3.4" },
+      "npm": { "nanoid@3.3.4": { "integrity": "sha512-abc" } },
+      "remote": {}
+    }"#;
+    // only downgraded when asked
+    let file_path = PathBuf::from("lockfile.json");
+    let err = Lockfile::with_lockfile_content(file_path, content, false)
+      .err()
+      .unwrap();
+    assert!(matches!(err, Error::UnsupportedVersion(_)), "{err}");
+
+    let lockfile = load_version_4(content);
+    assert_eq!(lockfile.original_version(), LockfileVersion::V3);
+    assert_eq!(
+      lockfile.as_json_string(),
+      r#"{
   "version": "3",
   "packages": {
-    "specifiers": {},
+    "specifiers": {
+      "npm:nanoid@3": "npm:nanoid@3.3.4"
+    },
     "npm": {
       "nanoid@3.3.4": {
-        "integrity": "sha512-MqBkQh/OHTS2egovRtLk45wEyNXwF+cokD+1YPf9u5VfJiRdAiRwB2froX5Co9Rh20xs4siNPm8naNotSD6RBw==",
+        "integrity": "sha512-abc",
         "dependencies": {}
+      }
+    }
+  },
+  "remote": {}
+}
+"#
+    );
+  }
+
+  #[test]
+  fn to_json_with_version() {
+    let v4_content: &str = r#"{
+      "version": "4",
+      "specifiers": {
+        "jsr:@scope/a@1": "1.0.0",
+        "npm:chalk@5": "5.0.0"
       },
-      "picocolors@1.0.0": {
-        "integrity": "sha512-foobar",
+      "jsr": {
+        "@scope/a@1.0.0": {
+          "integrity": "a",
+          "dependencies": ["npm:chalk@5"]
+        }
+      },
+      "npm": {
+        "ansi-styles@4.1.0": { "integrity": "sha512-ansi" },
+        "chalk@5.0.0": {
+          "integrity": "sha512-chalk",
+          "dependencies": ["ansi-styles"]
+        }
+      },
+      "remote": {}
+    }"#;
+    let file_path = PathBuf::from("lockfile.json");
+    let lockfile = load_version_4(v4_content);
+    let v3_text = lockfile.to_json_with_version(LockfileVersion::V3).unwrap();
+    assert_eq!(v3_text, lockfile.as_json_string());
+
+    // emitting and parsing the version 3 text again gives the same content
+    let reloaded =
+      Lockfile::with_lockfile_content(file_path.clone(), &v3_text, false)
+        .unwrap();
+    assert_eq!(reloaded.original_version(), LockfileVersion::V3);
+    assert_eq!(reloaded.content.to_value(), lockfile.content.to_value());
+    assert_eq!(
+      reloaded.to_json_with_version(LockfileVersion::V3).unwrap(),
+      v3_text
+    );
+    assert_eq!(
+      reloaded.content.packages.npm["chalk@5.0.0"].dependencies,
+      BTreeMap::from([(
+        "ansi-styles".to_string(),
+        "ansi-styles@4.1.0".to_string()
+      )])
+    );
+
+    let err = lockfile
+      .to_json_with_version(LockfileVersion::V4)
+      .unwrap_err();
+    assert!(matches!(err, Error::UnsupportedWriteVersion(_, _)));
+    assert_eq!(
+      err.to_string(),
+      "Unable to write a version 4 lockfile. Only versions 1 to 3 can be written."
+    );
+    let err = lockfile
+      .to_json_with_version(LockfileVersion::V2)
+      .unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "Unable to write a version 2 lockfile. The \"jsr\" packages have no version 2 equivalent."
+    );
+  }
+
+  #[test]
+  fn keep_original_version() {
+    let v2_text = r#"{
+  "version": "2",
+  "remote": {
+    "https://deno.land/std@0.71.0/textproto/mod.ts": "3118d7a42c03c242c5a49c2ad91c8396110e14acca1324e7aaefd31a999b71a4"
+  },
+  "npm": {
+    "specifiers": {
+      "nanoid": "nanoid@3.3.4"
+    },
+    "packages": {
+      "nanoid@3.3.4": {
+        "integrity": "sha512-nanoid",
+        "dependencies": {}
+      }
+    }
+  }
+}
+"#;
+    let file_path = PathBuf::from("/foo/deno.lock");
+    let mut lockfile =
+      Lockfile::with_lockfile_content(file_path.clone(), v2_text, false)
+        .unwrap();
+    assert_eq!(
+      lockfile.to_json_with_version(LockfileVersion::V2).unwrap(),
+      v2_text
+    );
+    lockfile.output_options.keep_original_version = true;
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/async/delay.ts".to_string(),
+      "checksum".to_string(),
+    );
+    // only the new entry is added
+    let bytes = lockfile.resolve_write_bytes().unwrap();
+    assert_eq!(
+      String::from_utf8(bytes).unwrap(),
+      v2_text.replace(
+        "  \"remote\": {\n",
+        "  \"remote\": {\n    \"https://deno.land/std@0.71.0/async/delay.ts\": \"checksum\",\n"
+      )
+    );
+
+    // upgrades when the content can't be represented in version 2
+    lockfile.insert_package_specifier(
+      "jsr:@std/path@^0.75".to_string(),
+      "jsr:@std/path@0.75.0".to_string(),
+    );
+    let bytes = lockfile.resolve_write_bytes().unwrap();
+    assert_eq!(bytes, lockfile.as_json_string().into_bytes());
+
+    // and by default
+    let mut lockfile =
+      Lockfile::with_lockfile_content(file_path.clone(), v2_text, false)
+        .unwrap();
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/async/delay.ts".to_string(),
+      "checksum".to_string(),
+    );
+    let bytes = lockfile.resolve_write_bytes().unwrap();
+    assert_eq!(bytes, lockfile.as_json_string().into_bytes());
+
+    // version 1 only has the remote modules
+    let v1_text = r#"{
+  "https://deno.land/std@0.71.0/textproto/mod.ts": "3118d7a42c03c242c5a49c2ad91c8396110e14acca1324e7aaefd31a999b71a4"
+}
+"#;
+    let mut lockfile =
+      Lockfile::with_lockfile_content(file_path, v1_text, false).unwrap();
+    lockfile.output_options.keep_original_version = true;
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/async/delay.ts".to_string(),
+      "checksum".to_string(),
+    );
+    let (bytes, _) = lockfile.resolve_write_bytes_if_newer(0).unwrap();
+    assert_eq!(
+      String::from_utf8(bytes).unwrap(),
+      r#"{
+  "https://deno.land/std@0.71.0/async/delay.ts": "checksum",
+  "https://deno.land/std@0.71.0/textproto/mod.ts": "3118d7a42c03c242c5a49c2ad91c8396110e14acca1324e7aaefd31a999b71a4"
+}
+"#
+    );
+  }
+
+  #[test]
+  fn output_version() {
+    let v2_text = r#"{
+  "version": "2",
+  "remote": {},
+  "npm": {
+    "specifiers": {
+      "nanoid": "nanoid@3.3.4"
+    },
+    "packages": {
+      "nanoid@3.3.4": {
+        "integrity": "sha512-nanoid",
         "dependencies": {}
       }
     }
-  },
-  "remote": {
-    "https://deno.land/std@0.71.0/textproto/mod.ts": "3118d7a42c03c242c5a49c2ad91c8396110e14acca1324e7aaefd31a999b71a4",
-    "https://deno.land/std@0.71.0/async/delay.ts": "35957d585a6e3dd87706858fb1d6b551cb278271b03f52c5a2cb70e65e00c26a"
   }
-}"#;
+}
+"#;
+    let file_path = PathBuf::from("/foo/deno.lock");
+    let mut lockfile =
+      Lockfile::with_lockfile_content(file_path, v2_text, false).unwrap();
+    lockfile.output_options.keep_original_version = true;
+    lockfile.output_options.omit_empty_remote = true;
+    lockfile.output_options.version = Some(LockfileVersion::V3);
+    lockfile.has_content_changed = true;
+    // takes precedence over the original version
+    let bytes = lockfile.resolve_write_bytes().unwrap();
+    let text = String::from_utf8(bytes).unwrap();
+    assert!(text.starts_with("{\n  \"version\": \"3\""));
+    assert!(!text.contains("\"remote\""));
 
-  fn setup(overwrite: bool) -> Result<Lockfile, Error> {
-    let file_path =
-      std::env::current_dir().unwrap().join("valid_lockfile.json");
-    Lockfile::with_lockfile_content(file_path, LOCKFILE_JSON, overwrite)
+    lockfile.output_options.keep_original_version = false;
+    lockfile.output_options.version = Some(LockfileVersion::V2);
+    lockfile.has_content_changed = true;
+    let bytes = lockfile.resolve_write_bytes().unwrap();
+    assert_eq!(String::from_utf8(bytes).unwrap(), v2_text);
   }
 
   #[test]
-  fn future_version_unsupported() {
+  fn invalid_current_version_error() {
+    let content: &str = r#"{
+      "version": "3",
+      "remote": []
+    }"#;
     let file_path = PathBuf::from("lockfile.json");
-    assert_eq!(
-      Lockfile::with_lockfile_content(
-        file_path,
-        "{ \"version\": \"2000\" }",
-        false
-      )
+    let err = Lockfile::with_lockfile_content(file_path, content, false)
       .err()
-      .unwrap().to_string(),
-      "Unsupported lockfile version '2000'. Try upgrading Deno or recreating the lockfile.".to_string()
-    );
+      .unwrap();
+    assert!(matches!(err, Error::ParseError(..)), "{err}");
+    assert!(err.to_string().starts_with(
+      "Unable to parse contents of lockfile. lockfile.json: invalid type: sequence, expected a map"
+    ));
   }
 
   #[test]
-  fn new_valid_lockfile() {
-    let lockfile = setup(false).unwrap();
-
-    let remote = lockfile.content.remote;
-    let keys: Vec<String> = remote.keys().cloned().collect();
-    let expected_keys = vec![
-      String::from("https://deno.land/std@0.71.0/async/delay.ts"),
-      String::from("https://deno.land/std@0.71.0/textproto/mod.ts"),
-    ];
-
-    assert_eq!(keys.len(), 2);
-    assert_eq!(keys, expected_keys);
+  fn duplicate_key_error() {
+    let content: &str = r#"{
+      "version": "3",
+      "remote": {},
+      "version": "3"
+    }"#;
+    let file_path = PathBuf::from("lockfile.json");
+    let err = Lockfile::with_lockfile_content(file_path, content, false)
+      .err()
+      .unwrap();
+    assert_eq!(
+      err.to_string(),
+      "Duplicate key 'version' in lockfile lockfile.json. The lockfile may have been incorrectly merged."
+    );
   }
 
   #[test]
-  fn with_lockfile_content_for_valid_lockfile() {
-    let file_path = PathBuf::from("/foo");
-    let result =
-      Lockfile::with_lockfile_content(file_path, LOCKFILE_JSON, false).unwrap();
-
-    let remote = result.content.remote;
-    let keys: Vec<String> = remote.keys().cloned().collect();
-    let expected_keys = vec![
-      String::from("https://deno.land/std@0.71.0/async/delay.ts"),
-      String::from("https://deno.land/std@0.71.0/textproto/mod.ts"),
-    ];
-
-    assert_eq!(keys.len(), 2);
-    assert_eq!(keys, expected_keys);
+  fn empty_lockfile_nicer_error() {
+    let content: &str = r#"  "#;
+    let file_path = PathBuf::from("lockfile.json");
+    let err = Lockfile::with_lockfile_content(file_path, content, false)
+      .err()
+      .unwrap();
+    assert_eq!(
+      err.to_string(),
+      "Unable to read lockfile. Lockfile was empty."
+    );
   }
 
   #[test]
-  fn new_lockfile_from_file_and_insert() {
-    let mut lockfile = setup(false).unwrap();
+  fn upgrade_to_latest_versions() {
+    let v1 = upgrade_to_latest(serde_json::json!({
+      "https://deno.land/std@0.71.0/textproto/mod.ts": "3118d7a42c03c242c5a49c2ad91c8396110e14acca1324e7aaefd31a999b71a4",
+    }))
+    .unwrap();
+    assert_eq!(
+      v1,
+      serde_json::json!({
+        "version": "3",
+        "remote": {
+          "https://deno.land/std@0.71.0/textproto/mod.ts": "3118d7a42c03c242c5a49c2ad91c8396110e14acca1324e7aaefd31a999b71a4",
+        },
+      })
+    );
 
-    lockfile.insert_remote(
-      "https://deno.land/std@0.71.0/io/util.ts".to_string(),
-      "checksum-1".to_string(),
+    let v2 = upgrade_to_latest(serde_json::json!({
+      "version": "2",
+      "remote": {},
+      "npm": {
+        "specifiers": {
+          "nanoid": "nanoid@3.3.4",
+        },
+        "packages": {
+          "nanoid@3.3.4": {
+            "integrity": "sha512-foobar",
+            "dependencies": {},
+          },
+        },
+      },
+    }))
+    .unwrap();
+    assert_eq!(
+      v2,
+      serde_json::json!({
+        "version": "3",
+        "remote": {},
+        "packages": {
+          "specifiers": {
+            "npm:nanoid": "npm:nanoid@3.3.4",
+          },
+          "npm": {
+            "nanoid@3.3.4": {
+              "integrity": "sha512-foobar",
+              "dependencies": {},
+            },
+          },
+        },
+      })
     );
 
-    let remote = lockfile.content.remote;
-    let keys: Vec<String> = remote.keys().cloned().collect();
-    let expected_keys = vec![
-      String::from("https://deno.land/std@0.71.0/async/delay.ts"),
-      String::from("https://deno.land/std@0.71.0/io/util.ts"),
-      String::from("https://deno.land/std@0.71.0/textproto/mod.ts"),
-    ];
-    assert_eq!(keys.len(), 3);
-    assert_eq!(keys, expected_keys);
+    let v3 = serde_json::json!({
+      "version": "3",
+      "remote": {},
+    });
+    assert_eq!(upgrade_to_latest(v3.clone()).unwrap(), v3);
   }
 
   #[test]
-  fn new_lockfile_and_write() {
-    let mut lockfile = setup(true).unwrap();
-
-    // true since overwrite was true
-    assert!(lockfile.resolve_write_bytes().is_some());
+  fn transform_version_4_to_3() {
+    let v4 = serde_json::json!({
+      "version": "4",
+      "specifiers": {
+        "npm:nanoid@3": "3.3.4",
+      },
+      "npm": {
+        "nanoid@3.3.4": {
+          "integrity": "sha512-foobar",
+        },
+      },
+    });
+    // upgrading doesn't downgrade
+    let err = upgrade_to_latest(v4.clone()).unwrap_err();
+    assert_eq!(err.from_version, "4");
+    assert_eq!(err.message, "The version is not supported.");
 
-    lockfile.insert_remote(
-      "https://deno.land/std@0.71.0/textproto/mod.ts".to_string(),
-      "checksum-1".to_string(),
-    );
-    lockfile.insert_remote(
-      "https://deno.land/std@0.71.0/io/util.ts".to_string(),
-      "checksum-2".to_string(),
-    );
-    lockfile.insert_remote(
-      "https://deno.land/std@0.71.0/async/delay.ts".to_string(),
-      "checksum-3".to_string(),
+    let v3 = transform4_to_3(v4).unwrap();
+    assert_eq!(v3["version"], "3");
+    assert_eq!(
+      v3["packages"]["specifiers"],
+      serde_json::json!({ "npm:nanoid@3": "npm:nanoid@3.3.4" })
     );
 
-    let bytes = lockfile.resolve_write_bytes().unwrap();
-    let contents_json =
-      serde_json::from_slice::<serde_json::Value>(&bytes).unwrap();
-    let object = contents_json["remote"].as_object().unwrap();
-
+    let err =
+      transform4_to_3(serde_json::json!({ "version": "3" })).unwrap_err();
     assert_eq!(
-      object
-        .get("https://deno.land/std@0.71.0/textproto/mod.ts")
-        .and_then(|v| v.as_str()),
-      Some("checksum-1")
+      err.to_string(),
+      "Unable to transform lockfile from version 4 to 3. Expected a version 4 lockfile."
     );
+  }
 
-    // confirm that keys are sorted alphabetically
-    let mut keys = object.keys().map(|k| k.as_str());
+  #[test]
+  fn upgrade_to_latest_errors() {
+    let err =
+      upgrade_to_latest(serde_json::json!({ "version": "9" })).unwrap_err();
     assert_eq!(
-      keys.next(),
-      Some("https://deno.land/std@0.71.0/async/delay.ts")
+      err,
+      TransformError {
+        from_version: "9".to_string(),
+        to_version: "3".to_string(),
+        message: "The version is not supported.".to_string(),
+      }
     );
-    assert_eq!(keys.next(), Some("https://deno.land/std@0.71.0/io/util.ts"));
     assert_eq!(
-      keys.next(),
-      Some("https://deno.land/std@0.71.0/textproto/mod.ts")
+      err.to_string(),
+      "Unable to transform lockfile from version 9 to 3. The version is not supported."
     );
-    assert!(keys.next().is_none());
+
+    let err = upgrade_to_latest(serde_json::json!([])).unwrap_err();
+    assert_eq!(err.message, "Expected the lockfile to be an object.");
   }
 
   #[test]
-  fn check_or_insert_lockfile() {
-    let mut lockfile = setup(false).unwrap();
-
-    // none since overwrite was false and there's no changes
-    assert!(lockfile.resolve_write_bytes().is_none());
-
-    lockfile.insert_remote(
-      "https://deno.land/std@0.71.0/textproto/mod.ts".to_string(),
-      "checksum-1".to_string(),
+  fn compute_remote_checksum_sha256_hex() {
+    assert_eq!(
+      compute_remote_checksum(b""),
+      "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
     );
-    assert!(lockfile.has_content_changed);
-
-    lockfile.has_content_changed = false;
-    lockfile.insert_remote(
-      "https://deno.land/std@0.71.0/textproto/mod.ts".to_string(),
-      "checksum-1".to_string(),
+    let checksum = compute_remote_checksum(b"hello world");
+    assert_eq!(
+      checksum,
+      "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
     );
-    assert!(!lockfile.has_content_changed);
+    assert_eq!(integrity_format(&checksum), "hex");
 
+    let mut lockfile = setup(false).unwrap();
     lockfile.insert_remote(
-      "https://deno.land/std@0.71.0/textproto/mod.ts".to_string(),
-      "checksum-new".to_string(),
+      "https://deno.land/std@0.71.0/hello.ts".to_string(),
+      compute_remote_checksum(b"hello world"),
     );
-    assert!(lockfile.has_content_changed);
-    lockfile.has_content_changed = false;
-
-    // Not present in lockfile yet, should be inserted and check passed.
-    lockfile.insert_remote(
-      "https://deno.land/std@0.71.0/http/file_server.ts".to_string(),
-      "checksum-1".to_string(),
+    assert_eq!(
+      lockfile.content.remote["https://deno.land/std@0.71.0/hello.ts"],
+      checksum
     );
-    assert!(lockfile.has_content_changed);
-
-    // true since there were changes
-    assert!(lockfile.resolve_write_bytes().is_some());
   }
 
   #[test]
-  fn check_or_insert_lockfile_npm() {
-    let mut lockfile = setup(false).unwrap();
-
-    // already in lockfile
-    let npm_package = NpmPackageLockfileInfo {
-      serialized_id: "nanoid@3.3.4".to_string(),
-      integrity: "sha512-MqBkQh/OHTS2egovRtLk45wEyNXwF+cokD+1YPf9u5VfJiRdAiRwB2froX5Co9Rh20xs4siNPm8naNotSD6RBw==".to_string(),
-      dependencies: vec![],
-    };
-    lockfile.insert_npm_package(npm_package);
-    assert!(!lockfile.has_content_changed);
-
-    // insert package that exists already, but has slightly different properties
-    let npm_package = NpmPackageLockfileInfo {
-      serialized_id: "picocolors@1.0.0".to_string(),
-      integrity: "sha512-1fygroTLlHu66zi26VoTDv8yRgm0Fccecssto+MhsZ0D/DGW2sm8E8AjW7NU5VVTRt5GxbeZ5qBuJr+HyLYkjQ==".to_string(),
-      dependencies: vec![],
-    };
-    lockfile.insert_npm_package(npm_package);
-    assert!(lockfile.has_content_changed);
-
-    lockfile.has_content_changed = false;
-    let npm_package = NpmPackageLockfileInfo {
-      serialized_id: "source-map-js@1.0.2".to_string(),
-      integrity: "sha512-R0XvVJ9WusLiqTCEiGCmICCMplcCkIwwR11mOSD9CR5u+IXYdiseeEuXCVAjS54zqwkLcPNnmU4OeJ6tUrWhDw==".to_string(),
-      dependencies: vec![],
-    };
-    // Not present in lockfile yet, should be inserted
-    lockfile.insert_npm_package(npm_package.clone());
-    assert!(lockfile.has_content_changed);
-    lockfile.has_content_changed = false;
+  fn package_req_and_id_parse() {
+    let req = PackageReq::parse("npm:@scope/name@^5").unwrap();
+    assert_eq!(req.kind(), RegistryKind::Npm);
+    assert_eq!(req.name(), "@scope/name");
+    assert_eq!(req.version_req(), Some("^5"));
+    assert_eq!(req.to_string(), "npm:@scope/name@^5");
+    let req: PackageReq = "jsr:@std/path".parse().unwrap();
+    assert_eq!(req.kind(), RegistryKind::Jsr);
+    assert_eq!(req.name(), "@std/path");
+    assert_eq!(req.version_req(), None);
 
-    // this one should not say the lockfile has changed because it's the same
-    lockfile.insert_npm_package(npm_package);
-    assert!(!lockfile.has_content_changed);
+    let id = PackageId::parse("npm:chalk@5.0.0_peer@1.0.0").unwrap();
+    assert_eq!(id.kind(), RegistryKind::Npm);
+    assert_eq!(id.name(), "chalk");
+    assert_eq!(id.version(), "5.0.0_peer@1.0.0");
+    assert_eq!(id.package_key(), "chalk@5.0.0_peer@1.0.0");
+    assert_eq!(String::from(id), "npm:chalk@5.0.0_peer@1.0.0");
 
-    let npm_package = NpmPackageLockfileInfo {
-      serialized_id: "source-map-js@1.0.2".to_string(),
-      integrity: "sha512-foobar".to_string(),
-      dependencies: vec![],
-    };
-    // Now present in lockfile, should be changed due to different integrity
-    lockfile.insert_npm_package(npm_package);
-    assert!(lockfile.has_content_changed);
+    for text in ["chalk@5", "npm:", "npm:@", "npm:chalk@", "npm:a b", "git:a"] {
+      assert!(PackageReq::parse(text).is_err(), "{}", text);
+    }
+    for text in ["npm:chalk", "npm:chalk@", "jsr:@std/path", "chalk@5.0.0"] {
+      assert!(PackageId::parse(text).is_err(), "{}", text);
+    }
+    assert_eq!(
+      PackageId::parse("npm:chalk").unwrap_err().to_string(),
+      "Invalid package specifier 'npm:chalk'. Expected a package id in the form npm:<name>@<version> or jsr:<name>@<version>."
+    );
   }
 
   #[test]
-  fn lockfile_with_redirects() {
-    let mut lockfile = Lockfile::with_lockfile_content(
-      PathBuf::from("/foo/deno.lock"),
-      r#"{
-  "version": "3",
-  "redirects": {
-    "https://deno.land/x/std/mod.ts": "https://deno.land/std@0.190.0/mod.ts"
-  },
-  "remote": {}
-}"#,
-      false,
-    )
-    .unwrap();
-    lockfile.content.redirects.insert(
-      "https://deno.land/x/other/mod.ts".to_string(),
-      "https://deno.land/x/other@0.1.0/mod.ts".to_string(),
+  fn try_insert_package_req() {
+    let mut lockfile = setup(false).unwrap();
+    lockfile
+      .try_insert_package_req(
+        PackageReq::parse("npm:chalk@5").unwrap(),
+        PackageId::parse("npm:chalk@5.0.0").unwrap(),
+      )
+      .unwrap();
+    assert_eq!(
+      lockfile.content.packages.specifiers["npm:chalk@5"],
+      "npm:chalk@5.0.0"
+    );
+    assert!(lockfile.has_content_changed);
+
+    let err = lockfile
+      .try_insert_package_req(
+        PackageReq::parse("jsr:@std/path@1").unwrap(),
+        PackageId::parse("npm:chalk@5.0.0").unwrap(),
+      )
+      .unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "Invalid package specifier 'npm:chalk@5.0.0'. Expected the package id to start with jsr:."
+    );
+    assert!(!lockfile
+      .content
+      .packages
+      .specifiers
+      .contains_key("jsr:@std/path@1"));
+
+    lockfile.insert_package_specifier(
+      PackageReq::parse("npm:nanoid@3").unwrap(),
+      PackageId::parse("npm:nanoid@3.3.4").unwrap(),
     );
     assert_eq!(
-      lockfile.as_json_string(),
-      r#"{
-  "version": "3",
-  "redirects": {
-    "https://deno.land/x/other/mod.ts": "https://deno.land/x/other@0.1.0/mod.ts",
-    "https://deno.land/x/std/mod.ts": "https://deno.land/std@0.190.0/mod.ts"
-  },
-  "remote": {}
-}
-"#,
+      lockfile.content.packages.specifiers["npm:nanoid@3"],
+      "npm:nanoid@3.3.4"
     );
   }
 
   #[test]
-  fn test_insert_redirect() {
-    let mut lockfile = Lockfile::with_lockfile_content(
-      PathBuf::from("/foo/deno.lock"),
-      r#"{
+  fn omit_empty_remote() {
+    let text = r#"{
   "version": "3",
-  "redirects": {
-    "https://deno.land/x/std/mod.ts": "https://deno.land/std@0.190.0/mod.ts"
+  "packages": {
+    "specifiers": {
+      "npm:nanoid": "npm:nanoid@3.3.4"
+    },
+    "npm": {
+      "nanoid@3.3.4": {
+        "integrity": "sha512-nanoid",
+        "dependencies": {}
+      }
+    }
   },
-  "remote": {}
-}"#,
-      false,
-    )
-    .unwrap();
-    lockfile.insert_redirect(
-      "https://deno.land/x/std/mod.ts".to_string(),
-      "https://deno.land/std@0.190.0/mod.ts".to_string(),
+  "workspace": {
+    "dependencies": [
+      "npm:nanoid"
+    ]
+  }
+}
+"#;
+    let file_path = PathBuf::from("/foo/deno.lock");
+    let mut lockfile =
+      Lockfile::with_lockfile_content(file_path, text, false).unwrap();
+    assert!(lockfile.content.remote.is_empty());
+    // the empty section is written by default
+    assert_eq!(
+      lockfile.as_json_string(),
+      text.replace(
+        "  },
+  \"workspace\"",
+        "  },
+  \"remote\": {},
+  \"workspace\""
+      )
     );
-    assert!(!lockfile.has_content_changed);
-    lockfile.insert_redirect(
-      "https://deno.land/x/std/mod.ts".to_string(),
-      "https://deno.land/std@0.190.1/mod.ts".to_string(),
+
+    lockfile.output_options.omit_empty_remote = true;
+    lockfile.insert_package_specifier(
+      "npm:nanoid@3".to_string(),
+      "npm:nanoid@3.3.4".to_string(),
     );
-    assert!(lockfile.has_content_changed);
-    lockfile.insert_redirect(
-      "https://deno.land/x/std/other.ts".to_string(),
-      "https://deno.land/std@0.190.1/other.ts".to_string(),
+    let bytes = lockfile.resolve_write_bytes().unwrap();
+    assert_eq!(
+      String::from_utf8(bytes).unwrap(),
+      text.replace(
+        "      \"npm:nanoid\": \"npm:nanoid@3.3.4\"\n",
+        "      \"npm:nanoid\": \"npm:nanoid@3.3.4\",\n      \"npm:nanoid@3\": \"npm:nanoid@3.3.4\"\n"
+      )
+    );
+    // only applies to what's written
+    assert!(lockfile.as_json_string().contains("\"remote\": {}"));
+
+    // kept once there are remote modules
+    lockfile.insert_remote(
+      "https://deno.land/std@0.71.0/async/delay.ts".to_string(),
+      "checksum".to_string(),
     );
+    let bytes = lockfile.resolve_write_bytes().unwrap();
+    assert_eq!(bytes, lockfile.as_json_string().into_bytes());
+  }
+
+  #[test]
+  fn lockfile_content_display() {
+    let lockfile = setup(false).unwrap();
+    let text = format!("{}", lockfile.content);
     assert_eq!(
-      lockfile.as_json_string(),
+      text,
+      serde_json::to_string_pretty(&lockfile.content).unwrap()
+    );
+    assert_eq!(format!("{}\n", text), lockfile.as_json_string());
+
+    assert_eq!(
+      LockfileContent::default().to_string(),
       r#"{
   "version": "3",
-  "redirects": {
-    "https://deno.land/x/std/mod.ts": "https://deno.land/std@0.190.1/mod.ts",
-    "https://deno.land/x/std/other.ts": "https://deno.land/std@0.190.1/other.ts"
-  },
   "remote": {}
-}
-"#,
+}"#
     );
   }
 
   #[test]
-  fn test_insert_jsr() {
-    let mut lockfile = Lockfile::with_lockfile_content(
-      PathBuf::from("/foo/deno.lock"),
-      r#"{
+  fn repair_misplaced_packages() {
+    // a version 3 lockfile with the package sections at the top level
+    let content: &str = r#"{
   "version": "3",
+  "specifiers": {
+    "jsr:@std/path@1": "jsr:@std/path@1.0.0",
+    "npm:chalk@5": "npm:chalk@5.0.0"
+  },
+  "jsr": {
+    "@std/path@1.0.0": {
+      "integrity": "09154a97e18c4d6a1692e3b3c8a3b1ec2934f00b7c1caf7491d762d963ada045"
+    }
+  },
+  "npm": {
+    "chalk@5.0.0": {
+      "integrity": "sha512-chalk",
+      "dependencies": {}
+    }
+  },
   "packages": {
     "specifiers": {
-      "jsr:path": "jsr:@std/path@0.75.0"
+      "npm:chalk@5": "npm:chalk@5.0.1"
+    },
+    "npm": {
+      "chalk@5.0.1": {
+        "integrity": "sha512-chalk-new",
+        "dependencies": {}
+      }
     }
   },
-  "remote": {}
-}"#,
-      false,
-    )
-    .unwrap();
-    lockfile.insert_package_specifier(
-      "jsr:path".to_string(),
-      "jsr:@std/path@0.75.0".to_string(),
+  "remote": {},
+  "$other": 1
+}"#;
+    let file_path = PathBuf::from("lockfile.json");
+    let mut lockfile =
+      Lockfile::with_lockfile_content(file_path, content, false).unwrap();
+    assert_eq!(
+      lockfile.content.misplaced_package_sections(),
+      vec!["specifiers", "jsr", "npm"]
     );
-    assert!(!lockfile.has_content_changed);
-    lockfile.insert_package_specifier(
-      "jsr:path".to_string(),
-      "jsr:@std/path@0.75.1".to_string(),
+    assert!(lockfile.validate().contains(
+      &LockfileProblem::MisplacedPackageSection {
+        section: "npm".to_string()
+      }
+    ));
+
+    assert_eq!(
+      lockfile.repair_misplaced_packages(),
+      vec!["specifiers", "jsr", "npm"]
     );
     assert!(lockfile.has_content_changed);
-    lockfile.insert_package_specifier(
-      "jsr:@foo/bar@^2".to_string(),
-      "jsr:@foo/bar@2.1.2".to_string(),
-    );
+    assert!(lockfile.validate().is_empty());
     assert_eq!(
       lockfile.as_json_string(),
       r#"{
   "version": "3",
   "packages": {
     "specifiers": {
-      "jsr:@foo/bar@^2": "jsr:@foo/bar@2.1.2",
-      "jsr:path": "jsr:@std/path@0.75.1"
+      "jsr:@std/path@1": "jsr:@std/path@1.0.0",
+      "npm:chalk@5": "npm:chalk@5.0.1"
+    },
+    "jsr": {
+      "@std/path@1.0.0": {
+        "integrity": "09154a97e18c4d6a1692e3b3c8a3b1ec2934f00b7c1caf7491d762d963ada045"
+      }
+    },
+    "npm": {
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {}
+      },
+      "chalk@5.0.1": {
+        "integrity": "sha512-chalk-new",
+        "dependencies": {}
+      }
     }
   },
-  "remote": {}
+  "remote": {},
+  "$other": 1
 }
-"#,
+"#
     );
+
+    // nothing left to repair
+    lockfile.has_content_changed = false;
+    assert!(lockfile.repair_misplaced_packages().is_empty());
+    assert!(!lockfile.has_content_changed);
   }
 
   #[test]
-  fn read_version_1() {
+  fn repair_misplaced_packages_invalid_section() {
     let content: &str = r#"{
-      "https://deno.land/std@0.71.0/textproto/mod.ts": "3118d7a42c03c242c5a49c2ad91c8396110e14acca1324e7aaefd31a999b71a4",
-      "https://deno.land/std@0.71.0/async/delay.ts": "35957d585a6e3dd87706858fb1d6b551cb278271b03f52c5a2cb70e65e00c26a"
-    }"#;
+  "version": "3",
+  "npm": ["chalk@5.0.0"],
+  "remote": {}
+}"#;
     let file_path = PathBuf::from("lockfile.json");
-    let lockfile =
+    let mut lockfile =
       Lockfile::with_lockfile_content(file_path, content, false).unwrap();
-    assert_eq!(lockfile.content.version, "3");
-    assert_eq!(lockfile.content.remote.len(), 2);
+    assert!(lockfile.repair_misplaced_packages().is_empty());
+    assert!(!lockfile.has_content_changed);
+    assert_eq!(lockfile.content.misplaced_package_sections(), vec!["npm"]);
   }
 
   #[test]
-  fn read_version_2() {
-    let content: &str = r#"{
-      "version": "2",
-      "remote": {
-        "https://deno.land/std@0.71.0/textproto/mod.ts": "3118d7a42c03c242c5a49c2ad91c8396110e14acca1324e7aaefd31a999b71a4",
-        "https://deno.land/std@0.71.0/async/delay.ts": "35957d585a6e3dd87706858fb1d6b551cb278271b03f52c5a2cb70e65e00c26a"
-      },
-      "npm": {
-        "specifiers": {
-          "nanoid": "nanoid@3.3.4"
-        },
-        "packages": {
-          "nanoid@3.3.4": {
-            "integrity": "sha512-MqBkQh/OHTS2egovRtLk45wEyNXwF+cokD+1YPf9u5VfJiRdAiRwB2froX5Co9Rh20xs4siNPm8naNotSD6RBw==",
-            "dependencies": {}
-          },
-          "picocolors@1.0.0": {
-            "integrity": "sha512-foobar",
-            "dependencies": {}
-          }
-        }
-      }
-    }"#;
+  fn byte_order_mark_and_crlf() {
     let file_path = PathBuf::from("lockfile.json");
+    let lf_lockfile = setup(false).unwrap();
+
+    let bom_text = format!("\u{FEFF}{}", LOCKFILE_JSON);
+    let mut lockfile =
+      Lockfile::with_lockfile_content(file_path.clone(), &bom_text, false)
+        .unwrap();
+    assert_eq!(lockfile.as_json_string(), lf_lockfile.as_json_string());
+    assert_eq!(lockfile.resolve_write_bytes(), None);
+    // restoring compares with the loaded text
+    let snapshot = lockfile.snapshot();
+    lockfile.insert_remote("https://deno.land/x/a.ts".to_string(), "a".into());
+    lockfile.restore(snapshot);
+    assert_eq!(lockfile.resolve_write_bytes(), None);
+
+    let crlf_text = LOCKFILE_JSON.replace('\n', "\r\n");
     let lockfile =
-      Lockfile::with_lockfile_content(file_path, content, false).unwrap();
-    assert_eq!(lockfile.content.version, "3");
-    assert_eq!(lockfile.content.packages.npm.len(), 2);
+      Lockfile::with_lockfile_content(file_path.clone(), &crlf_text, false)
+        .unwrap();
+    assert_eq!(lockfile.as_json_string(), lf_lockfile.as_json_string());
+    assert_eq!(lockfile.resolve_write_bytes(), None);
+
+    let err = Lockfile::with_lockfile_content(file_path, "\u{FEFF}\r\n", false)
+      .err()
+      .unwrap();
     assert_eq!(
-      lockfile.content.packages.specifiers,
-      BTreeMap::from([(
-        "npm:nanoid".to_string(),
-        "npm:nanoid@3.3.4".to_string()
-      )])
+      err.to_string(),
+      "Unable to read lockfile. Lockfile was empty."
     );
-    assert_eq!(lockfile.content.remote.len(), 2);
   }
 
   #[test]
-  fn insert_package_deps_changes_empty_insert() {
-    let content: &str = r#"{
-      "version": "2",
-      "remote": {}
-    }"#;
+  fn from_reader() {
     let file_path = PathBuf::from("lockfile.json");
-    let mut lockfile =
-      Lockfile::with_lockfile_content(file_path, content, false).unwrap();
+    let lockfile =
+      Lockfile::from_reader(file_path.clone(), LOCKFILE_JSON.as_bytes(), false)
+        .unwrap();
+    assert_eq!(
+      lockfile.as_json_string(),
+      setup(false).unwrap().as_json_string()
+    );
+    assert_eq!(lockfile.original_text(), Some(LOCKFILE_JSON));
 
-    assert!(!lockfile.has_content_changed);
-    lockfile.insert_package("dep".to_string(), "integrity".to_string());
-    // has changed even though it was empty
-    assert!(lockfile.has_content_changed);
+    // chained readers are read to the end
+    let (start, end) = LOCKFILE_JSON.split_at(20);
+    let lockfile = Lockfile::from_reader(
+      file_path.clone(),
+      std::io::Read::chain(start.as_bytes(), end.as_bytes()),
+      false,
+    )
+    .unwrap();
+    assert_eq!(lockfile.original_text(), Some(LOCKFILE_JSON));
 
-    // now try inserting the same package
-    lockfile.has_content_changed = false;
-    lockfile.insert_package("dep".to_string(), "integrity".to_string());
-    assert!(!lockfile.has_content_changed);
+    struct FailingReader;
 
-    // now with new deps
-    lockfile.add_package_deps("dep", vec!["dep2".to_string()].into_iter());
-    assert!(lockfile.has_content_changed);
-  }
+    impl std::io::Read for FailingReader {
+      fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+        Err(std::io::Error::new(
+          std::io::ErrorKind::Other,
+          "broken pipe",
+        ))
+      }
+    }
 
-  #[test]
-  fn empty_lockfile_nicer_error() {
-    let content: &str = r#"  "#;
-    let file_path = PathBuf::from("lockfile.json");
-    let err = Lockfile::with_lockfile_content(file_path, content, false)
+    let err = Lockfile::from_reader(file_path.clone(), FailingReader, false)
       .err()
       .unwrap();
+    assert!(matches!(err, Error::Io(..)));
     assert_eq!(
       err.to_string(),
-      "Unable to read lockfile. Lockfile was empty."
+      "Unable to read lockfile lockfile.json. broken pipe"
+    );
+    // nothing is read when overwriting
+    let lockfile =
+      Lockfile::from_reader(file_path.clone(), FailingReader, true).unwrap();
+    assert!(lockfile.content.is_empty());
+
+    let err = Lockfile::from_reader(file_path, &[0xff, 0xfe][..], false)
+      .err()
+      .unwrap();
+    assert!(matches!(err, Error::Io(..)));
+  }
+
+  #[test]
+  fn normalize_specifiers_then_prune() {
+    let content = r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "npm:chalk@^5": "npm:chalk@5.0.0",
+      "npm:nanoid@3.x": "npm:nanoid@3.3.4",
+      "npm:nanoid@^3": "npm:nanoid@3.3.4"
+    },
+    "npm": {
+      "chalk@5.0.0": {
+        "integrity": "sha512-chalk",
+        "dependencies": {}
+      },
+      "nanoid@3.3.4": {
+        "integrity": "sha512-nanoid",
+        "dependencies": {}
+      }
+    }
+  },
+  "remote": {},
+  "workspace": {
+    "dependencies": [
+      "npm:chalk@^5",
+      "npm:nanoid@3.x"
+    ],
+    "members": {
+      "a": {
+        "packageJson": {
+          "dependencies": [
+            "npm:nanoid@^3"
+          ]
+        }
+      }
+    }
+  }
+}"#;
+    let mut lockfile = Lockfile::with_lockfile_content(
+      PathBuf::from("lockfile.json"),
+      content,
+      false,
+    )
+    .unwrap();
+    assert_eq!(lockfile.normalize_specifiers(), Ok(1));
+    assert_eq!(
+      lockfile
+        .content
+        .packages
+        .specifiers
+        .keys()
+        .collect::<Vec<_>>(),
+      vec!["npm:chalk@^5", "npm:nanoid@^3.0.0"]
+    );
+    let workspace = &lockfile.content.workspace;
+    assert_eq!(
+      workspace.root().dependencies,
+      BTreeSet::from([
+        "npm:chalk@^5".to_string(),
+        "npm:nanoid@^3.0.0".to_string()
+      ])
+    );
+    assert_eq!(
+      workspace.members()["a"].package_json.dependencies,
+      BTreeSet::from(["npm:nanoid@^3.0.0".to_string()])
+    );
+
+    assert!(lockfile.content.unreachable_packages().is_empty());
+    let report = lockfile.prune(PruneOptions::default());
+    assert_eq!(report, PruneReport::default());
+    assert_eq!(lockfile.content.packages.npm.len(), 2);
+    assert_eq!(lockfile.content.packages.specifiers.len(), 2);
+  }
+
+  #[test]
+  fn transaction_rollback_after_write() {
+    let mut lockfile = setup(false).unwrap();
+    let text = lockfile.as_json_string();
+
+    let mut transaction = lockfile.transaction();
+    transaction.insert_remote(
+      "https://deno.land/x/new/mod.ts".to_string(),
+      "new".to_string(),
+    );
+    let (_, written) = transaction.resolve_write_bytes_if_newer(0).unwrap();
+    assert_eq!(written, 1);
+    drop(transaction);
+
+    // the restored content is newer than what was written
+    assert!(lockfile.has_content_changed);
+    let (bytes, generation) =
+      lockfile.resolve_write_bytes_if_newer(written).unwrap();
+    assert_eq!(String::from_utf8(bytes).unwrap(), text);
+    assert!(generation > written);
+
+    lockfile.insert_remote(
+      "https://deno.land/x/other/mod.ts".to_string(),
+      "other".to_string(),
     );
+    assert!(lockfile.resolve_write_bytes_if_newer(generation).is_some());
   }
 }
@@ -0,0 +1,253 @@
+// Copyright 2018-2024 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use crate::Error;
+use crate::LockfileContent;
+use crate::NpmPackageInfo;
+
+type JsonMap = serde_json::Map<String, serde_json::Value>;
+
+pub fn from_npm_package_lock(
+  json: &serde_json::Value,
+) -> Result<LockfileContent, Error> {
+  let Some(packages) = json.get("packages").and_then(|p| p.as_object()) else {
+    let version = json
+      .get("lockfileVersion")
+      .map(|v| v.to_string())
+      .unwrap_or_else(|| "none".to_string());
+    return Err(Error::InvalidNpmPackageLock(format!(
+      "Expected a \"packages\" object, which requires lockfileVersion 2 or 3 (found {}).",
+      version
+    )));
+  };
+
+  // collect the ids of the installed packages by their path
+  let mut path_ids = HashMap::with_capacity(packages.len());
+  for (path, entry) in packages {
+    if path.is_empty()
+      || entry.get("link").and_then(|l| l.as_bool()) == Some(true)
+    {
+      continue;
+    }
+    let version = entry.get("version").and_then(|v| v.as_str());
+    let integrity = entry.get("integrity").and_then(|i| i.as_str());
+    let (Some(version), Some(_)) = (version, integrity) else {
+      continue;
+    };
+    let name = entry
+      .get("name")
+      .and_then(|n| n.as_str())
+      .unwrap_or_else(|| path_package_name(path));
+    path_ids.insert(path.as_str(), format!("{}@{}", name, version));
+  }
+
+  let mut content = LockfileContent::empty();
+  for (path, entry) in packages {
+    let Some(entry) = entry.as_object() else {
+      continue;
+    };
+    if path.is_empty() {
+      for (name, range) in dependency_entries(entry) {
+        if let Some(id) = resolve_dependency(&path_ids, path, name) {
+          content.packages.specifiers.insert(
+            format!("npm:{}@{}", name, range.as_str().unwrap_or("*")),
+            format!("npm:{}", id),
+          );
+        }
+      }
+      continue;
+    }
+    let Some(id) = path_ids.get(path.as_str()) else {
+      continue;
+    };
+    if content.packages.npm.contains_key(id) {
+      continue;
+    }
+    let integrity = entry["integrity"].as_str().unwrap_or_default();
+    let resolve = |keys: &[&str]| {
+      dependency_entries_for(entry, keys)
+        .filter_map(|(name, _)| {
+          resolve_dependency(&path_ids, path, name)
+            .map(|dep_id| (name.clone(), dep_id.clone()))
+        })
+        .collect::<BTreeMap<_, _>>()
+    };
+    content.packages.npm.insert(
+      id.clone(),
+      NpmPackageInfo {
+        integrity: integrity.to_string(),
+        dependencies: resolve(&DEPENDENCY_KEYS),
+        optional_dependencies: resolve(&["optionalDependencies"]),
+        peer_dependencies: resolve(&["peerDependencies"]),
+      },
+    );
+  }
+  Ok(content)
+}
+
+/// Gets the name of the package installed at a path
+/// (ex. `@scope/b` from `node_modules/a/node_modules/@scope/b`).
+fn path_package_name(path: &str) -> &str {
+  match path.rfind("node_modules/") {
+    Some(index) => &path[index + "node_modules/".len()..],
+    None => path,
+  }
+}
+
+const DEPENDENCY_KEYS: [&str; 4] = [
+  "dependencies",
+  "devDependencies",
+  "optionalDependencies",
+  "peerDependencies",
+];
+
+fn dependency_entries(
+  entry: &JsonMap,
+) -> impl Iterator<Item = (&String, &serde_json::Value)> {
+  dependency_entries_for(entry, &DEPENDENCY_KEYS)
+}
+
+fn dependency_entries_for<'a>(
+  entry: &'a JsonMap,
+  keys: &'a [&'a str],
+) -> impl Iterator<Item = (&'a String, &'a serde_json::Value)> {
+  keys
+    .iter()
+    .filter_map(|key| entry.get(*key).and_then(|deps| deps.as_object()))
+    .flatten()
+}
+
+/// Resolves a dependency the same way node does by looking in the
+/// `node_modules` folder of the package, then in the ones of its ancestors.
+fn resolve_dependency<'a>(
+  path_ids: &'a HashMap<&str, String>,
+  from_path: &str,
+  name: &str,
+) -> Option<&'a String> {
+  let mut base = from_path;
+  loop {
+    let candidate = if base.is_empty() {
+      format!("node_modules/{}", name)
+    } else {
+      format!("{}/node_modules/{}", base, name)
+    };
+    if let Some(id) = path_ids.get(candidate.as_str()) {
+      return Some(id);
+    }
+    if base.is_empty() {
+      return None;
+    }
+    base = match base.rfind("/node_modules/") {
+      Some(index) => &base[..index],
+      None => "",
+    };
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use pretty_assertions::assert_eq;
+  use serde_json::json;
+
+  use super::*;
+
+  #[test]
+  fn converts_packages() {
+    let content = from_npm_package_lock(&json!({
+      "name": "project",
+      "lockfileVersion": 3,
+      "packages": {
+        "": {
+          "name": "project",
+          "dependencies": {
+            "@scope/a": "^1.0.0",
+            "local": "file:../local"
+          },
+          "devDependencies": {
+            "b": "~2.0.0"
+          }
+        },
+        "node_modules/@scope/a": {
+          "version": "1.0.0",
+          "resolved": "https://registry.npmjs.org/@scope/a/-/a-1.0.0.tgz",
+          "integrity": "sha512-a",
+          "dependencies": {
+            "b": "^1.0.0",
+            "c": "^1.0.0"
+          }
+        },
+        "node_modules/@scope/a/node_modules/b": {
+          "version": "1.0.0",
+          "integrity": "sha512-b1"
+        },
+        "node_modules/b": {
+          "version": "2.0.0",
+          "integrity": "sha512-b2",
+          "dev": true
+        },
+        "node_modules/c": {
+          "version": "1.0.0",
+          "integrity": "sha512-c",
+          "optionalDependencies": {
+            "b": "*"
+          }
+        },
+        "node_modules/local": {
+          "resolved": "../local",
+          "link": true
+        }
+      }
+    }))
+    .unwrap();
+    assert_eq!(
+      serde_json::to_value(content.packages).unwrap(),
+      json!({
+        "specifiers": {
+          "npm:@scope/a@^1.0.0": "npm:@scope/a@1.0.0",
+          "npm:b@~2.0.0": "npm:b@2.0.0"
+        },
+        "npm": {
+          "@scope/a@1.0.0": {
+            "integrity": "sha512-a",
+            "dependencies": {
+              "b": "b@1.0.0",
+              "c": "c@1.0.0"
+            }
+          },
+          "b@1.0.0": {
+            "integrity": "sha512-b1",
+            "dependencies": {}
+          },
+          "b@2.0.0": {
+            "integrity": "sha512-b2",
+            "dependencies": {}
+          },
+          "c@1.0.0": {
+            "integrity": "sha512-c",
+            "dependencies": {
+              "b": "b@2.0.0"
+            },
+            "optionalDependencies": {
+              "b": "b@2.0.0"
+            }
+          }
+        }
+      })
+    );
+  }
+
+  #[test]
+  fn errors_for_lockfile_version_1() {
+    let err = from_npm_package_lock(&json!({
+      "lockfileVersion": 1,
+      "dependencies": {}
+    }))
+    .unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "Unable to convert npm package-lock.json. Expected a \"packages\" object, which requires lockfileVersion 2 or 3 (found 1)."
+    );
+  }
+}
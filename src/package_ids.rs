@@ -0,0 +1,141 @@
+// Copyright 2018-2024 the Deno authors. All rights reserved. MIT license.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::extract_nv_from_id;
+use crate::npm_id_name;
+use crate::split_pkg_req;
+use crate::Error;
+use crate::RegistryKind;
+
+fn registry_kind(scheme: &str) -> RegistryKind {
+  match scheme {
+    "npm:" => RegistryKind::Npm,
+    _ => RegistryKind::Jsr,
+  }
+}
+
+/// A package requirement as it appears in the package specifiers
+/// (ex. `npm:chalk@^5` or `jsr:@std/path`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PackageReq(String);
+
+impl PackageReq {
+  /// Parses a package requirement, erroring when it doesn't start with
+  /// `npm:` or `jsr:` or is missing the package name.
+  pub fn parse(text: &str) -> Result<Self, Error> {
+    let is_valid = split_pkg_req(text).is_some_and(|(_, rest)| {
+      let name = npm_id_name(rest);
+      name != "@" && &rest[name.len()..] != "@"
+    });
+    if !is_valid {
+      return Err(Error::InvalidPackageSpecifier(
+        text.to_string(),
+        "Expected a package requirement starting with npm: or jsr:."
+          .to_string(),
+      ));
+    }
+    Ok(Self(text.to_string()))
+  }
+
+  /// Requirement as it's serialized in the lockfile.
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+
+  /// Registry the requirement is for.
+  pub fn kind(&self) -> RegistryKind {
+    registry_kind(&self.0[..4])
+  }
+
+  /// Name of the package (ex. `@std/path`).
+  pub fn name(&self) -> &str {
+    npm_id_name(&self.0[4..])
+  }
+
+  /// Version requirement (ex. `^5`) when there is one.
+  pub fn version_req(&self) -> Option<&str> {
+    self.0[4 + self.name().len()..].strip_prefix('@')
+  }
+}
+
+/// A package id that a requirement resolves to
+/// (ex. `npm:chalk@5.0.0` or `jsr:@std/path@0.75.0`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PackageId(String);
+
+impl PackageId {
+  /// Parses a package id, erroring when it doesn't start with `npm:` or
+  /// `jsr:` or is missing the name or version.
+  pub fn parse(text: &str) -> Result<Self, Error> {
+    match extract_nv_from_id(text) {
+      Some(_) => Ok(Self(text.to_string())),
+      None => Err(Error::InvalidPackageSpecifier(
+        text.to_string(),
+        "Expected a package id in the form npm:<name>@<version> or jsr:<name>@<version>."
+          .to_string(),
+      )),
+    }
+  }
+
+  /// Id as it's serialized in the lockfile.
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+
+  /// Registry the package is from.
+  pub fn kind(&self) -> RegistryKind {
+    registry_kind(&self.0[..4])
+  }
+
+  /// Name of the package (ex. `@std/path`).
+  pub fn name(&self) -> &str {
+    npm_id_name(&self.0[4..])
+  }
+
+  /// Version of the package, including the peer dependency suffix of
+  /// npm packages (ex. `1.0.0_peer@1.0.0`).
+  pub fn version(&self) -> &str {
+    &self.0[4 + self.name().len() + 1..]
+  }
+
+  /// Id without the scheme as it's keyed in the `npm` or `jsr`
+  /// section (ex. `chalk@5.0.0`).
+  pub fn package_key(&self) -> &str {
+    &self.0[4..]
+  }
+}
+
+macro_rules! impl_string_traits {
+  ($name:ident) => {
+    impl fmt::Display for $name {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+      }
+    }
+
+    impl FromStr for $name {
+      type Err = Error;
+
+      fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::parse(text)
+      }
+    }
+
+    impl AsRef<str> for $name {
+      fn as_ref(&self) -> &str {
+        &self.0
+      }
+    }
+
+    impl From<$name> for String {
+      fn from(value: $name) -> Self {
+        value.0
+      }
+    }
+  };
+}
+
+impl_string_traits!(PackageReq);
+impl_string_traits!(PackageId);
@@ -1,39 +1,372 @@
 // Copyright 2018-2024 the Deno authors. All rights reserved. MIT license.
 
+use std::collections::HashMap;
+
+use crate::npm_id_name;
+use crate::LockfileVersion;
+use crate::TransformError;
+
 pub type JsonMap = serde_json::Map<String, serde_json::Value>;
 
-pub fn transform1_to_2(json: JsonMap) -> JsonMap {
+/// Transforms a lockfile in version 1 to 3 to version 3, returning it
+/// along with the version it was in. Lockfiles without a `version` are
+/// version 1.
+///
+/// Version 4 lockfiles are unsupported since transforming them would
+/// downgrade them. That's only done when asked with [`transform4_to_3`].
+pub fn transform_to_current(
+  json: JsonMap,
+) -> Result<(JsonMap, LockfileVersion), TransformError> {
+  fn error(
+    from_version: &str,
+    to_version: &str,
+    message: String,
+  ) -> TransformError {
+    TransformError {
+      from_version: from_version.to_string(),
+      to_version: to_version.to_string(),
+      message,
+    }
+  }
+
+  let version = match json.get("version") {
+    Some(serde_json::Value::String(version)) => Some(version.as_str()),
+    Some(_) => {
+      return Err(error(
+        "unknown",
+        "3",
+        "Expected \"version\" to be a string.".to_string(),
+      ))
+    }
+    None => None,
+  };
+  match version {
+    Some("3") => Ok((json, LockfileVersion::V3)),
+    Some("2") => {
+      let json =
+        transform2_to_3(json).map_err(|message| error("2", "3", message))?;
+      Ok((json, LockfileVersion::V2))
+    }
+    None => {
+      let json =
+        transform1_to_2(json).map_err(|message| error("1", "2", message))?;
+      let json =
+        transform2_to_3(json).map_err(|message| error("2", "3", message))?;
+      Ok((json, LockfileVersion::V1))
+    }
+    Some(version) => Err(error(
+      version,
+      "3",
+      "The version is not supported.".to_string(),
+    )),
+  }
+}
+
+/// Wraps the remote modules of a version 1 lockfile in a version 2
+/// lockfile. Errors when a checksum isn't a string.
+pub fn transform1_to_2(json: JsonMap) -> Result<JsonMap, String> {
+  if let Some((url, _)) = json.iter().find(|(_, value)| !value.is_string()) {
+    return Err(format!(
+      "Expected a string checksum for remote module '{}'.",
+      url
+    ));
+  }
   let mut new_map = JsonMap::new();
   new_map.insert("version".to_string(), "2".into());
   new_map.insert("remote".to_string(), json.into());
-  new_map
+  Ok(new_map)
 }
 
-pub fn transform2_to_3(mut json: JsonMap) -> JsonMap {
+/// Moves the npm section of a version 2 lockfile under `packages` and
+/// prefixes its specifiers with `npm:`. Missing or null sections are
+/// treated as empty and unknown fields in the npm section are ignored.
+/// Errors with the offending entry when a section has the wrong shape.
+pub fn transform2_to_3(mut json: JsonMap) -> Result<JsonMap, String> {
+  fn take_object(
+    json: &mut JsonMap,
+    key: &str,
+    section: &str,
+  ) -> Result<Option<JsonMap>, String> {
+    match json.remove(key) {
+      Some(serde_json::Value::Object(obj)) => Ok(Some(obj)),
+      Some(serde_json::Value::Null) | None => Ok(None),
+      Some(_) => Err(format!("Expected {} to be an object.", section)),
+    }
+  }
+
   json.insert("version".into(), "3".into());
-  if let Some(serde_json::Value::Object(mut npm_obj)) = json.remove("npm") {
+  if let Some(remote) = take_object(&mut json, "remote", "\"remote\"")? {
+    if let Some((url, _)) = remote.iter().find(|(_, value)| !value.is_string())
+    {
+      return Err(format!(
+        "Expected a string checksum for remote module '{}'.",
+        url
+      ));
+    }
+    json.insert("remote".into(), remote.into());
+  }
+  if let Some(mut npm_obj) = take_object(&mut json, "npm", "\"npm\"")? {
+    let packages = take_object(&mut npm_obj, "packages", "\"npm.packages\"")?;
+    let specifiers =
+      take_object(&mut npm_obj, "specifiers", "\"npm.specifiers\"")?
+        .unwrap_or_default();
+
     let mut new_obj = JsonMap::new();
-    if let Some(packages) = npm_obj.remove("packages") {
-      new_obj.insert("npm".into(), packages);
+    if let Some(packages) = packages {
+      if let Some((id, _)) =
+        packages.iter().find(|(_, value)| !value.is_object())
+      {
+        return Err(format!("Expected an object for npm package '{}'.", id));
+      }
+      new_obj.insert("npm".into(), packages.into());
     }
-    if let Some(serde_json::Value::Object(specifiers)) =
-      npm_obj.remove("specifiers")
-    {
-      let mut new_specifiers = JsonMap::new();
-      for (key, value) in specifiers {
-        if let serde_json::Value::String(value) = value {
-          new_specifiers
-            .insert(format!("npm:{}", key), format!("npm:{}", value).into());
+    let mut new_specifiers = JsonMap::new();
+    for (key, value) in specifiers {
+      let serde_json::Value::String(value) = value else {
+        return Err(format!("Expected a string for specifier '{}'.", key));
+      };
+      new_specifiers
+        .insert(format!("npm:{}", key), format!("npm:{}", value).into());
+    }
+    if !new_specifiers.is_empty() {
+      new_obj.insert("specifiers".into(), new_specifiers.into());
+    }
+    json.insert("packages".into(), new_obj.into());
+  }
+
+  Ok(json)
+}
+
+/// Converts a version 3 lockfile to version 2, which only has remote
+/// modules and npm packages. Errors with the offending entry when there's
+/// data that can't be represented in version 2.
+pub fn transform3_to_2(mut json: JsonMap) -> Result<JsonMap, String> {
+  json.remove("version");
+  let remote = json
+    .remove("remote")
+    .unwrap_or_else(|| JsonMap::new().into());
+  let packages = match json.remove("packages") {
+    Some(serde_json::Value::Object(packages)) => packages,
+    Some(_) => return Err("Expected \"packages\" to be an object.".into()),
+    None => JsonMap::new(),
+  };
+  if let Some(key) = json.keys().next() {
+    return Err(format!(
+      "The \"{}\" field has no version 2 equivalent.",
+      key
+    ));
+  }
+
+  let mut specifiers = JsonMap::new();
+  let mut npm = JsonMap::new();
+  for (key, value) in packages {
+    match (key.as_str(), value) {
+      ("specifiers", serde_json::Value::Object(obj)) => {
+        for (req, id) in obj {
+          let new_id = id.as_str().and_then(|id| id.strip_prefix("npm:"));
+          match (req.strip_prefix("npm:"), new_id) {
+            (Some(new_req), Some(new_id)) => {
+              specifiers.insert(new_req.to_string(), new_id.into());
+            }
+            _ => {
+              return Err(format!(
+                "The specifier '{}' has no version 2 equivalent.",
+                req
+              ))
+            }
+          }
+        }
+      }
+      ("npm", serde_json::Value::Object(obj)) => {
+        for (id, info) in &obj {
+          let serde_json::Value::Object(info) = info else {
+            return Err(format!(
+              "Expected an object for npm package '{}'.",
+              id
+            ));
+          };
+          if let Some(key) = info
+            .keys()
+            .find(|key| !matches!(key.as_str(), "integrity" | "dependencies"))
+          {
+            return Err(format!(
+              "The \"{}\" field of npm package '{}' has no version 2 equivalent.",
+              key, id
+            ));
+          }
         }
+        npm = obj;
       }
-      if !new_specifiers.is_empty() {
-        new_obj.insert("specifiers".into(), new_specifiers.into());
+      (key, _) => {
+        return Err(format!(
+          "The \"{}\" packages have no version 2 equivalent.",
+          key
+        ))
       }
     }
-    json.insert("packages".into(), new_obj.into());
   }
 
-  json
+  json.insert("version".into(), "2".into());
+  json.insert("remote".into(), remote);
+  if !specifiers.is_empty() || !npm.is_empty() {
+    let mut new_npm = JsonMap::new();
+    new_npm.insert("specifiers".into(), specifiers.into());
+    new_npm.insert("packages".into(), npm.into());
+    json.insert("npm".into(), new_npm.into());
+  }
+  Ok(json)
+}
+
+/// Converts a version 2 lockfile to version 1, which is only the
+/// remote modules. Errors when the lockfile has npm packages.
+pub fn transform2_to_1(mut json: JsonMap) -> Result<JsonMap, String> {
+  json.remove("version");
+  let remote = match json.remove("remote") {
+    Some(serde_json::Value::Object(remote)) => remote,
+    Some(_) => return Err("Expected \"remote\" to be an object.".into()),
+    None => JsonMap::new(),
+  };
+  if let Some(key) = json.keys().next() {
+    return Err(format!(
+      "The \"{}\" field has no version 1 equivalent.",
+      key
+    ));
+  }
+  Ok(remote)
+}
+
+/// Moves the package sections of a version 4 lockfile back under
+/// `packages` and reconstructs the version 3 specifier and npm dependency
+/// formats. Errors with the offending entry when there's data that can't
+/// be represented in version 3.
+pub fn transform4_to_3(mut json: JsonMap) -> Result<JsonMap, String> {
+  fn take_object(json: &mut JsonMap, key: &str) -> Result<JsonMap, String> {
+    match json.remove(key) {
+      Some(serde_json::Value::Object(obj)) => Ok(obj),
+      Some(_) => Err(format!("Expected \"{}\" to be an object.", key)),
+      None => Ok(JsonMap::new()),
+    }
+  }
+
+  json.insert("version".into(), "3".into());
+  let specifiers = take_object(&mut json, "specifiers")?;
+  let jsr = take_object(&mut json, "jsr")?;
+  let npm = take_object(&mut json, "npm")?;
+
+  let mut new_specifiers = JsonMap::new();
+  for (req, id) in specifiers {
+    let Some(id) = id.as_str() else {
+      return Err(format!("Expected a string for specifier '{}'.", req));
+    };
+    let id = if id.starts_with("npm:") || id.starts_with("jsr:") {
+      id.to_string()
+    } else {
+      let (scheme, name) = match req.get(..4) {
+        Some(scheme @ ("npm:" | "jsr:")) => (scheme, npm_id_name(&req[4..])),
+        _ => return Err(format!("Unknown scheme for specifier '{}'.", req)),
+      };
+      format!("{}{}@{}", scheme, name, id)
+    };
+    new_specifiers.insert(req, id.into());
+  }
+
+  let mut new_jsr = JsonMap::new();
+  for (nv, info) in jsr {
+    let info = match info {
+      serde_json::Value::Object(info) => info,
+      _ => return Err(format!("Expected an object for jsr package '{}'.", nv)),
+    };
+    if let Some(key) = info
+      .keys()
+      .find(|key| !matches!(key.as_str(), "integrity" | "dependencies"))
+    {
+      return Err(format!(
+        "The \"{}\" field of jsr package '{}' has no version 3 equivalent.",
+        key, nv
+      ));
+    }
+    new_jsr.insert(nv, info.into());
+  }
+
+  let mut ids_by_name: HashMap<&str, Vec<&str>> = HashMap::new();
+  for id in npm.keys() {
+    ids_by_name.entry(npm_id_name(id)).or_default().push(id);
+  }
+  let mut new_npm = JsonMap::new();
+  for (id, info) in &npm {
+    let serde_json::Value::Object(info) = info else {
+      return Err(format!("Expected an object for npm package '{}'.", id));
+    };
+    let mut new_info = JsonMap::new();
+    for (key, value) in info {
+      match key.as_str() {
+        "integrity" => {
+          new_info.insert(key.clone(), value.clone());
+        }
+        "dependencies" => {
+          let Some(deps) = value.as_array() else {
+            return Err(format!(
+              "Expected an array for the dependencies of npm package '{}'.",
+              id
+            ));
+          };
+          let mut new_deps = JsonMap::new();
+          for dep in deps {
+            let Some(dep) = dep.as_str() else {
+              return Err(format!(
+                "Expected a string dependency for npm package '{}'.",
+                id
+              ));
+            };
+            let name = npm_id_name(dep);
+            let rest = &dep[name.len()..];
+            let dep_id = if rest.is_empty() {
+              match ids_by_name.get(name).map(|ids| ids.as_slice()) {
+                Some([dep_id]) => dep_id.to_string(),
+                _ => {
+                  return Err(format!(
+                    "Unable to resolve dependency '{}' of npm package '{}'.",
+                    dep, id
+                  ))
+                }
+              }
+            } else if let Some(dep_id) = rest.strip_prefix("@npm:") {
+              dep_id.to_string()
+            } else {
+              dep.to_string()
+            };
+            new_deps.insert(name.to_string(), dep_id.into());
+          }
+          new_info.insert(key.clone(), new_deps.into());
+        }
+        _ => {
+          return Err(format!(
+            "The \"{}\" field of npm package '{}' has no version 3 equivalent.",
+            key, id
+          ));
+        }
+      }
+    }
+    new_info
+      .entry("dependencies")
+      .or_insert_with(|| JsonMap::new().into());
+    new_npm.insert(id.clone(), new_info.into());
+  }
+
+  let mut packages = JsonMap::new();
+  if !new_specifiers.is_empty() {
+    packages.insert("specifiers".into(), new_specifiers.into());
+  }
+  if !new_jsr.is_empty() {
+    packages.insert("jsr".into(), new_jsr.into());
+  }
+  if !new_npm.is_empty() {
+    packages.insert("npm".into(), new_npm.into());
+  }
+  if !packages.is_empty() {
+    json.insert("packages".into(), packages.into());
+  }
+  Ok(json)
 }
 
 #[cfg(test)]
@@ -50,7 +383,7 @@ mod test {
       "https://github.com/mod.ts": "asdf2",
     }))
     .unwrap();
-    let result = transform1_to_2(data);
+    let result = transform1_to_2(data).unwrap();
     assert_eq!(
       result,
       serde_json::from_value(json!({
@@ -88,7 +421,7 @@ mod test {
         }
       }
     })).unwrap();
-    let result = transform2_to_3(data);
+    let result = transform2_to_3(data).unwrap();
     assert_eq!(result, serde_json::from_value(json!({
       "version": "3",
       "remote": {
@@ -112,4 +445,309 @@ mod test {
       }
     })).unwrap());
   }
+
+  #[test]
+  fn test_transforms_2_to_3_missing_sections() {
+    let transform = |value: serde_json::Value| {
+      transform2_to_3(serde_json::from_value(value).unwrap()).unwrap()
+    };
+    let expected = |value: serde_json::Value| -> JsonMap {
+      serde_json::from_value(value).unwrap()
+    };
+    assert_eq!(
+      transform(json!({ "version": "2", "remote": null, "npm": null })),
+      expected(json!({ "version": "3" }))
+    );
+    assert_eq!(
+      transform(json!({
+        "version": "2",
+        "remote": {},
+        "npm": {
+          "specifiers": { "nanoid": "nanoid@3.3.4" },
+          "registry": {}
+        }
+      })),
+      expected(json!({
+        "version": "3",
+        "remote": {},
+        "packages": {
+          "specifiers": { "npm:nanoid": "npm:nanoid@3.3.4" }
+        }
+      }))
+    );
+    assert_eq!(
+      transform(json!({
+        "version": "2",
+        "npm": {
+          "packages": {
+            "nanoid@3.3.4": { "integrity": "sha512-foobar", "dependencies": {} }
+          }
+        }
+      })),
+      expected(json!({
+        "version": "3",
+        "packages": {
+          "npm": {
+            "nanoid@3.3.4": { "integrity": "sha512-foobar", "dependencies": {} }
+          }
+        }
+      }))
+    );
+  }
+
+  #[test]
+  fn test_transforms_3_to_2_to_1() {
+    let data: JsonMap = serde_json::from_value(json!({
+      "version": "3",
+      "packages": {
+        "specifiers": {
+          "npm:nanoid": "npm:nanoid@3.3.4",
+        },
+        "npm": {
+          "nanoid@3.3.4": {
+            "integrity": "sha512-nanoid",
+            "dependencies": {}
+          }
+        }
+      },
+      "remote": {
+        "https://github.com/mod.ts": "asdf",
+      }
+    }))
+    .unwrap();
+    let v2 = transform3_to_2(data.clone()).unwrap();
+    assert_eq!(
+      v2,
+      serde_json::from_value::<JsonMap>(json!({
+        "version": "2",
+        "remote": {
+          "https://github.com/mod.ts": "asdf",
+        },
+        "npm": {
+          "specifiers": {
+            "nanoid": "nanoid@3.3.4",
+          },
+          "packages": {
+            "nanoid@3.3.4": {
+              "integrity": "sha512-nanoid",
+              "dependencies": {}
+            }
+          }
+        }
+      }))
+      .unwrap()
+    );
+    // the forward transform gives back the original
+    assert_eq!(transform2_to_3(v2.clone()).unwrap(), data);
+
+    assert_eq!(
+      transform2_to_1(v2).unwrap_err(),
+      "The \"npm\" field has no version 1 equivalent."
+    );
+    let v1 = transform2_to_1(
+      transform3_to_2(
+        serde_json::from_value(json!({
+          "version": "3",
+          "remote": { "https://github.com/mod.ts": "asdf" }
+        }))
+        .unwrap(),
+      )
+      .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+      v1,
+      serde_json::from_value::<JsonMap>(json!({
+        "https://github.com/mod.ts": "asdf"
+      }))
+      .unwrap()
+    );
+  }
+
+  #[test]
+  fn test_transforms_3_to_2_errors() {
+    let transform = |value: serde_json::Value| {
+      transform3_to_2(serde_json::from_value(value).unwrap()).unwrap_err()
+    };
+    assert_eq!(
+      transform(json!({
+        "version": "3",
+        "packages": {
+          "specifiers": { "jsr:@std/path@1": "jsr:@std/path@1.0.0" }
+        },
+        "remote": {}
+      })),
+      "The specifier 'jsr:@std/path@1' has no version 2 equivalent."
+    );
+    assert_eq!(
+      transform(json!({
+        "version": "3",
+        "packages": {
+          "jsr": { "@std/path@1.0.0": { "integrity": "abc" } }
+        },
+        "remote": {}
+      })),
+      "The \"jsr\" packages have no version 2 equivalent."
+    );
+    assert_eq!(
+      transform(json!({
+        "version": "3",
+        "remote": {},
+        "workspace": { "dependencies": ["npm:chalk@5"] }
+      })),
+      "The \"workspace\" field has no version 2 equivalent."
+    );
+  }
+
+  #[test]
+  fn test_transforms_4_to_3() {
+    let data: JsonMap = serde_json::from_value(json!({
+      "version": "4",
+      "specifiers": {
+        "jsr:@std/path@1": "1.0.0",
+        "npm:chalk@5": "5.0.0",
+        "npm:colors@1": "npm:chalk@5.0.0",
+      },
+      "jsr": {
+        "@std/path@1.0.0": {
+          "integrity": "sha256-path",
+          "dependencies": ["jsr:@std/assert@1"]
+        }
+      },
+      "npm": {
+        "ansi-styles@4.1.0": {
+          "integrity": "sha512-a"
+        },
+        "chalk@5.0.0": {
+          "integrity": "sha512-c",
+          "dependencies": [
+            "ansi-styles",
+            "color-name@1.0.0",
+            "colors@npm:color-name@2.0.0"
+          ]
+        },
+        "color-name@1.0.0": {
+          "integrity": "sha512-c1"
+        },
+        "color-name@2.0.0": {
+          "integrity": "sha512-c2"
+        }
+      },
+      "remote": {
+        "https://github.com/mod.ts": "asdf",
+      }
+    }))
+    .unwrap();
+    let result = transform4_to_3(data).unwrap();
+    assert_eq!(
+      result,
+      serde_json::from_value::<JsonMap>(json!({
+        "version": "3",
+        "packages": {
+          "specifiers": {
+            "jsr:@std/path@1": "jsr:@std/path@1.0.0",
+            "npm:chalk@5": "npm:chalk@5.0.0",
+            "npm:colors@1": "npm:chalk@5.0.0",
+          },
+          "jsr": {
+            "@std/path@1.0.0": {
+              "integrity": "sha256-path",
+              "dependencies": ["jsr:@std/assert@1"]
+            }
+          },
+          "npm": {
+            "ansi-styles@4.1.0": {
+              "integrity": "sha512-a",
+              "dependencies": {}
+            },
+            "chalk@5.0.0": {
+              "integrity": "sha512-c",
+              "dependencies": {
+                "ansi-styles": "ansi-styles@4.1.0",
+                "color-name": "color-name@1.0.0",
+                "colors": "color-name@2.0.0"
+              }
+            },
+            "color-name@1.0.0": {
+              "integrity": "sha512-c1",
+              "dependencies": {}
+            },
+            "color-name@2.0.0": {
+              "integrity": "sha512-c2",
+              "dependencies": {}
+            }
+          }
+        },
+        "remote": {
+          "https://github.com/mod.ts": "asdf",
+        }
+      }))
+      .unwrap()
+    );
+  }
+
+  #[test]
+  fn test_transforms_4_to_3_aliases() {
+    // aliases with the name of another or the same package
+    let data: JsonMap = serde_json::from_value(json!({
+      "version": "4",
+      "npm": {
+        "a@1.0.0": {
+          "integrity": "sha512-a",
+          "dependencies": [
+            "b@npm:c@1.0.0",
+            "c@npm:c@2.0.0",
+            "@scope/d@npm:@scope/d@1.0.0"
+          ]
+        },
+        "b@1.0.0": { "integrity": "sha512-b" },
+        "c@1.0.0": { "integrity": "sha512-c1" },
+        "c@2.0.0": { "integrity": "sha512-c2" },
+        "@scope/d@1.0.0": { "integrity": "sha512-d" }
+      }
+    }))
+    .unwrap();
+    let result = transform4_to_3(data).unwrap();
+    assert_eq!(
+      result["packages"]["npm"]["a@1.0.0"]["dependencies"],
+      json!({
+        "b": "c@1.0.0",
+        "c": "c@2.0.0",
+        "@scope/d": "@scope/d@1.0.0"
+      })
+    );
+  }
+
+  #[test]
+  fn test_transforms_4_to_3_errors() {
+    let transform = |value: serde_json::Value| {
+      transform4_to_3(serde_json::from_value(value).unwrap()).unwrap_err()
+    };
+    assert_eq!(
+      transform(json!({
+        "version": "4",
+        "npm": {
+          "chalk@5.0.0": {
+            "integrity": "sha512-c",
+            "dependencies": ["color-name"]
+          },
+          "color-name@1.0.0": { "integrity": "sha512-c1" },
+          "color-name@2.0.0": { "integrity": "sha512-c2" }
+        }
+      })),
+      "Unable to resolve dependency 'color-name' of npm package 'chalk@5.0.0'."
+    );
+    assert_eq!(
+      transform(json!({
+        "version": "4",
+        "npm": {
+          "fsevents@2.3.3": {
+            "integrity": "sha512-f",
+            "os": ["darwin"]
+          }
+        }
+      })),
+      "The \"os\" field of npm package 'fsevents@2.3.3' has no version 3 equivalent."
+    );
+  }
 }
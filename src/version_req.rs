@@ -0,0 +1,290 @@
+// Copyright 2018-2024 the Deno authors. All rights reserved. MIT license.
+
+/// Gets the canonical spelling of a package requirement
+/// (ex. `npm:chalk@^5.0.0` for `npm:chalk@5.x`).
+///
+/// Equivalent version ranges are spelled the same way, so requirements
+/// that were written differently over time can be deduplicated. Ranges
+/// that can't be parsed, such as tags, are kept as is.
+pub fn canonicalize_package_req(req: &str) -> String {
+  let Some((name, range)) = split_req_range(req) else {
+    return req.to_string();
+  };
+  match canonicalize_range(range) {
+    Some(range) => format!("{}@{}", name, range),
+    None => req.to_string(),
+  }
+}
+
+/// Splits a package requirement into the scheme and name
+/// (ex. `npm:@scope/name`) and the version range.
+fn split_req_range(req: &str) -> Option<(&str, &str)> {
+  if !req.starts_with("npm:") && !req.starts_with("jsr:") {
+    return None;
+  }
+  let rest = &req[4..];
+  // skip the @ of a scoped package name
+  let index = rest.get(1..)?.find('@')? + 1;
+  Some((&req[..4 + index], &rest[index + 1..]))
+}
+
+fn canonicalize_range(range: &str) -> Option<String> {
+  let mut alternatives = Vec::new();
+  for alternative in range.split("||") {
+    let mut comparators = Vec::new();
+    for (op, version) in parse_comparators(alternative.trim())? {
+      comparators.push(canonicalize_comparator(op, &parse_version(version)?)?);
+    }
+    // a wildcard doesn't restrict the other comparators
+    if comparators.len() > 1 {
+      comparators.retain(|comparator| comparator != "*");
+    }
+    if comparators.is_empty() {
+      comparators.push("*".to_string());
+    }
+    alternatives.push(comparators.join(" "));
+  }
+  Some(alternatives.join(" || "))
+}
+
+const OPERATORS: [&str; 8] = [">=", "<=", "~>", ">", "<", "=", "^", "~"];
+
+/// Parses the comparators of a range without `||` into their operator
+/// and version, converting hyphen ranges (ex. `1.2.3 - 2.3.4`).
+fn parse_comparators(text: &str) -> Option<Vec<(&str, &str)>> {
+  let tokens = text.split_whitespace().collect::<Vec<_>>();
+  if tokens.len() == 3 && tokens[1] == "-" {
+    return Some(vec![(">=", tokens[0]), ("<=", tokens[2])]);
+  }
+
+  let mut comparators = Vec::with_capacity(tokens.len());
+  let mut tokens = tokens.into_iter();
+  while let Some(token) = tokens.next() {
+    let op = OPERATORS
+      .iter()
+      .find(|op| token.starts_with(**op))
+      .copied()
+      .unwrap_or("");
+    let version = &token[op.len()..];
+    let version = if version.is_empty() && !op.is_empty() {
+      // operator separated from its version (ex. `>= 5.0.0`)
+      tokens.next()?
+    } else {
+      version
+    };
+    comparators.push((op, version));
+  }
+  Some(comparators)
+}
+
+struct Version<'a> {
+  /// Numeric parts until the first missing or wildcard part.
+  parts: Vec<u64>,
+  /// Prerelease and build metadata including the leading `-` or `+`.
+  suffix: &'a str,
+}
+
+fn parse_version(text: &str) -> Option<Version> {
+  let text = match text.strip_prefix('v') {
+    Some(rest) if rest.starts_with(|c: char| c.is_ascii_digit()) => rest,
+    _ => text,
+  };
+  let suffix_index = text.find(['-', '+']).unwrap_or(text.len());
+  let (core, suffix) = text.split_at(suffix_index);
+  let mut parts = Vec::with_capacity(3);
+  let mut has_wildcard = false;
+  for (index, part) in core.split('.').enumerate() {
+    if index > 2 {
+      return None;
+    }
+    if matches!(part, "x" | "X" | "*") || (part.is_empty() && index == 0) {
+      has_wildcard = true;
+    } else if has_wildcard {
+      // parts after a wildcard must be wildcards too (ex. `1.x.x`)
+      return None;
+    } else {
+      if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+      }
+      parts.push(part.parse().ok()?);
+    }
+  }
+  if !suffix.is_empty() && parts.len() != 3 {
+    return None;
+  }
+  Some(Version { parts, suffix })
+}
+
+fn canonicalize_comparator(op: &str, version: &Version) -> Option<String> {
+  let parts = &version.parts;
+  let suffix = version.suffix;
+  let comparator = match (op, parts.as_slice()) {
+    // these don't match any version
+    (">" | "<", []) => return None,
+    (_, []) => "*".to_string(),
+    ("" | "=", [major, minor, patch]) => {
+      format!("{}.{}.{}{}", major, minor, patch, suffix)
+    }
+    ("" | "=" | "~" | "~>", [major, minor]) => tilde(*major, *minor, 0, ""),
+    ("" | "=" | "~" | "~>" | "^", [major]) => caret_major(*major),
+    ("~" | "~>", [major, minor, patch]) => {
+      tilde(*major, *minor, *patch, suffix)
+    }
+    ("^", [major, minor, patch]) => {
+      format!("^{}.{}.{}{}", major, minor, patch, suffix)
+    }
+    ("^", [0, 0]) => tilde(0, 0, 0, ""),
+    ("^", [major, minor]) => format!("^{}.{}.0", major, minor),
+    (">=" | "<", _) => format!("{}{}", op, full_version(parts, suffix)),
+    (">" | "<=", [_, _, _]) => format!("{}{}", op, full_version(parts, suffix)),
+    // `>1.2` is `>=1.3.0` and `<=1.2` is `<1.3.0`
+    (">" | "<=", _) => {
+      let mut parts = parts.clone();
+      let last = parts.last_mut()?;
+      *last = last.checked_add(1)?;
+      let op = if op == ">" { ">=" } else { "<" };
+      format!("{}{}", op, full_version(&parts, ""))
+    }
+    // not reachable with the operators and versions that are parsed
+    _ => return None,
+  };
+  Some(comparator)
+}
+
+/// Fills the missing parts of a version with zeros.
+fn full_version(parts: &[u64], suffix: &str) -> String {
+  let get = |index: usize| parts.get(index).copied().unwrap_or(0);
+  format!("{}.{}.{}{}", get(0), get(1), get(2), suffix)
+}
+
+fn caret_major(major: u64) -> String {
+  if major == 0 {
+    // `^0.0.0` only allows `0.0.0`
+    "^0".to_string()
+  } else {
+    format!("^{}.0.0", major)
+  }
+}
+
+fn tilde(major: u64, minor: u64, patch: u64, suffix: &str) -> String {
+  if major == 0 && minor > 0 {
+    // `~0.2.3` and `^0.2.3` both allow `>=0.2.3 <0.3.0`
+    format!("^0.{}.{}{}", minor, patch, suffix)
+  } else {
+    format!("~{}.{}.{}{}", major, minor, patch, suffix)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[track_caller]
+  fn assert_canonical(reqs: &[&str], expected: &str) {
+    for req in reqs {
+      assert_eq!(canonicalize_package_req(req), expected, "{}", req);
+    }
+    assert_eq!(canonicalize_package_req(expected), expected);
+  }
+
+  #[test]
+  fn canonicalizes_caret_and_tilde() {
+    assert_canonical(
+      &[
+        "npm:chalk@^5",
+        "npm:chalk@^5.0",
+        "npm:chalk@5",
+        "npm:chalk@5.x",
+        "npm:chalk@5.*.*",
+        "npm:chalk@~5",
+        "npm:chalk@^ 5.0.0",
+      ],
+      "npm:chalk@^5.0.0",
+    );
+    assert_canonical(
+      &["npm:chalk@^0", "npm:chalk@0", "npm:chalk@~0"],
+      "npm:chalk@^0",
+    );
+    assert_canonical(
+      &["npm:chalk@~5.1", "npm:chalk@5.1", "npm:chalk@~>5.1.0"],
+      "npm:chalk@~5.1.0",
+    );
+    assert_canonical(
+      &[
+        "npm:chalk@~0.2",
+        "npm:chalk@0.2",
+        "npm:chalk@^0.2",
+        "npm:chalk@~0.2.0",
+      ],
+      "npm:chalk@^0.2.0",
+    );
+    assert_canonical(
+      &["npm:chalk@^0.0", "npm:chalk@0.0", "npm:chalk@~0.0"],
+      "npm:chalk@~0.0.0",
+    );
+    // already canonical
+    assert_canonical(&[], "npm:chalk@^0.0.3");
+    assert_canonical(&[], "npm:chalk@~1.2.3-beta.1");
+    assert_canonical(&["jsr:@std/path@~0.75.1"], "jsr:@std/path@^0.75.1");
+  }
+
+  #[test]
+  fn canonicalizes_comparators() {
+    assert_canonical(
+      &[
+        "npm:chalk@>=5",
+        "npm:chalk@>= 5.0",
+        "npm:chalk@  >=5.0.0  ",
+        "npm:chalk@>=v5.0.0",
+      ],
+      "npm:chalk@>=5.0.0",
+    );
+    assert_canonical(
+      &[
+        "npm:chalk@>=5 <6",
+        "npm:chalk@>= 5.0.0 < 6",
+        "npm:chalk@5 - 5",
+      ],
+      "npm:chalk@>=5.0.0 <6.0.0",
+    );
+    assert_canonical(&["npm:chalk@>5.1"], "npm:chalk@>=5.2.0");
+    assert_canonical(&["npm:chalk@<=5"], "npm:chalk@<6.0.0");
+    assert_canonical(&[], "npm:chalk@>5.1.0");
+    assert_canonical(&[], "npm:chalk@>18446744073709551615");
+    assert_canonical(&["npm:chalk@1.2 - 2.3.4"], "npm:chalk@>=1.2.0 <=2.3.4");
+    assert_canonical(
+      &["npm:chalk@^4||5.x", "npm:chalk@^4.0  ||  ^5"],
+      "npm:chalk@^4.0.0 || ^5.0.0",
+    );
+    assert_canonical(
+      &["npm:chalk@*", "npm:chalk@x", "npm:chalk@"],
+      "npm:chalk@*",
+    );
+  }
+
+  #[test]
+  fn canonicalizes_exact_versions() {
+    assert_canonical(
+      &["npm:chalk@=5.0.0", "npm:chalk@v5.0.0", "npm:chalk@ 5.0.0"],
+      "npm:chalk@5.0.0",
+    );
+    assert_canonical(
+      &["npm:@scope/a@=1.0.0-rc.1+build"],
+      "npm:@scope/a@1.0.0-rc.1+build",
+    );
+  }
+
+  #[test]
+  fn keeps_unknown_requirements() {
+    for req in [
+      "npm:chalk",
+      "npm:@scope/a",
+      "npm:chalk@latest",
+      "npm:chalk@5.0.0.0",
+      "npm:chalk@1.x.3",
+      "https://deno.land/x/a@1/mod.ts",
+    ] {
+      assert_eq!(canonicalize_package_req(req), req);
+    }
+  }
+}
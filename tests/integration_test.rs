@@ -1,9 +1,15 @@
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::path::Path;
 use std::path::PathBuf;
 
+use deno_lockfile::DotKindStyle;
+use deno_lockfile::DotOptions;
+use deno_lockfile::MermaidOptions;
 use deno_lockfile::PackagesContent;
 use deno_lockfile::WorkspaceConfig;
+use deno_lockfile::WorkspaceConfigChange;
+use deno_lockfile::WorkspaceConfigContent;
 use deno_lockfile::WorkspaceMemberConfig;
 use pretty_assertions::assert_eq;
 
@@ -12,61 +18,11 @@ use deno_lockfile::SetWorkspaceConfigOptions;
 
 use helpers::ConfigChangeSpec;
 use serde::Deserialize;
-use serde::Serialize;
 
 mod helpers;
 
 #[test]
 fn config_changes() {
-  #[derive(Debug, Default, Clone, Serialize, Deserialize, Hash)]
-  #[serde(rename_all = "camelCase")]
-  struct LockfilePackageJsonContent {
-    #[serde(default)]
-    dependencies: BTreeSet<String>,
-  }
-
-  #[derive(Debug, Default, Clone, Deserialize, Hash)]
-  #[serde(rename_all = "camelCase")]
-  struct WorkspaceMemberConfigContent {
-    #[serde(default)]
-    dependencies: BTreeSet<String>,
-    #[serde(default)]
-    package_json: LockfilePackageJsonContent,
-  }
-
-  #[derive(Debug, Default, Clone, Deserialize, Hash)]
-  #[serde(rename_all = "camelCase")]
-  struct WorkspaceConfigContent {
-    #[serde(default, flatten)]
-    root: WorkspaceMemberConfigContent,
-    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
-    #[serde(default)]
-    members: BTreeMap<String, WorkspaceMemberConfigContent>,
-  }
-  impl WorkspaceConfigContent {
-    fn into_workspace_config(self) -> WorkspaceConfig {
-      WorkspaceConfig {
-        root: WorkspaceMemberConfig {
-          dependencies: self.root.dependencies,
-          package_json_deps: self.root.package_json.dependencies,
-        },
-        members: self
-          .members
-          .into_iter()
-          .map(|(k, v)| {
-            (
-              k,
-              WorkspaceMemberConfig {
-                dependencies: v.dependencies,
-                package_json_deps: v.package_json.dependencies,
-              },
-            )
-          })
-          .collect(),
-      }
-    }
-  }
-
   let specs = ConfigChangeSpec::collect_in_dir(&PathBuf::from(
     "./tests/specs/config_changes",
   ));
@@ -82,18 +38,38 @@ fn config_changes() {
     for change_and_output in &mut spec.change_and_outputs {
       // setting the new workspace config should change the has_content_changed flag
       config_file.has_content_changed = false;
-      let config = serde_json::from_str::<WorkspaceConfigContent>(
-        &change_and_output.change.text,
-      )
+      let config: WorkspaceConfig = serde_json::from_str::<
+        WorkspaceConfigContent,
+      >(&change_and_output.change.text)
       .unwrap()
-      .into_workspace_config();
+      .into();
       let no_npm = change_and_output.change.title.contains("--no-npm");
       let no_config = change_and_output.change.title.contains("--no-config");
-      config_file.set_workspace_config(SetWorkspaceConfigOptions {
-        no_config,
-        no_npm,
-        config: config.clone(),
-      });
+      let old_packages = config_file.content.packages.clone();
+      let change =
+        config_file.set_workspace_config(SetWorkspaceConfigOptions {
+          no_config,
+          no_npm,
+          config: config.clone(),
+        });
+      verify_workspace_config_change(
+        &change,
+        &old_packages,
+        &config_file.content.packages,
+      );
+      if spec.path.ends_with("RemovedPackageJsonRemoves.txt") {
+        assert_eq!(
+          change.removed_reqs,
+          BTreeSet::from(["npm:ts-morph".to_string()])
+        );
+        assert_eq!(
+          change.specifiers,
+          BTreeSet::from(["npm:ts-morph".to_string()])
+        );
+        assert_eq!(change.npm_packages.len(), 26);
+        assert!(change.npm_packages.contains("ts-morph@21.0.1"));
+        assert!(change.jsr_packages.is_empty());
+      }
       assert_eq!(
         config_file.has_content_changed,
         !change_and_output.change.title.contains("no change"),
@@ -103,12 +79,14 @@ fn config_changes() {
 
       // now try resetting it and the flag should remain the same
       config_file.has_content_changed = false;
-      config_file.set_workspace_config(SetWorkspaceConfigOptions {
-        no_config,
-        no_npm,
-        config: config.clone(),
-      });
+      let change =
+        config_file.set_workspace_config(SetWorkspaceConfigOptions {
+          no_config,
+          no_npm,
+          config: config.clone(),
+        });
       assert!(!config_file.has_content_changed);
+      assert_eq!(change, WorkspaceConfigChange::default());
 
       let expected_text = change_and_output.output.text.clone();
       let actual_text = config_file.as_json_string();
@@ -131,6 +109,172 @@ fn config_changes() {
   }
 }
 
+#[test]
+fn dot_output() {
+  #[derive(Debug, Default, Clone, Deserialize)]
+  #[serde(rename_all = "camelCase")]
+  struct DotOptionsContent {
+    #[serde(default)]
+    kind_style: Option<String>,
+    #[serde(default)]
+    root: Option<String>,
+    #[serde(default)]
+    max_depth: Option<usize>,
+  }
+
+  let specs =
+    ConfigChangeSpec::collect_in_dir(&PathBuf::from("./tests/specs/dot"));
+  let is_update = std::env::var("UPDATE") == Ok("1".to_string());
+  for mut spec in specs {
+    eprintln!("Looking at {}...", spec.path.display());
+    let lockfile = Lockfile::with_lockfile_content(
+      spec.path.with_extension("lock"),
+      &spec.original_text.text,
+      false,
+    )
+    .unwrap();
+    for change_and_output in &mut spec.change_and_outputs {
+      let options = serde_json::from_str::<DotOptionsContent>(
+        &change_and_output.change.text,
+      )
+      .unwrap();
+      let actual_text = lockfile.to_dot(DotOptions {
+        kind_style: match options.kind_style.as_deref() {
+          Some("color") => DotKindStyle::Color,
+          _ => DotKindStyle::Shape,
+        },
+        root: options.root,
+        max_depth: options.max_depth,
+      });
+      if is_update {
+        change_and_output.output.text = actual_text;
+      } else {
+        assert_eq!(
+          actual_text.trim(),
+          change_and_output.output.text.trim(),
+          "Failed for: {} - {}",
+          spec.path.display(),
+          change_and_output.change.title,
+        );
+      }
+    }
+    if is_update {
+      std::fs::write(&spec.path, spec.emit()).unwrap();
+    }
+  }
+}
+
+#[test]
+fn mermaid_output() {
+  #[derive(Debug, Default, Clone, Deserialize)]
+  #[serde(rename_all = "camelCase")]
+  struct MermaidOptionsContent {
+    #[serde(default)]
+    workspace_member: Option<String>,
+  }
+
+  let specs =
+    ConfigChangeSpec::collect_in_dir(&PathBuf::from("./tests/specs/mermaid"));
+  let is_update = std::env::var("UPDATE") == Ok("1".to_string());
+  for mut spec in specs {
+    eprintln!("Looking at {}...", spec.path.display());
+    let lockfile = Lockfile::with_lockfile_content(
+      spec.path.with_extension("lock"),
+      &spec.original_text.text,
+      false,
+    )
+    .unwrap();
+    for change_and_output in &mut spec.change_and_outputs {
+      let options = serde_json::from_str::<MermaidOptionsContent>(
+        &change_and_output.change.text,
+      )
+      .unwrap();
+      let actual_text = lockfile.to_mermaid(MermaidOptions {
+        workspace_member: options.workspace_member,
+      });
+      if is_update {
+        change_and_output.output.text = actual_text;
+      } else {
+        assert_eq!(
+          actual_text.trim(),
+          change_and_output.output.text.trim(),
+          "Failed for: {} - {}",
+          spec.path.display(),
+          change_and_output.change.title,
+        );
+      }
+    }
+    if is_update {
+      std::fs::write(&spec.path, spec.emit()).unwrap();
+    }
+  }
+}
+
+#[test]
+fn graph_json_output() {
+  let specs = ConfigChangeSpec::collect_in_dir(&PathBuf::from(
+    "./tests/specs/graph_json",
+  ));
+  let is_update = std::env::var("UPDATE") == Ok("1".to_string());
+  for mut spec in specs {
+    eprintln!("Looking at {}...", spec.path.display());
+    let lockfile = Lockfile::with_lockfile_content(
+      spec.path.with_extension("lock"),
+      &spec.original_text.text,
+      false,
+    )
+    .unwrap();
+    for change_and_output in &mut spec.change_and_outputs {
+      let actual_text =
+        serde_json::to_string_pretty(&lockfile.graph_json()).unwrap();
+      if is_update {
+        change_and_output.output.text = actual_text;
+      } else {
+        assert_eq!(
+          actual_text.trim(),
+          change_and_output.output.text.trim(),
+          "Failed for: {} - {}",
+          spec.path.display(),
+          change_and_output.change.title,
+        );
+      }
+    }
+    if is_update {
+      std::fs::write(&spec.path, spec.emit()).unwrap();
+    }
+  }
+}
+
+fn verify_workspace_config_change(
+  change: &WorkspaceConfigChange,
+  old_packages: &PackagesContent,
+  packages: &PackagesContent,
+) {
+  fn removed_keys<T>(
+    old: &BTreeMap<String, T>,
+    new: &BTreeMap<String, T>,
+  ) -> BTreeSet<String> {
+    old
+      .keys()
+      .filter(|key| !new.contains_key(*key))
+      .cloned()
+      .collect()
+  }
+
+  assert_eq!(
+    change.npm_packages,
+    removed_keys(&old_packages.npm, &packages.npm)
+  );
+  assert_eq!(
+    change.jsr_packages,
+    removed_keys(&old_packages.jsr, &packages.jsr)
+  );
+  assert_eq!(
+    change.specifiers,
+    removed_keys(&old_packages.specifiers, &packages.specifiers)
+  );
+}
+
 fn verify_packages_content(packages: &PackagesContent) {
   // verify the specifiers
   for id in packages.specifiers.values() {
@@ -241,3 +385,235 @@ fn adding_workspace_does_not_cause_content_changes() {
     assert!(lockfile.has_content_changed); // should have changed since lockfile was not empty
   }
 }
+
+#[test]
+fn printed_lockfiles_round_trip() {
+  // loading and writing a lockfile without changes should be a no-op
+  let specs = ConfigChangeSpec::collect_in_dir(&PathBuf::from(
+    "./tests/specs/config_changes",
+  ));
+  for spec in specs {
+    for change_and_output in &spec.change_and_outputs {
+      let text = format!("{}\n", change_and_output.output.text.trim_end());
+      let lockfile = Lockfile::with_lockfile_content(
+        spec.path.with_extension("lock"),
+        &text,
+        false,
+      )
+      .unwrap();
+      assert_eq!(
+        lockfile.as_json_string(),
+        text,
+        "Failed for: {} - {}",
+        spec.path.display(),
+        change_and_output.output.title,
+      );
+    }
+  }
+}
+
+#[test]
+fn malformed_lockfiles() {
+  // version 4 lockfiles are only loaded after explicitly downgrading them
+  fn load(path: &Path, text: &str) -> Result<Lockfile, String> {
+    let filename = path.with_extension("lock");
+    let is_version_4 = path.file_stem().unwrap() == "V4";
+    let text = if is_version_4 {
+      let value = serde_json::from_str(text).map_err(|err| err.to_string())?;
+      deno_lockfile::transform4_to_3(value)
+        .map_err(|err| err.to_string())?
+        .to_string()
+    } else {
+      text.to_string()
+    };
+    Lockfile::with_lockfile_content(filename, &text, false)
+      .map_err(|err| err.to_string())
+  }
+
+  let specs =
+    ConfigChangeSpec::collect_in_dir(&PathBuf::from("./tests/specs/malformed"));
+  let is_update = std::env::var("UPDATE") == Ok("1".to_string());
+  for mut spec in specs {
+    eprintln!("Looking at {}...", spec.path.display());
+    load(&spec.path, &spec.original_text.text).unwrap();
+    for change_and_output in &mut spec.change_and_outputs {
+      let err = load(&spec.path, &change_and_output.change.text)
+        .err()
+        .unwrap_or_else(|| {
+          panic!(
+            "Expected an error for: {} - {}",
+            spec.path.display(),
+            change_and_output.change.title,
+          )
+        });
+      let actual_text = format!("{}\n", err);
+      if is_update {
+        change_and_output.output.text = actual_text;
+      } else {
+        assert_eq!(
+          actual_text.trim(),
+          change_and_output.output.text.trim(),
+          "Failed for: {} - {}",
+          spec.path.display(),
+          change_and_output.change.title,
+        );
+      }
+    }
+    if is_update {
+      std::fs::write(&spec.path, spec.emit()).unwrap();
+    }
+  }
+}
+
+#[test]
+fn arbitrary_json_does_not_panic() {
+  // small deterministic generator so failures are reproducible
+  struct Rng(u64);
+
+  impl Rng {
+    fn next(&mut self, max: usize) -> usize {
+      self.0 ^= self.0 << 13;
+      self.0 ^= self.0 >> 7;
+      self.0 ^= self.0 << 17;
+      (self.0 % max as u64) as usize
+    }
+  }
+
+  const KEYS: [&str; 16] = [
+    "version",
+    "packages",
+    "specifiers",
+    "npm",
+    "jsr",
+    "remote",
+    "redirects",
+    "workspace",
+    "members",
+    "packageJson",
+    "dependencies",
+    "optionalDependencies",
+    "integrity",
+    "$comments",
+    "npm:a@1",
+    "a@1.0.0",
+  ];
+  const STRINGS: [&str; 16] = [
+    "",
+    "1",
+    "2",
+    "3",
+    "4",
+    "@",
+    "npm:",
+    "jsr:",
+    "npm:@",
+    "npm:a@1.0.0",
+    "jsr:@scope/a@1.0.0",
+    "a@1.0.0",
+    "a@npm:b@1.0.0",
+    "é@1",
+    "npm:a@1.0.0_b@1.0.0",
+    "https://deno.land/x/a/",
+  ];
+
+  fn gen_value(rng: &mut Rng, depth: usize) -> serde_json::Value {
+    let kind = if depth > 4 { rng.next(4) } else { rng.next(7) };
+    match kind {
+      0 => serde_json::Value::Null,
+      1 => serde_json::Value::Bool(rng.next(2) == 0),
+      2 => serde_json::Value::from(rng.next(10)),
+      3 => serde_json::Value::from(STRINGS[rng.next(STRINGS.len())]),
+      4 => (0..rng.next(4))
+        .map(|_| gen_value(rng, depth + 1))
+        .collect(),
+      _ => (0..rng.next(5))
+        .map(|_| {
+          let key = if rng.next(4) == 0 {
+            STRINGS[rng.next(STRINGS.len())]
+          } else {
+            KEYS[rng.next(KEYS.len())]
+          };
+          (key.to_string(), gen_value(rng, depth + 1))
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into(),
+    }
+  }
+
+  // lockfile shaped values with arbitrary ids and requirements
+  fn gen_lockfile(rng: &mut Rng) -> serde_json::Value {
+    fn gen_str(rng: &mut Rng) -> String {
+      STRINGS[rng.next(STRINGS.len())].to_string()
+    }
+    fn gen_map(
+      rng: &mut Rng,
+      gen_entry: impl Fn(&mut Rng) -> serde_json::Value,
+    ) -> serde_json::Value {
+      (0..rng.next(6))
+        .map(|_| (gen_str(rng), gen_entry(rng)))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+    }
+    fn gen_list(rng: &mut Rng) -> serde_json::Value {
+      (0..rng.next(4)).map(|_| gen_str(rng)).collect()
+    }
+
+    serde_json::json!({
+      "version": "3",
+      "packages": {
+        "specifiers": gen_map(rng, |rng| gen_str(rng).into()),
+        "jsr": gen_map(rng, |rng| serde_json::json!({
+          "integrity": gen_str(rng),
+          "dependencies": gen_list(rng),
+        })),
+        "npm": gen_map(rng, |rng| serde_json::json!({
+          "integrity": gen_str(rng),
+          "dependencies": gen_map(rng, |rng| gen_str(rng).into()),
+        })),
+      },
+      "redirects": gen_map(rng, |rng| gen_str(rng).into()),
+      "remote": gen_map(rng, |rng| gen_str(rng).into()),
+      "workspace": {
+        "dependencies": gen_list(rng),
+        "members": gen_map(rng, |rng| serde_json::json!({
+          "dependencies": gen_list(rng),
+        })),
+      },
+    })
+  }
+
+  // kept small so the tests stay fast, see the fuzz directory for
+  // longer runs or set FUZZ_ITERATIONS
+  let iterations = std::env::var("FUZZ_ITERATIONS")
+    .ok()
+    .and_then(|value| value.parse().ok())
+    .unwrap_or(500);
+  let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+  for i in 0..iterations {
+    let mut value = if i % 2 == 0 {
+      gen_lockfile(&mut rng)
+    } else {
+      gen_value(&mut rng, 0)
+    };
+    if let Some(obj) = value.as_object_mut() {
+      if rng.next(2) == 0 {
+        obj.insert("version".to_string(), STRINGS[rng.next(5)].into());
+      }
+    }
+    let text = value.to_string();
+    let Ok(mut lockfile) =
+      Lockfile::with_lockfile_content(PathBuf::from("deno.lock"), &text, false)
+    else {
+      continue;
+    };
+    lockfile.as_json_string();
+    lockfile.graph_json();
+    lockfile.to_dot(Default::default());
+    lockfile.to_mermaid(Default::default());
+    let _ = lockfile.topological_order();
+    lockfile.content.unreachable_packages();
+    lockfile.canonicalize();
+    lockfile.prune(Default::default());
+    let _ = lockfile.normalize_specifiers();
+  }
+}